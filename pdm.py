@@ -1,14 +1,51 @@
 import git
 import re
 import argparse
+import codecs
+import configparser
+import contextlib
+import csv
+import io
+import fnmatch
+import glob
+import html
+import functools
 import signal
-import threading
 import os
+import queue
+import threading
 import time
 import platform
+import stat
+try:
+    import fcntl
+except ImportError:
+    fcntl = None
+try:
+    import curses
+except ImportError:
+    # Windows Python ships without curses, so no --tui there
+    curses = None
+import collections
+import ctypes
 import json
+import logging
+import logging.handlers
+import math
+import mmap
+import random
+import shlex
+import shutil
+import statistics
+import string
 import subprocess
 import sys
+import tempfile
+import tomllib
+import urllib.error
+import urllib.parse
+import urllib.request
+from dataclasses import dataclass, field
 from pprint import pprint
 
 # For disk detection
@@ -39,7 +76,587 @@ else:
             return "Unknown"
 
 
-FIO_CONFIG = 'config/cdm8.fio'
+log = logging.getLogger('pdm')
+
+CONFIG_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), 'config')
+FIO_CONFIG = os.path.join(CONFIG_DIR, 'cdm8.fio')
+PRESETS = {
+    'default': (FIO_CONFIG, 'CrystalDiskMark 8 default: SEQ1M Q8T1/Q1T1, RND4K Q32T1/Q1T1'),
+    'quick': (os.path.join(CONFIG_DIR, 'quick.fio'), '128 MiB file, 1 loop, 2 s runtime: SEQ1M Q8T1, RND4K Q32T1'),
+    'nvme': (os.path.join(CONFIG_DIR, 'nvme.fio'), 'High queue depths and threads: SEQ1M Q8T1, SEQ128K Q32T1, RND4K Q32T16/Q1T1'),
+    'hdd': (os.path.join(CONFIG_DIR, 'hdd.fio'), 'Sequential-heavy, low queue depth: SEQ1M Q1T1, SEQ128K Q2T1, RND4K Q1T1'),
+    'peak': (os.path.join(CONFIG_DIR, 'peak.fio'), 'CrystalDiskMark peak performance: SEQ1M Q8T1, RND4K Q32T16'),
+}
+MIN_TEST_SIZE = 16 * 1024**2
+# Lines of fio stderr quoted in error messages
+STDERR_TAIL_LINES = 20
+# Rotated log files kept with --log-max-size unless --log-keep says otherwise
+LOG_KEEP = 5
+# KiB of log lines the JSON --output carries by default
+LOG_BUFFER_KIB = 512
+# Levels of --log-level and --log-filter, most verbose first
+LOG_LEVELS = ['debug', 'info', 'warning', 'error']
+# Bytes of stdout and of stderr run_command() keeps, a runaway child can't
+# make us hold more than that
+COMMAND_OUTPUT_LIMIT = 16 * 1024**2
+# Share of the required space that has to be free on top of it
+FREE_SPACE_MARGIN = 0.05
+# Seconds between temperature readings and the longest --thermal-limit wait
+TEMPERATURE_INTERVAL = 2
+THERMAL_WAIT_MAX = 600
+# Other I/O below this rate counts as an idle disk, and the longest --wait-idle wait
+IDLE_BYTES_PER_SECOND = 1024**2
+IDLE_WAIT_MAX = 300
+# CPU utilization that makes small block results suspect, and what counts as small
+CPU_BOUND_PERCENT = 90
+SMALL_BLOCK_SIZE = 64 * 1024
+# Filesystems whose speed is mostly that of the network
+NETWORK_FILESYSTEMS = ('nfs', 'nfs4', 'cifs', 'smbfs', 'smb3', 'fuse.sshfs', 'sshfs', '9p')
+# Filesystems that serve O_DIRECT from the page cache or don't support it
+BUFFERED_FILESYSTEMS = ('tmpfs', 'ramfs', 'zfs', 'overlay', 'fuse.sshfs')
+NATIVE_TEST_SIZE = 1024**3
+# Test file the native backend keeps in a target directory with --keep-data
+NATIVE_FILE = '.pdm-native'
+# Test file the native backend creates in a target directory and deletes after the run
+NATIVE_TEMP_FILE = 'pydiskmark-test-{pid}.bin'
+# Marker next to a --prepare-file data file recording what it was prepared for
+PREPARED_SUFFIX = '.pdm-prepared'
+# Bytes written at a time when filling a prepared data file
+PREPARE_CHUNK = 4 * 1024**2
+# Bytes of zeros written at a time when --shred overwrites a test file
+SHRED_CHUNK = 16 * 1024**2
+# fallocate() mode that deallocates a range of a file, from linux/falloc.h
+FALLOC_FL_KEEP_SIZE = 0x01
+FALLOC_FL_PUNCH_HOLE = 0x02
+# Random tests cover at most this much data per pass to keep them short
+NATIVE_RANDOM_BYTES = 256 * 1024**2
+NATIVE_RANDOM_BLOCK = 4 * 1024
+# How often a native pass updates the progress bar while it runs
+NATIVE_PROGRESS_SECONDS = 0.25
+# Slow disk speeds in bytes/s, used to estimate how long size based tests take
+ESTIMATE_SEQ_SPEED = 100 * 1024**2
+ESTIMATE_RND_SPEED = 10 * 1024**2
+
+# Exit codes, 2 matches what argparse uses for bad arguments
+EXIT_BAD_ARGS = 2
+EXIT_MISSING_BACKEND = 3
+EXIT_IO_ERROR = 4
+EXIT_PARSE_ERROR = 5
+EXIT_REGRESSION = 6
+EXIT_BASELINE_FAILED = 7
+EXIT_HOOK_FAILED = 8
+# What shells report for a process stopped by SIGINT
+EXIT_INTERRUPTED = 130
+
+# What --sweep can vary, mapped to the fio option it sets
+SWEEP_PARAMETERS = {'bs': 'bs', 'qd': 'iodepth'}
+# Block size and queue depth a sweep keeps fixed, as in cdm8
+SWEEP_BASE = {'seq': {'bs': 1024**2, 'qd': 8}, 'rnd': {'bs': 4 * 1024, 'qd': 32}}
+# --regions offsets are rounded down to this so they stay aligned for direct I/O
+REGION_ALIGNMENT = 1024**2
+# Width of the bars of the --regions table at the fastest region
+REGION_BAR_WIDTH = 30
+
+# Engines --ioengine auto picks from, best first
+IOENGINE_PREFERENCE = {
+    'Windows': ['windowsaio', 'psync'],
+    'default': ['io_uring', 'libaio', 'posixaio', 'psync'],
+}
+
+# Minimums a --baseline file can set per test, mapped to (direction, result key, unit)
+BASELINE_METRICS = {
+    'read_mbs': ('read', 'speed_mbs', 'MB/s'),
+    'write_mbs': ('write', 'speed_mbs', 'MB/s'),
+    'read_iops': ('read', 'iops', 'IOPS'),
+    'write_iops': ('write', 'iops', 'IOPS'),
+}
+
+# Characters of the bandwidth sparklines, lowest to highest, and their widest width
+SPARKLINE_CHARS = '▁▂▃▄▅▆▇█'
+SPARKLINE_WIDTH = 60
+# Colors of the --chart bars per direction and of its bandwidth lines
+CHART_COLORS = {'read': '#4e79a7', 'write': '#f28e2b', 'trim': '#59a14f'}
+CHART_LINE_COLORS = ['#4e79a7', '#f28e2b', '#e15759', '#76b7b2', '#59a14f', '#edc948']
+CHART_WIDTH = 800
+# fio's direction numbers in its log files
+FIO_LOG_DIRECTIONS = {0: 'read', 1: 'write', 2: 'trim'}
+
+# Latency percentiles reported per test
+LATENCY_PERCENTILES = [50, 95, 99, 99.9]
+# The only job of --mode latency, 4K random reads at QD1 for a fixed time
+LATENCY_JOB = 'RND-R-4K-Q1-T1'
+LATENCY_SECONDS = 20
+# Highest p99 read latency in us of each class of drive, fastest first
+LATENCY_CLASSES = [(250, 'NVMe-class'), (2000, 'SATA SSD-class'), (50000, 'HDD-class')]
+# The --fsync-test job, 4K writes each followed by an fsync
+FSYNC_JOB = 'SYNC-W-4K-Q1-T1'
+# Seconds the native fsync test runs without --duration
+FSYNC_SECONDS = 10
+# Mean fsync latency in us below which the data likely only reached a volatile cache
+FSYNC_VOLATILE_US = 50
+# The --trim-test job, discarding the test range in 1M pieces
+TRIM_JOB = 'TRIM-T-1M-Q1-T1'
+# fio options --steady-state sets on the sequential write job, it stops once the
+# bandwidth stays within 10% for a minute or after half an hour
+STEADY_STATE_OPTIONS = {'steadystate': 'bw:10%', 'steadystate_duration': '60',
+                        'runtime': '1800', 'time_based': None, 'loops': '1'}
+# Oldest fio whose JSON output parse_fio_results() understands
+FIO_MIN_VERSION = (3, 0, 0)
+# fio arguments --fio-arg can't pass, since the output has to stay what we read
+FIO_RESERVED_ARGS = {
+    '--output-format': 'the results are read from fio\'s JSON output',
+    '--output': 'the results are read from fio\'s stdout',
+    '--minimal': 'the results are read from fio\'s JSON output',
+    '--append-terse': 'the results are read from fio\'s JSON output',
+    '--status-interval': 'the progress is read from the status reports it sets',
+    '--client': 'use --remote to run the jobs on a fio server',
+    '--server': 'use --remote to run the jobs on a fio server',
+}
+# How fio built without JSON output rejects --output-format=json
+FIO_NO_JSON = re.compile(r"unrecognized option '?--output-format|invalid output format", re.I)
+# The fields of a line of fio's terse output, version 3, in order. Latencies are
+# in us, sizes and bandwidth in KiB. Disk utilization and error details follow.
+FIO_TERSE_DIRECTION_FIELDS = (
+    ['io_kib', 'bw_kib', 'iops', 'runtime_ms']
+    + [f"{kind}_{stat}" for kind in ('slat', 'clat') for stat in ('min', 'max', 'mean', 'stdev')]
+    + [f"clat_percentile_{n}" for n in range(1, 21)]
+    + [f"lat_{stat}" for stat in ('min', 'max', 'mean', 'stdev')]
+    + ['bw_min', 'bw_max', 'bw_agg_pct', 'bw_mean', 'bw_stdev'])
+FIO_TERSE_FIELDS = (
+    ['terse_version', 'fio_version', 'jobname', 'groupid', 'error']
+    + [f"{direction}_{name}" for direction in ('read', 'write', 'trim')
+       for name in FIO_TERSE_DIRECTION_FIELDS]
+    + ['cpu_user', 'cpu_sys', 'ctx', 'majf', 'minf']
+    + [f"iodepth_{depth}" for depth in ('1', '2', '4', '8', '16', '32', '64')]
+    + [f"lat_us_{limit}" for limit in (2, 4, 10, 20, 50, 100, 250, 500, 750, 1000)]
+    + [f"lat_ms_{limit}" for limit in (2, 4, 10, 20, 50, 100, 250, 500, 750, 1000, 2000, 'over')])
+# First fio version reporting the steadystate block in its JSON output
+FIO_STEADY_STATE_VERSION = (3, 0, 0)
+
+# Weight of each test and direction in the score, RND4K Q1T1 counts double
+# since it dominates how responsive a drive feels
+SCORE_WEIGHTS = {
+    ('SEQ1M Q8T1', 'read'): 1.0,
+    ('SEQ1M Q8T1', 'write'): 1.0,
+    ('SEQ1M Q1T1', 'read'): 1.0,
+    ('SEQ1M Q1T1', 'write'): 1.0,
+    ('RND4K Q32T1', 'read'): 1.0,
+    ('RND4K Q32T1', 'write'): 1.0,
+    ('RND4K Q1T1', 'read'): 2.0,
+    ('RND4K Q1T1', 'write'): 2.0,
+}
+
+SIZE_UNITS = {'': 1, 'k': 1024, 'm': 1024**2, 'g': 1024**3, 't': 1024**4}
+
+
+class ProgressLogHandler(logging.StreamHandler):
+    """Print log records on their own line above a running progress bar."""
+
+    def emit(self, record):
+        line = progress_state['line']
+        if line is None:
+            return super().emit(record)
+        # Clear the bar so the record doesn't end up inside it, then redraw it
+        sys.stdout.write('\r\033[K' if sys.stdout.isatty() else '\n')
+        sys.stdout.flush()
+        super().emit(record)
+        sys.stdout.write(line)
+        sys.stdout.flush()
+
+
+def log_timestamp(record, timestamps):
+    """Format when a log record was made, in the --log-timestamps style.
+
+    utc and local give the wall clock time, elapsed the seconds since the
+    start of the program and none gives None.
+    """
+    if timestamps == 'utc':
+        return time.strftime('%Y-%m-%dT%H:%M:%S', time.gmtime(record.created)) + \
+            f".{int(record.msecs):03d}Z"
+    if timestamps == 'local':
+        local = time.localtime(record.created)
+        offset = time.strftime('%z', local)
+        return time.strftime('%Y-%m-%dT%H:%M:%S', local) + \
+            f".{int(record.msecs):03d}{offset[:3]}:{offset[3:]}"
+    if timestamps == 'elapsed':
+        # logging counts from when it was imported, right as the program starts
+        return f"{record.relativeCreated / 1000:.3f}"
+    return None
+
+
+class LogRingBuffer(logging.Handler):
+    """Keep the newest log lines in memory, up to limit bytes of them.
+
+    Records may come from any thread, lines() can be read while they do.
+    """
+
+    def __init__(self, limit):
+        super().__init__(logging.DEBUG)
+        self.limit = limit
+        self.records = collections.deque()
+        self.size = 0
+        self.dropped = 0
+
+    def emit(self, record):
+        # handle() holds self.lock while emitting
+        line = self.format(record)
+        self.records.append(line)
+        self.size += len(line) + 1
+        while self.size > self.limit and len(self.records) > 1:
+            self.size -= len(self.records.popleft()) + 1
+            self.dropped += 1
+
+    def lines(self):
+        """Return the kept lines, led by a note when older ones were dropped."""
+        with self.lock:
+            lines, dropped = list(self.records), self.dropped
+        if dropped:
+            lines.insert(0, f"[{dropped} earlier log lines dropped, only the last "
+                         f"{format_size(self.limit)} are kept]")
+        return lines
+
+
+class JsonLogFormatter(logging.Formatter):
+    """Format log records as one JSON object per line."""
+
+    def __init__(self, timestamps='utc'):
+        super().__init__()
+        self.timestamps = timestamps
+
+    def format(self, record):
+        stamp = log_timestamp(record, self.timestamps)
+        entry = {
+            **({'ts': stamp} if stamp else {}),
+            'level': record.levelname.lower(),
+            'target': record.name,
+            'file': record.filename,
+            'line': record.lineno,
+            'msg': record.getMessage(),
+        }
+        if record.exc_info:
+            entry['exc'] = self.formatException(record.exc_info)
+        return json.dumps(entry)
+
+
+class ColorLogFormatter(logging.Formatter):
+    """Format log records as text, coloring the level name."""
+    colors = {'DEBUG': '2', 'INFO': '36', 'WARNING': '33', 'ERROR': '31', 'CRITICAL': '1;31'}
+
+    def __init__(self, color, timestamps='none'):
+        super().__init__()
+        self.color = color
+        self.timestamps = timestamps
+
+    def formatMessage(self, record):
+        level = record.levelname
+        if self.color:
+            level = f"\033[{self.colors.get(level, '0')}m{level}\033[0m"
+        stamp = log_timestamp(record, self.timestamps)
+        return f"{f'{stamp} ' if stamp else ''}{level}: {record.message}"
+
+
+def setup_logging(level, log_format='text', log_file=None, log_target=None,
+                  max_size=None, keep=LOG_KEEP, log_filter=(), timestamps=None):
+    """Send log records to stderr and/or a file.
+
+    log_target is 'console', 'file' or 'both', by default the file is
+    written in addition to the console when one is given. With max_size the
+    file is renamed to .1 once it grows past that many bytes, and the keep
+    newest of the renamed files are kept as .1 to .<keep>.
+
+    log_filter holds the (logger, level) rules of --log-filter, a rule
+    without a logger replaces level. Loggers without a rule of their own go
+    by their closest parent that has one, so the most specific rule wins.
+
+    timestamps is the --log-timestamps style, see log_timestamp(). By default
+    JSON lines get UTC times and text lines none.
+    """
+    log_target = log_target or ('both' if log_file else 'console')
+    handlers = []
+    if log_target in ('console', 'both'):
+        handlers.append(ProgressLogHandler(sys.stderr))
+        # Only a terminal gets colors
+        handlers[-1].setFormatter(ColorLogFormatter(color_enabled(sys.stderr),
+                                                    timestamps or 'none'))
+    if log_target in ('file', 'both'):
+        if not log_file:
+            raise PdmError("--log-target file needs --log-file", EXIT_BAD_ARGS)
+        try:
+            # Handlers write and flush every record under their own lock, so
+            # records logged from several threads never mix
+            if max_size:
+                handlers.append(logging.handlers.RotatingFileHandler(
+                    log_file, mode='a', maxBytes=max_size, backupCount=keep, encoding='utf-8'))
+            else:
+                handlers.append(logging.FileHandler(log_file, mode='a', encoding='utf-8'))
+        except OSError as e:
+            raise PdmError(f"Could not open log file '{log_file}': {e.strerror}", EXIT_IO_ERROR)
+        # Color codes would only clutter the file
+        handlers[-1].setFormatter(ColorLogFormatter(False, timestamps or 'none'))
+    if log_format == 'json':
+        for handler in handlers:
+            handler.setFormatter(JsonLogFormatter(timestamps or 'utc'))
+    for name, rule_level in log_filter:
+        if name is None:
+            level = rule_level
+        else:
+            logging.getLogger(name).setLevel(rule_level.upper())
+    logging.basicConfig(level=level.upper(), handlers=handlers)
+
+
+def tee_log_file(path, level, log_format='text', timestamps=None):
+    """Also write the log messages to a file, next to where they already go.
+
+    The file gets everything down to level even where the console shows
+    less, as with --quiet.
+    """
+    try:
+        handler = logging.FileHandler(path, mode='a', encoding='utf-8')
+    except OSError as e:
+        raise PdmError(f"Could not open log file '{path}': {e.strerror}", EXIT_IO_ERROR)
+    handler.setFormatter(JsonLogFormatter(timestamps or 'utc') if log_format == 'json'
+                         else ColorLogFormatter(False, timestamps or 'none'))
+    handler.setLevel(level.upper())
+    attach_log_handler(handler)
+
+
+def attach_log_handler(handler):
+    """Add a log handler that may want more than is shown so far.
+
+    The level of the root logger is lowered to that of handler, while the
+    handlers that were there keep showing what they did.
+    """
+    root = logging.getLogger()
+    shown_level = root.level
+
+    def shown_before(record):
+        # The closest logger with a --log-filter level decides, else the root did
+        logger = logging.getLogger(record.name)
+        while logger.level == logging.NOTSET and logger.parent is not None:
+            logger = logger.parent
+        return record.levelno >= (shown_level if logger is root else logger.level)
+
+    # Keep the other handlers at what they showed so far
+    for existing in root.handlers:
+        if existing.level == logging.NOTSET:
+            existing.addFilter(shown_before)
+    root.setLevel(min(root.level, handler.level))
+    root.addHandler(handler)
+
+
+class PdmError(Exception):
+    """An error that aborts the run with a specific exit code."""
+
+    def __init__(self, message, exit_code):
+        super().__init__(message)
+        self.exit_code = exit_code
+
+
+class JobTimeoutError(PdmError):
+    """A single job ran past its time limit and was killed."""
+
+    def __init__(self, message):
+        super().__init__(message, EXIT_IO_ERROR)
+
+
+@dataclass
+class BenchmarkReport:
+    """Results of one benchmark run, independent of the backend."""
+    backend: str
+    version: str
+    target: str
+    # One entry per test and direction, see parse_fio_results()
+    tests: list = field(default_factory=list)
+    # test_size, loops, runtime and engine as far as the backend knows them
+    settings: dict = field(default_factory=dict)
+    # Backend specific output, saved as-is next to the report
+    raw: dict = None
+    # Machine and device the run happened on, see collect_system_metadata()
+    system: dict = field(default_factory=dict)
+    # Set when Ctrl+C stopped the run, tests then holds what finished before
+    partial: bool = False
+    # Results by swept parameter for --sweep runs, see build_sweep_series()
+    sweep: dict = None
+    # Results by device offset for --regions runs, see build_region_series()
+    regions: list = None
+    # Estimated and actual run time in seconds, filled in by main()
+    timing: dict = None
+    # SMART values before and after the run, see smart_snapshot()
+    smart: dict = None
+
+
+class Backend:
+    """Base class for the tools that can run a benchmark."""
+    name = None
+    # host[:port] of the machine the benchmark runs on, None for this one
+    remote = None
+    # Whether a target that doesn't exist yet is a file for run() to create
+    creates_target = False
+
+    def is_available(self):
+        """Return None if the backend can run, otherwise the reason it can't."""
+        raise NotImplementedError
+
+    def install_hint(self):
+        """Tell the user how to make the backend available."""
+        return f"Please install {self.name} before using this tool."
+
+    def run(self, test_path, args, artifacts=None):
+        """Benchmark the test path and return a BenchmarkReport.
+
+        artifacts is a directory to keep the raw files of the run in, if any.
+        """
+        raise NotImplementedError
+
+    def dry_run(self, test_path, args):
+        """Describe what run() would do without touching the target."""
+        raise NotImplementedError
+
+    def writes(self, test_path, args):
+        """Tell whether run() writes to the target."""
+        return True
+
+    def estimate_runtime(self, test_path, args):
+        """Estimate how long run() takes, as (seconds, number of tests of unknown length)."""
+        return 0, 1
+
+
+@dataclass
+class CommandSpec:
+    """An external command for run_command() to run."""
+    # The program and its arguments, or a shell command line with shell
+    args: list
+    shell: bool = False
+    # Text written to the stdin of the command, None leaves ours to it
+    stdin: str = None
+    cwd: str = None
+    # Variables set on top of our own environment
+    env: dict = None
+    # Seconds until the command is killed, None waits for it however long it takes
+    timeout: float = None
+    # Capture stderr as part of stdout instead of on its own
+    merge_stderr: bool = False
+    # Bytes kept of stdout and of stderr, the rest is read and dropped
+    output_limit: int = COMMAND_OUTPUT_LIMIT
+
+
+@dataclass
+class CommandResult:
+    """What a command run by run_command() printed and how it ended."""
+    returncode: int
+    stdout: str
+    stderr: str
+    # Killed after CommandSpec.timeout, returncode is then that of the kill
+    timed_out: bool = False
+    # Printed more than CommandSpec.output_limit
+    truncated: bool = False
+
+
+def run_command(spec):
+    """Run an external command to the end and return its CommandResult.
+
+    The output is read while the command runs, so it can't block on a full
+    pipe, and stdin is written the same way. OSError, like
+    FileNotFoundError for a missing program, is raised if it can't start.
+    """
+    process = subprocess.Popen(spec.args, shell=spec.shell, cwd=spec.cwd,
+                               env={**os.environ, **spec.env} if spec.env else None,
+                               stdin=None if spec.stdin is None else subprocess.PIPE,
+                               stdout=subprocess.PIPE,
+                               stderr=subprocess.STDOUT if spec.merge_stderr
+                               else subprocess.PIPE)
+    captured = {}
+
+    def capture(name, pipe):
+        kept, dropped = bytearray(), 0
+        with pipe:
+            for chunk in iter(lambda: pipe.read1(65536), b''):
+                room = spec.output_limit - len(kept)
+                kept += chunk[:room]
+                dropped += max(len(chunk) - room, 0)
+        captured[name] = (bytes(kept), dropped)
+
+    def feed(pipe):
+        try:
+            pipe.write(spec.stdin.encode())
+            pipe.close()
+        except BrokenPipeError:
+            # The command quit without reading everything, its exit code says why
+            pass
+
+    threads = [threading.Thread(target=capture, args=(name, pipe), daemon=True)
+               for name, pipe in (('stdout', process.stdout), ('stderr', process.stderr))
+               if pipe]
+    if spec.stdin is not None:
+        threads.append(threading.Thread(target=feed, args=(process.stdin,), daemon=True))
+    for thread in threads:
+        thread.start()
+    timed_out = False
+    try:
+        process.wait(timeout=spec.timeout)
+    except subprocess.TimeoutExpired:
+        process.kill()
+        process.wait()
+        timed_out = True
+    for thread in threads:
+        # Children of a killed shell may still hold the pipes open
+        thread.join(timeout=5 if timed_out else None)
+
+    def text(name):
+        data, _ = captured.get(name, (b'', 0))
+        return data.decode(errors='replace').replace('\r\n', '\n')
+
+    truncated = any(dropped for _, dropped in captured.values())
+    if truncated:
+        log.warning(f"{spec.args if spec.shell else spec.args[0]} printed more than "
+                    f"{format_size(spec.output_limit)}, the rest was dropped")
+    return CommandResult(process.returncode, text('stdout'), text('stderr'),
+                         timed_out, truncated)
+
+
+class CommandRunner:
+    """Starts fio and reads what it prints.
+
+    Everything that runs fio goes through command_runner, so a subclass put
+    there can answer with canned fio output when fio isn't installed.
+    """
+
+    def run(self, args, stdin=None, merge_stderr=False):
+        """Run a program to the end and return its CommandResult.
+
+        The output is text, with merge_stderr stderr is part of stdout.
+        FileNotFoundError is raised if the program doesn't exist.
+        """
+        return run_command(CommandSpec(args, stdin=stdin, merge_stderr=merge_stderr))
+
+    def start(self, args, stdin=None, env=None):
+        """Start a program whose output is read while it runs.
+
+        This is for the fio jobs, whose progress is shown as it comes in and
+        which Ctrl+C has to be able to stop, anything else uses run().
+
+        stdin, if given, is text written to the program's stdin, which is then
+        closed. A program that exits before reading all of it is left for the
+        caller to notice by its exit code and stderr. env holds variables set
+        on top of our own environment.
+
+        Returns a subprocess.Popen, or anything with the same byte stdout and
+        stderr pipes, wait(), kill(), terminate() and returncode.
+        """
+        process = subprocess.Popen(args, stdin=None if stdin is None else subprocess.PIPE,
+                                   stdout=subprocess.PIPE, stderr=subprocess.PIPE,
+                                   env={**os.environ, **env} if env else None)
+        if stdin is not None:
+            try:
+                process.stdin.write(stdin.encode())
+                process.stdin.close()
+            except BrokenPipeError:
+                # fio quit early, e.g. on a bad option, and says why on stderr
+                pass
+        return process
+
+
+command_runner = CommandRunner()
 
 
 def hash_data(data) -> str:
@@ -52,266 +669,5267 @@ def hash_data(data) -> str:
     return sha256.hexdigest()[:8]  # Return first 8 characters for brevity
 
 
-def progress_bar(iteration, total, prefix='', length=40, fill='█', print_end="\r"):
+# The progress bar line currently on the console, redrawn below log messages
+progress_state = {'line': None}
+# --color and --quiet settings, see color_enabled() and plain_progress(),
+# and the open --tui screen if any
+console = {'color': 'auto', 'quiet': False, 'tui': None}
+# Whether dropping the page cache failed already, see drop_page_caches()
+page_cache_state = {'warned': False}
+# The fio every fio command runs, see select_fio_binary(), the --fio-arg
+# arguments and --fio-env variables the jobs get on top of our own, and the
+# output format, terse once fio turned out to have no JSON output
+fio_command = {'path': 'fio', 'args': [], 'env': {}, 'format': 'json'}
+
+
+def color_enabled(stream):
+    """Check whether ANSI colors should be written to a stream."""
+    if console['color'] == 'always':
+        return True
+    if console['color'] == 'never' or os.environ.get('NO_COLOR'):
+        return False
+    return stream.isatty()
+
+
+def plain_progress():
+    """Check whether progress is shown as plain lines instead of bars."""
+    # Bars redrawn with \r turn into garbage in captured CI logs
+    return console['color'] == 'never' or not sys.stdout.isatty()
+
+
+def progress_step(index, total, name):
+    """Announce a step of the run when progress bars are not drawn."""
+    if plain_progress() and not (console['quiet'] or console['tui']):
+        print(f"job {index} of {total}: {name}", flush=True)
+
+
+def progress_bar(iteration, total, prefix='', length=40, fill='█', print_end="\r", suffix=''):
     """Display a progress bar in the console."""
+    if console['tui']:
+        console['tui'].progress(iteration / total, prefix.strip(), suffix.strip())
+        return
+    if plain_progress() or console['quiet']:
+        return
     percent = (iteration / total)
     filled_length = int(length * percent)
     bar = fill * filled_length + '-' * (length - filled_length)
-    print(f'\r{prefix} |{bar}| {percent:.1%}', end=print_end)
+    line = f'\r{prefix} |{bar}| {percent:.1%}{suffix}'
+    print(line, end=print_end)
+    progress_state['line'] = line if print_end == "\r" and iteration != total else None
 
     # Print new line on completion
     if iteration == total:
         print()
 
 
-def check_fio_available():
-    """Check if fio is available in the system."""
-    try:
-        subprocess.run(['fio', '--version'],
-                       stdout=subprocess.PIPE, stderr=subprocess.PIPE)
+def announce_results(results):
+    """Show parsed results of a test that just finished where the run is watched."""
+    if console['tui']:
+        console['tui'].add_results(results)
+
+
+class TuiWriter(io.TextIOBase):
+    """Stream that sends whatever the run prints to the log pane of the TUI."""
+
+    def __init__(self, tui):
+        self.tui = tui
+        self.pending = ''
+
+    def writable(self):
         return True
-    except FileNotFoundError:
+
+    def write(self, text):
+        # Progress output rewrites its line with \r, only the last version counts
+        lines = (self.pending + text).split('\n')
+        self.pending = lines.pop()
+        for line in lines:
+            self.tui.add_log(line.rsplit('\r', 1)[-1])
+        return len(text)
+
+
+class Tui:
+    """Full screen frontend drawing a CrystalDiskMark-like grid during the run.
+
+    The backends run as usual, progress_bar() and announce_results() feed
+    the screen, and everything printed or logged ends up in the log pane.
+    A thread owns the screen, redrawing it and reading the keys: q stops
+    the run like Ctrl+C does, s saves the results of the finished targets.
+    """
+    REDRAW_INTERVAL_MS = 200
+    LOG_LINES = 500
+
+    def __init__(self):
+        self.lock = threading.Lock()
+        self.target = ''
+        self.rows = {}
+        self.job = ''
+        self.fraction = 0.0
+        self.speed = ''
+        self.log = collections.deque(maxlen=self.LOG_LINES)
+        self.documents = []
+        self.status = 'q: stop  s: save results'
+        self.finished = False
+        self.closing = threading.Event()
+        self.screen = None
+        self.thread = None
+        self.saved = {}
+
+    def __enter__(self):
+        self.screen = curses.initscr()
+        curses.noecho()
+        curses.cbreak()
+        self.screen.keypad(True)
+        self.screen.timeout(self.REDRAW_INTERVAL_MS)
+        with contextlib.suppress(curses.error):
+            curses.curs_set(0)
+        # A crash anywhere must not leave the terminal in curses mode
+        self.saved = {'excepthook': sys.excepthook, 'thread_excepthook': threading.excepthook,
+                      'stdout': sys.stdout, 'stderr': sys.stderr, 'streams': {}}
+        sys.excepthook = self.crash_hook(sys.excepthook)
+        threading.excepthook = self.crash_hook(threading.excepthook)
+        writer = TuiWriter(self)
+        for handler in logging.getLogger().handlers:
+            if isinstance(handler, logging.StreamHandler) and \
+                    handler.stream in (sys.stdout, sys.stderr):
+                self.saved['streams'][handler] = handler.setStream(writer)
+        sys.stdout = sys.stderr = writer
+        console['tui'] = self
+        self.thread = threading.Thread(target=self.loop, daemon=True)
+        self.thread.start()
+        return self
+
+    def __exit__(self, *exc):
+        self.closing.set()
+        self.thread.join()
+        self.restore_terminal()
+        # Leave what a run without --tui would have shown on the terminal
+        for line in self.log:
+            print(line)
         return False
 
+    def crash_hook(self, hook):
+        def restore_and_report(*args):
+            self.restore_terminal()
+            hook(*args)
+        return restore_and_report
 
-def get_available_disks():
-    """Detect all available disks in the system."""
-    disks = []
+    def restore_terminal(self):
+        """Leave curses mode and put the console back, safe to call twice."""
+        if self.screen is None:
+            return
+        self.screen = None
+        with contextlib.suppress(curses.error):
+            curses.nocbreak()
+            curses.echo()
+            curses.endwin()
+        console['tui'] = None
+        sys.stdout = self.saved['stdout']
+        sys.stderr = self.saved['stderr']
+        sys.excepthook = self.saved['excepthook']
+        threading.excepthook = self.saved['thread_excepthook']
+        for handler, stream in self.saved['streams'].items():
+            handler.setStream(stream)
 
-    if platform.system() == 'Windows':
-        drives = win32api.GetLogicalDriveStrings().split('\000')[:-1]
-        for drive in drives:
-            try:
-                drive_type = win32file.GetDriveType(drive)
-                # Only include fixed drives (3) and removable drives (2)
-                if drive_type in (2, 3):
-                    drive_info = {
-                        'path': drive,
-                        'type': 'Fixed' if drive_type == 3 else 'Removable',
-                        'name': f"Drive {drive}",
-                        'size': get_disk_size(drive)
-                    }
-                    disks.append(drive_info)
-            except:
-                pass
+    def set_target(self, name):
+        with self.lock:
+            self.target = name
+            self.rows = {}
+            self.job, self.fraction, self.speed = '', 0.0, ''
+
+    def add_results(self, results):
+        with self.lock:
+            for test in results:
+                row = self.rows.setdefault(job_label(test['name']), {})
+                row[test['direction']] = test
+
+    def add_document(self, document):
+        with self.lock:
+            self.documents.append(document)
+
+    def add_log(self, line):
+        with self.lock:
+            self.log.append(line)
+
+    def progress(self, fraction, job, speed):
+        with self.lock:
+            self.fraction, self.job, self.speed = fraction, job, speed
+
+    def wait_until_closed(self):
+        """Keep the finished results on screen until the user quits."""
+        with self.lock:
+            self.finished = True
+            self.status = 'Finished. q: quit  s: save results'
+        self.closing.wait()
+
+    def save(self):
+        with self.lock:
+            documents = list(self.documents)
+        if not documents:
+            self.status = 'Nothing to save until a target has finished'
+            return
+        path = f"out/PDM_{time.strftime('%Y%m%d_%H%M%S')}.json"
+        document = build_multi_target_document(documents) if len(documents) > 1 \
+            else documents[0]
+        try:
+            os.makedirs('out', exist_ok=True)
+            write_file_atomic(path, json.dumps(document, indent=4) + "\n")
+            self.status = f"Results saved to {path}"
+        except OSError as e:
+            self.status = f"Could not save the results: {e}"
+
+    def loop(self):
+        while not self.closing.is_set():
+            with self.lock:
+                self.draw()
+            key = self.screen.getch()
+            if key in (ord('q'), ord('Q')):
+                if self.finished:
+                    self.closing.set()
+                else:
+                    # Stop like Ctrl+C, so the backend cleans up the same way
+                    self.status = 'Stopping after the current job...'
+                    os.kill(os.getpid(), signal.SIGINT)
+            elif key in (ord('s'), ord('S')):
+                self.save()
+
+    def draw(self):
+        screen = self.screen
+        screen.erase()
+        height, width = screen.getmaxyx()
+
+        def put(y, text, attr=0):
+            if 0 <= y < height:
+                with contextlib.suppress(curses.error):
+                    screen.addnstr(y, 0, text, width - 1, attr)
+
+        put(0, f" PyDiskMark  {self.target}", curses.A_REVERSE)
+        put(2, f" {'Test':<16} {'Read MB/s':>12} {'Write MB/s':>12}", curses.A_BOLD)
+        y = 3
+        for label, row in self.rows.items():
+            cells = []
+            for direction in ('read', 'write'):
+                test = row.get(direction)
+                cells.append('-' if test is None else
+                             'failed' if 'error' in test else test['speed_mbs'])
+            put(y, f" {label:<16} {cells[0]:>12} {cells[1]:>12}")
+            y += 1
+        y += 1
+        bar_width = max(10, min(40, width - 40))
+        filled = int(bar_width * self.fraction)
+        put(y, f" {self.job[:24]:<24} [{'#' * filled}{'-' * (bar_width - filled)}] "
+            f"{self.fraction:.0%} {self.speed}")
+        y += 2
+        put(y, '-' * (width - 1))
+        y += 1
+        tail = height - 1 - y
+        for line in list(self.log)[-tail:] if tail > 0 else []:
+            put(y, line)
+            y += 1
+        put(height - 1, f" {self.status}", curses.A_REVERSE)
+        screen.refresh()
+
+
+def parse_size(text):
+    """Parse a human-readable size like 256M, 4G or 512MiB into bytes."""
+    match = re.fullmatch(r'\s*(\d+(?:\.\d+)?)\s*([kmgt]?)(?:i?b)?\s*',
+                         str(text), re.IGNORECASE)
+    if not match:
+        raise ValueError(f"invalid size '{text}'")
+    return int(float(match.group(1)) * SIZE_UNITS[match.group(2).lower()])
+
+
+def format_test_size(test_size):
+    """Format the test_size setting of a result for display.
+
+    It holds fio's own text for sizes like 50%, and None when unknown.
+    """
+    if not test_size:
+        return 'unknown'
+    return test_size if isinstance(test_size, str) else format_size(test_size)
+
+
+def format_size(size_bytes):
+    """Format a size in bytes using binary units (e.g. 4 GiB)."""
+    size = float(size_bytes)
+    for unit in ('B', 'KiB', 'MiB', 'GiB'):
+        if size < 1024:
+            break
+        size /= 1024
     else:
-        # For Linux/macOS using psutil
-        partitions = psutil.disk_partitions(all=False)
-        for p in partitions:
-            if p.fstype:  # Skip empty or special filesystems
-                try:
-                    drive_info = {
-                        'path': p.mountpoint,
-                        'type': 'Fixed',
-                        'name': f"{p.device} ({p.fstype})",
-                        'size': get_disk_size(p.mountpoint)
-                    }
-                    disks.append(drive_info)
-                except:
-                    pass
+        unit = 'TiB'
+    return f"{size:.0f} {unit}" if size == int(size) else f"{size:.2f} {unit}"
 
-    return disks
+
+def block_size_arg(text):
+    """argparse type for --block-size, which must suit direct I/O."""
+    try:
+        size = parse_size(text)
+    except ValueError as e:
+        raise argparse.ArgumentTypeError(str(e))
+    if size < NATIVE_RANDOM_BLOCK or size % NATIVE_RANDOM_BLOCK:
+        raise argparse.ArgumentTypeError(
+            f"block size must be a multiple of {format_size(NATIVE_RANDOM_BLOCK)}")
+    return size
 
 
-def get_disk_size(path):
-    """Get the total size of a disk in GB."""
+def sweep_arg(text):
+    """argparse type for --sweep, like bs=4k,64k,1m or qd=1,8,32."""
+    key, _, values = text.partition('=')
+    key = key.strip().lower()
+    if key not in SWEEP_PARAMETERS or not values:
+        raise argparse.ArgumentTypeError(
+            f"expected bs=<sizes> or qd=<depths>, e.g. bs=4k,64k,1m, got '{text}'")
+    parsed = []
+    for value in values.split(','):
+        try:
+            number = parse_size(value) if key == 'bs' else int(value)
+        except ValueError as e:
+            raise argparse.ArgumentTypeError(str(e))
+        if key == 'bs' and (number < 1024 or number % 1024):
+            raise argparse.ArgumentTypeError(
+                f"invalid bs '{value}', block sizes must be multiples of 1K")
+        if number < 1:
+            raise argparse.ArgumentTypeError(f"invalid qd '{value}'")
+        if number not in parsed:
+            parsed.append(number)
+    return key, sorted(parsed)
+
+
+def export_arg(text):
+    """argparse type for --export, like prometheus:<path> or pushgateway:<url>."""
+    kind, _, destination = text.partition(':')
+    if kind not in ('prometheus', 'pushgateway') or not destination:
+        raise argparse.ArgumentTypeError(
+            f"expected prometheus:<path> or pushgateway:<url>, got '{text}'")
+    return kind, destination
+
+
+def chart_arg(text):
+    """argparse type for --chart, a path ending in .svg or .png."""
+    if not text.lower().endswith(('.svg', '.png')):
+        raise argparse.ArgumentTypeError(f"the chart path must end in .svg or .png, not '{text}'")
+    return text
+
+
+def fio_arg_arg(text):
+    """argparse type for --fio-arg, one fio argument we don't set ourselves."""
+    flag = text.split('=', 1)[0]
+    if flag in FIO_RESERVED_ARGS:
+        raise argparse.ArgumentTypeError(
+            f"{flag} is set by PyDiskMark and can't be passed, {FIO_RESERVED_ARGS[flag]}")
+    return text
+
+
+def fio_env_arg(text):
+    """argparse type for --fio-env, a KEY=VALUE environment variable."""
+    key, has_value, value = text.partition('=')
+    if not has_value or not re.fullmatch(r'[A-Za-z_]\w*', key):
+        raise argparse.ArgumentTypeError(f"expected KEY=VALUE, got '{text}'")
+    return key, value
+
+
+def log_filter_arg(text):
+    """argparse type for --log-filter, like warning,pdm=debug,git=error.
+
+    Returns the (logger, level) rules, the logger is None for the default.
+    """
+    rules = []
+    for rule in text.split(','):
+        name, has_level, level = rule.strip().rpartition('=')
+        level = level.strip().lower()
+        name = name.strip() if has_level else None
+        if level not in LOG_LEVELS:
+            raise argparse.ArgumentTypeError(
+                f"invalid level '{level}' in '{rule.strip()}', expected one of "
+                f"{', '.join(LOG_LEVELS)}")
+        if has_level and not name:
+            raise argparse.ArgumentTypeError(f"missing logger name in '{rule.strip()}'")
+        if any(existing == name for existing, _ in rules):
+            raise argparse.ArgumentTypeError(
+                f"{f'logger {name}' if name else 'the default level'} is set twice")
+        rules.append((name, level))
+    return rules
+
+
+def log_size_arg(text):
+    """argparse type for --log-max-size, like 10m."""
     try:
-        if platform.system() == 'Windows':
-            sectors_per_cluster, bytes_per_sector, free_clusters, total_clusters = win32file.GetDiskFreeSpace(
-                path)
-            total_size = total_clusters * sectors_per_cluster * bytes_per_sector
-            return f"{total_size / (1024**3):.2f} GB"
+        size = parse_size(text)
+    except ValueError as e:
+        raise argparse.ArgumentTypeError(str(e))
+    if size < 1024:
+        raise argparse.ArgumentTypeError("the log file must be allowed at least 1 KiB")
+    return size
+
+
+def positive_int_arg(text):
+    """argparse type for counts that must be at least 1."""
+    value = int(text)
+    if value < 1:
+        raise argparse.ArgumentTypeError("must be at least 1")
+    return value
+
+
+def non_negative_int_arg(text):
+    """argparse type for counts that may be 0."""
+    value = int(text)
+    if value < 0:
+        raise argparse.ArgumentTypeError("must not be negative")
+    return value
+
+
+def size_arg(text):
+    """argparse type for --size, enforcing the minimum test size."""
+    try:
+        size = parse_size(text)
+    except ValueError as e:
+        raise argparse.ArgumentTypeError(str(e))
+    if size < MIN_TEST_SIZE:
+        raise argparse.ArgumentTypeError(
+            f"size must be at least {format_size(MIN_TEST_SIZE)}")
+    return size
+
+
+def new_fio_config():
+    """Create an empty config that keeps key case and valueless options."""
+    config = configparser.ConfigParser(
+        allow_no_value=True, inline_comment_prefixes=('#', ';'),
+        interpolation=None, strict=False)
+    config.optionxform = str
+    return config
+
+
+def parse_fio_job_file(text, source):
+    """Parse the text of a fio job file the way fio reads it.
+
+    configparser alone gets fio files wrong: indented lines become continuations
+    of the previous value, ':' splits keys and repeated sections merge. Here
+    every line stands alone, only the first '=' splits it, keys without a value
+    (stonewall, group_reporting) stay valueless and key order and case are kept.
+    """
+    config = new_fio_config()
+    section = None
+    for number, line in enumerate(text.splitlines(), 1):
+        # Comments take a whole line or follow a blank, like "direct=1  # O_DIRECT"
+        line = re.sub(r'\s[#;].*', '', line).strip()
+        if not line or line[0] in '#;':
+            continue
+        if line.startswith('['):
+            if not line.endswith(']') or len(line) < 3:
+                raise PdmError(f"Could not parse fio config '{source}', line {number}: {line}",
+                               EXIT_PARSE_ERROR)
+            section = line[1:-1].strip()
+            if config.has_section(section) or section == configparser.DEFAULTSECT:
+                # fio would run a second job of the same name, the config can't hold it
+                raise PdmError(f"fio config '{source}' has more than one [{section}] "
+                               f"section (line {number}), give every job its own name",
+                               EXIT_PARSE_ERROR)
+            config.add_section(section)
+            continue
+        if section is None:
+            raise PdmError(f"fio config '{source}' has options before its first section, "
+                           f"line {number}: {line}", EXIT_PARSE_ERROR)
+        key, has_value, value = line.partition('=')
+        config.set(section, key.strip(), value.strip() if has_value else None)
+    return config
+
+
+def load_fio_config(path):
+    """Load a fio job file, keeping key case and valueless options.
+
+    A path of '-' reads the job file from stdin.
+    """
+    try:
+        if path == '-':
+            path = '<stdin>'
+            text = sys.stdin.read()
         else:
-            usage = psutil.disk_usage(path)
-            return f"{usage.total / (1024**3):.2f} GB"
-    except:
-        return "Unknown"
+            with open(path, 'r') as f:
+                text = f.read()
+    except OSError as e:
+        raise PdmError(f"Could not read fio config '{path}': {e.strerror}",
+                       EXIT_IO_ERROR)
+    config = parse_fio_job_file(text, path)
+
+    if not config.sections():
+        raise PdmError(f"fio config '{path}' is empty", EXIT_PARSE_ERROR)
+    if not [s for s in config.sections() if s != 'global']:
+        raise PdmError(f"fio config '{path}' has no job sections, only [global]",
+                       EXIT_PARSE_ERROR)
+    return config
+
+
+def write_fio_config(config):
+    """Render a fio job file from a loaded config."""
+    lines = []
+    for section in config.sections():
+        lines.append(f"[{section}]")
+        for key, value in config.items(section, raw=True):
+            lines.append(key if value is None else f"{key}={value}")
+        lines.append("")
+    return "\n".join(lines)
+
+
+def set_test_size(config, size):
+    """Override the size of the test file for every job."""
+    if not config.has_section('global'):
+        config.add_section('global')
+    for section in config.sections():
+        for key in ('size', 'filesize'):
+            if section != 'global':
+                config.remove_option(section, key)
+    if config.has_option('global', 'size'):
+        config.set('global', 'size', str(size))
+    config.set('global', 'filesize', str(size))
+
+
+def set_fio_duration(config, seconds):
+    """Make every job run for a fixed time instead of a number of loops."""
+    if not config.has_section('global'):
+        config.add_section('global')
+    for section in config.sections():
+        for key in ('time_based', 'runtime', 'loops'):
+            config.remove_option(section, key)
+    config.set('global', 'time_based', '1')
+    config.set('global', 'runtime', str(seconds))
+
+
+def format_duration(seconds):
+    """Format a number of seconds like 1 h 2 min 5 sec."""
+    minutes, seconds = divmod(round(seconds), 60)
+    hours, minutes = divmod(minutes, 60)
+    if hours:
+        return f"{hours} h {minutes} min {seconds} sec"
+    if minutes:
+        return f"{minutes} min {seconds} sec"
+    return f"{seconds} sec"
+
+
+def set_fio_target(config, test_path, is_directory=None):
+    """Point every job at the test path, overriding what the config says.
+
+    Directories become the job directory, while files and block devices
+    become the job filename. is_directory is looked up locally unless given.
+    """
+    if not config.has_section('global'):
+        config.add_section('global')
+    if is_directory is None:
+        is_directory = os.path.isdir(test_path)
+    for section in config.sections():
+        keys = ('directory',) if is_directory else ('directory', 'filename')
+        for key in keys:
+            if config.has_option(section, key):
+                log.debug(
+                    f"Overriding {key}={config.get(section, key)} in [{section}] with {test_path}")
+                config.remove_option(section, key)
+    if is_directory:
+        config.set('global', 'directory', test_path)
+    else:
+        config.set('global', 'filename', test_path)
+
+
+def enable_fio_percentiles(config):
+    """Make fio report the completion latency percentiles we show."""
+    if any(config.has_option('global', key) for key in ('clat_percentiles', 'lat_percentiles')):
+        return
+    config.set('global', 'clat_percentiles', '1')
+    config.set('global', 'percentile_list',
+               ':'.join(f"{p:g}" for p in LATENCY_PERCENTILES))
+
+
+def fio_job_writes(config, job):
+    """Check whether a job of the config writes to its file."""
+    for section in (job, 'global'):
+        if config.has_option(section, 'rw'):
+            rw = config.get(section, 'rw').split(':')[0]
+            break
+    else:
+        rw = 'read'
+    return rw not in ('read', 'randread')
+
+
+def fio_job_direct(config, job):
+    """Check whether a job of the config bypasses the page cache."""
+    for section in (job, 'global'):
+        if config.has_option(section, 'direct'):
+            return config.get(section, 'direct') in (None, '1', 'true')
+        if config.has_option(section, 'buffered'):
+            return config.get(section, 'buffered') in ('0', 'false')
+    return False
+
+
+def set_fio_direct(config):
+    """Make the jobs use direct I/O unless the config decides it itself."""
+    if any(config.has_option(section, key) for section in config.sections()
+           for key in ('direct', 'buffered')):
+        return
+    if not config.has_section('global'):
+        config.add_section('global')
+    config.set('global', 'direct', '1')
+
+
+# fio options deciding what the written buffers hold, --data-pattern sets them
+FIO_BUFFER_OPTIONS = ('zero_buffers', 'refill_buffers', 'scramble_buffers',
+                      'buffer_compress_percentage', 'buffer_pattern', 'dedupe_percentage')
+
+
+def data_pattern_arg(text):
+    """Parse a --data-pattern of zero, random or compressible:<percent> for argparse."""
+    match = re.fullmatch(r'(zero|random|compressible:(\d+))', text.strip().lower())
+    if not match or (match.group(2) and not 0 <= int(match.group(2)) <= 100):
+        raise argparse.ArgumentTypeError(
+            f"invalid data pattern '{text}', use zero, random or compressible:<0-100>")
+    return f"compressible:{int(match.group(2))}" if match.group(2) else match.group(1)
+
+
+def set_fio_data_pattern(config, pattern):
+    """Make every job write the data of a --data-pattern, warning about what it replaces."""
+    if pattern == 'zero':
+        options = {'zero_buffers': '1'}
+    else:
+        # Fresh buffers for every I/O, so a controller can't dedupe the repeats
+        options = {'refill_buffers': '1'}
+        if pattern.startswith('compressible:'):
+            options['buffer_compress_percentage'] = pattern.split(':')[1]
+    # Valueless options like refill_buffers mean 1
+    overridden = sorted({f"{key} in [{section}]" for section in config.sections()
+                         for key in FIO_BUFFER_OPTIONS if config.has_option(section, key)
+                         and (config.get(section, key) or '1') != options.get(key)})
+    if overridden:
+        log.warning(f"--data-pattern {pattern} overrides {', '.join(overridden)}")
+    for section in config.sections():
+        for key in FIO_BUFFER_OPTIONS:
+            config.remove_option(section, key)
+    if not config.has_section('global'):
+        config.add_section('global')
+    for key, value in options.items():
+        config.set('global', key, value)
+
+
+def pattern_bytes(pattern, size):
+    """Make size bytes of test data following a --data-pattern."""
+    if pattern == 'zero':
+        return bytes(size)
+    data = bytearray(os.urandom(size))
+    if pattern.startswith('compressible:'):
+        # Zeroing the same share of every 4 KiB lets each block compress by it
+        zeros = 4096 * int(pattern.split(':')[1]) // 100
+        for offset in range(0, size, 4096):
+            end = min(offset + zeros, size)
+            data[offset:end] = bytes(end - offset)
+    return bytes(data)
+
+
+def drop_page_caches():
+    """Flush and drop the Linux page cache so reads come from the disk.
+
+    Needs root, returns whether it worked and warns once if it didn't.
+    """
+    reason = None
+    if platform.system() != 'Linux':
+        reason = f"dropping caches isn't supported on {platform.system()}"
+    else:
+        os.sync()
+        try:
+            with open('/proc/sys/vm/drop_caches', 'w') as f:
+                f.write('3\n')
+            return True
+        except OSError as e:
+            reason = f"writing /proc/sys/vm/drop_caches failed: {e.strerror}, run as root"
+    if not page_cache_state['warned']:
+        log.warning(f"Could not drop the page cache, {reason}")
+        page_cache_state['warned'] = True
+    return False
+
+
+def warn_buffered_io(test_path, buffered_jobs):
+    """Warn loudly when read results may come from the page cache."""
+    if buffered_jobs:
+        print(f"WARNING: {', '.join(buffered_jobs)} use buffered I/O, their results include "
+              "the OS page cache and may be far too high.", file=sys.stderr)
+    mount = get_mount(test_path)
+    if mount and mount.fstype in BUFFERED_FILESYSTEMS:
+        print(f"WARNING: {mount.mountpoint} is {mount.fstype}, which doesn't honor direct "
+              "I/O, the results may come from memory rather than a disk.", file=sys.stderr)
+
+
+def get_fio_jobs(config):
+    """List the job sections of a config in file order."""
+    return [section for section in config.sections() if section != 'global']
+
+
+def filter_fio_jobs(config, patterns):
+    """Keep only the jobs matching any of the case-insensitive glob patterns."""
+    jobs = get_fio_jobs(config)
+    selected = set()
+    for pattern in patterns:
+        matches = [job for job in jobs
+                   if fnmatch.fnmatch(job.lower(), pattern.lower())]
+        if not matches:
+            raise PdmError(f"Unknown job '{pattern}', available jobs: {', '.join(jobs)}",
+                           EXIT_BAD_ARGS)
+        selected.update(matches)
+    for job in jobs:
+        if job not in selected:
+            config.remove_section(job)
+
+
+def make_latency_config():
+    """Build the job file of --mode latency, a single 4K random read job at QD1."""
+    config = new_fio_config()
+    config.add_section('global')
+    for key, value in (('size', '1g'), ('direct', '1'), ('time_based', '1'),
+                       ('runtime', str(LATENCY_SECONDS))):
+        config.set('global', key, value)
+    config.add_section(LATENCY_JOB)
+    for key, value in (('rw', 'randread'), ('bs', '4k'), ('iodepth', '1'), ('numjobs', '1')):
+        config.set(LATENCY_JOB, key, value)
+    return config
+
+
+def add_fio_fsync_job(config):
+    """Add the --fsync-test job, 4K sequential writes with an fsync after each."""
+    if config.has_section(FSYNC_JOB):
+        return
+    config.add_section(FSYNC_JOB)
+    for key, value in (('rw', 'write'), ('bs', '4k'), ('iodepth', '1'), ('numjobs', '1'),
+                       ('fsync', '1')):
+        config.set(FSYNC_JOB, key, value)
+
+
+def fsync_stats(mean_us, p99_us, max_us, syncs, seconds):
+    """Collect the fsync latency and rate of a job, flagging a likely volatile cache."""
+    return {
+        'mean_us': mean_us,
+        'p99_us': p99_us,
+        'max_us': max_us,
+        'syncs_per_second': syncs / seconds if seconds else None,
+        'volatile_cache_suspected': mean_us < FSYNC_VOLATILE_US,
+    }
+
+
+def set_fio_steady_state(config):
+    """Make the first sequential write job run until its bandwidth settles.
+
+    Returns the name of the job, None if the config has no sequential write job.
+    """
+    for job in get_fio_jobs(config):
+        rw = config.get(job, 'rw', fallback=config.get('global', 'rw', fallback='read'))
+        if rw.split(':')[0] == 'write':
+            for key, value in STEADY_STATE_OPTIONS.items():
+                config.set(job, key, value)
+            return job
+    return None
+
+
+def steady_state_unsupported_reason(version):
+    """Tell why a fio version can't detect steady state, None when it can."""
+    parsed = fio_version_tuple(version)
+    if parsed is not None and parsed < FIO_STEADY_STATE_VERSION:
+        return f"steady state detection needs fio " \
+            f"{fio_version_text(FIO_STEADY_STATE_VERSION)} or newer, found {version}"
+    return None
+
+
+def trim_unsupported_reason(test_path, remote=None):
+    """Tell why fio can't trim the target, None when it can."""
+    if remote:
+        return "the discard support of a device on a --remote host can't be checked"
+    if platform.system() != 'Linux':
+        return "fio only trims on Linux"
+    if not is_block_device(test_path):
+        return "fio only trims block devices, not files on a filesystem"
+    device = get_block_device(test_path)
+    if device and get_discard_max_bytes(device) == 0:
+        return f"{device} doesn't advertise discard support"
+    return None
+
+
+def add_fio_trim_job(config):
+    """Add the --trim-test job, discarding the test range sequentially."""
+    if config.has_section(TRIM_JOB):
+        return
+    config.add_section(TRIM_JOB)
+    for key, value in (('rw', 'trim'), ('bs', '1m'), ('iodepth', '1'), ('numjobs', '1')):
+        config.set(TRIM_JOB, key, value)
+
+
+def unsupported_trim_result(reason):
+    """The result entry of a --trim-test that couldn't run on the target."""
+    log.warning(f"Skipping the trim test: {reason}")
+    return {'name': TRIM_JOB, 'direction': 'trim', 'error': 'unsupported', 'reason': reason}
+
+
+def mixed_percent_arg(text):
+    """Parse the read share of a --mixed job, 1 to 99 percent, for argparse."""
+    try:
+        percent = int(text.strip().rstrip('%'))
+    except ValueError:
+        raise argparse.ArgumentTypeError(f"invalid read percentage '{text}'")
+    if not 1 <= percent <= 99:
+        raise argparse.ArgumentTypeError(f"the read percentage must be 1 to 99, not {percent}")
+    return percent
+
+
+def add_fio_mixed_jobs(config, read_percents):
+    """Add a random 4K Q32T1 job mixing reads and writes for every --mixed share.
+
+    The jobs are named like RND-M70-4K-Q32-T1, M70 meaning 70% reads.
+    """
+    for percent in read_percents:
+        section = f"RND-M{percent}-4K-Q32-T1"
+        if config.has_section(section):
+            continue
+        config.add_section(section)
+        for key, value in (('rw', 'randrw'), ('rwmixread', str(percent)), ('bs', '4k'),
+                           ('iodepth', '32'), ('numjobs', '1')):
+            config.set(section, key, value)
+
+
+def make_sweep_config(config, sweeps, pattern):
+    """Replace the jobs of a config with one job per swept bs/qd value.
+
+    sweeps maps 'bs' and/or 'qd' to the values to try, both together give
+    every combination. Reads and writes are generated for each point.
+    """
+    for job in get_fio_jobs(config):
+        config.remove_section(job)
+    base = SWEEP_BASE[pattern]
+    block_sizes = sweeps.get('bs', [base['bs']])
+    depths = sweeps.get('qd', [base['qd']])
+    kind = pattern.upper()
+    for rw, fio_rw in (('R', 'read'), ('W', 'write')):
+        for block_size in block_sizes:
+            for depth in depths:
+                job = f"{kind}-{rw}-{block_label(block_size)}-Q{depth}-T1"
+                config.add_section(job)
+                config.set(job, 'iodepth', str(depth))
+                config.set(job, 'numjobs', '1')
+                config.set(job, 'bs', block_label(block_size).lower())
+                config.set(job, 'rw', fio_rw if pattern == 'seq' else f"rand{fio_rw}")
+
+
+def build_sweep_series(tests, parameters):
+    """Order the results of a sweep by the swept values."""
+    points = {}
+    for test in tests:
+        spl = test['name'].split('-')
+        point = points.setdefault((parse_size(spl[2]), int(spl[3][1:])), {
+            'bs': spl[2], 'qd': int(spl[3][1:])})
+        if 'error' in test:
+            point[f"{test['direction']}_error"] = test['error']
+            continue
+        point[f"{test['direction']}_mbs"] = float(test['speed_mbs'])
+        point[f"{test['direction']}_iops"] = test['iops']
+    return {'parameters': parameters,
+            'points': [points[key] for key in sorted(points)]}
+
+
+def get_block_device_size(path):
+    """Get the size of a block device in bytes by seeking to its end."""
+    try:
+        fd = os.open(path, os.O_RDONLY)
+        try:
+            return os.lseek(fd, 0, os.SEEK_END)
+        finally:
+            os.close(fd)
+    except OSError as e:
+        raise PdmError(f"Could not get the size of '{path}': {e.strerror}", EXIT_IO_ERROR)
+
+
+def make_region_config(config, regions, device_size):
+    """Replace the sequential jobs with one job per region of the device.
+
+    The regions are evenly spaced from the start to the end of the device, the
+    jobs are named after the job and region like SEQ-R-1M-Q8-T1@2.
+    """
+    length = min(get_test_size(config) or device_size, device_size // regions)
+    length -= length % REGION_ALIGNMENT
+    if length <= 0:
+        raise PdmError(f"The device is too small to split into {regions} regions", EXIT_BAD_ARGS)
+    step = (device_size - length) // (regions - 1)
+    # Every job is added again so the region jobs stay where the job was
+    for job in get_fio_jobs(config):
+        rw = config.get(job, 'rw', fallback=config.get('global', 'rw', fallback='read'))
+        options = config.items(job, raw=True)
+        config.remove_section(job)
+        if rw.split(':')[0] not in ('read', 'write'):
+            config.add_section(job)
+            for key, value in options:
+                config.set(job, key, value)
+            continue
+        for region in range(regions):
+            section = f"{job}@{region + 1}"
+            config.add_section(section)
+            for key, value in options:
+                config.set(section, key, value)
+            offset = region * step
+            config.set(section, 'offset', str(offset - offset % REGION_ALIGNMENT))
+            config.set(section, 'size', str(length))
+
+
+def build_region_series(tests, config):
+    """Group the results of a --regions run by job and direction, ordered by offset.
+
+    Each series also gets the falloff in percent from the first to the last region.
+    """
+    series = {}
+    for test in tests:
+        job, _, region = test['name'].partition('@')
+        if not region or 'error' in test:
+            continue
+        entry = series.setdefault((job, test['direction']), {
+            'job': job, 'direction': test['direction'], 'points': []})
+        entry['points'].append({
+            'region': int(region),
+            'offset': int(config.get(test['name'], 'offset')),
+            'speed_mbs': float(test['speed_mbs']),
+        })
+    for entry in series.values():
+        entry['points'].sort(key=lambda point: point['region'])
+        first, last = entry['points'][0]['speed_mbs'], entry['points'][-1]['speed_mbs']
+        entry['falloff_percent'] = round((last - first) / first * 100, 1) if first else None
+    return list(series.values())
+
+
+def apply_fio_overrides(config, overrides):
+    """Apply --set [section.]key[=value] overrides to a loaded config.
+
+    Without a section the key goes into [global], '*' means every job.
+    """
+    for override in overrides:
+        name, has_value, value = override.partition('=')
+        section, _, key = name.rpartition('.')
+        key = key.strip()
+        if not key:
+            raise PdmError(f"Invalid --set '{override}', expected [section.]key=value",
+                           EXIT_BAD_ARGS)
+        if not section:
+            sections = ['global']
+            if not config.has_section('global'):
+                config.add_section('global')
+        elif section == '*':
+            sections = get_fio_jobs(config)
+        elif config.has_section(section):
+            sections = [section]
+        else:
+            raise PdmError(f"Unknown section '{section}' in --set '{override}', available "
+                           f"sections: {', '.join(config.sections())}", EXIT_BAD_ARGS)
+        for target in sections:
+            log.debug(f"Setting {key}={value} in [{target}]")
+            # Options like time_based are given without a value
+            config.set(target, key, value.strip() if has_value else None)
+
+
+def filter_fio_mode(config, mode):
+    """Drop the jobs that don't fit a read or write only run."""
+    if mode == 'readwrite':
+        return
+    skipped = []
+    for job in get_fio_jobs(config):
+        writes = fio_job_writes(config, job)
+        rw = config.get(job, 'rw', fallback=config.get('global', 'rw', fallback='read'))
+        # Mixed jobs both read and write, so they only run in readwrite mode
+        mixed = rw.split(':')[0] in ('rw', 'readwrite', 'randrw', 'trimwrite')
+        if mixed or writes != (mode == 'write'):
+            config.remove_section(job)
+            skipped.append(job)
+    if skipped:
+        print(f"Note: Skipping {', '.join(skipped)} in {mode} mode", file=sys.stderr)
+    if not get_fio_jobs(config):
+        raise PdmError(f"No jobs left to run in {mode} mode", EXIT_BAD_ARGS)
+
+
+def get_test_size(config):
+    """Get the size of the test file from a loaded config in bytes.
+
+    None when it isn't set or isn't a plain size, like 50% of the target.
+    """
+    for key in ('filesize', 'size'):
+        if config.has_option('global', key):
+            try:
+                return parse_size(config.get('global', key))
+            except ValueError:
+                return None
+    return None
+
+
+def is_block_device(path):
+    """Check whether a path is a raw disk rather than a file or directory."""
+    if platform.system() == 'Windows':
+        return re.fullmatch(r'\\\\\.\\PhysicalDrive\d+', path, re.IGNORECASE) is not None
+    try:
+        return stat.S_ISBLK(os.stat(path).st_mode)
+    except OSError:
+        return False
+
+
+def validate_target(path, allow_block_device=False):
+    """Check that a target path can be benchmarked, returning an error or None."""
+    if is_block_device(path):
+        if not allow_block_device:
+            return f"'{path}' is a block device, pass --allow-block-device to test it"
+        if not os.access(path, os.R_OK | os.W_OK):
+            return f"Block device '{path}' is not readable and writable"
+        return None
+
+    try:
+        mode = os.stat(path).st_mode
+    except FileNotFoundError:
+        mode = None
+    except OSError as e:
+        return f"Cannot access '{path}': {e.strerror}"
+
+    if mode is not None and stat.S_ISDIR(mode):
+        directory = path
+    else:
+        directory = os.path.dirname(os.path.abspath(path))
+        if not os.path.isdir(directory):
+            return f"Parent directory '{directory}' does not exist"
+    if not os.access(directory, os.W_OK):
+        return f"Directory '{directory}' is not writable"
+    return None
+
+
+def get_fio_data_files(config):
+    """List the data files the jobs of a config lay out, in fio's naming."""
+    return list(get_fio_file_sizes(config))
+
+
+def fio_job_size(value, job, key):
+    """Read a size option of a job in bytes, None where fio takes it from the target.
+
+    A percentage like 50% is a share of the file or device fio finds, a
+    range like 1M-10M counts as its upper end, the most fio lays out.
+    """
+    if '%' in value:
+        return None
+    try:
+        return max(parse_size(part) for part in value.split('-'))
+    except ValueError:
+        raise PdmError(f"Invalid {key}={value} in job {job}", EXIT_PARSE_ERROR)
+
+
+def fio_job_count(value, job, key):
+    """Read a count option of a job like numjobs."""
+    try:
+        count = int(value)
+    except ValueError:
+        count = 0
+    if count < 1:
+        raise PdmError(f"Invalid {key}={value} in job {job}", EXIT_PARSE_ERROR)
+    return count
+
+
+def get_fio_file_sizes(config):
+    """Map each data file the jobs of a config lay out to its size in bytes.
+
+    The size is None where fio takes it from the target, see fio_job_size().
+    """
+    files = {}
+    for section in config.sections():
+        if section == 'global':
+            continue
+
+        def option(key, default=None):
+            if config.has_option(section, key):
+                return config.get(section, key)
+            if config.has_option('global', key):
+                return config.get('global', key)
+            return default
+
+        directory = option('directory', os.getcwd())
+        filename = option('filename')
+        nrfiles = fio_job_count(option('nrfiles', '1'), section, 'nrfiles')
+        numjobs = fio_job_count(option('numjobs', '1'), section, 'numjobs')
+        size = option('filesize') or option('size')
+        # size is spread over the files of a job, filesize is per file
+        if option('filesize'):
+            file_size = fio_job_size(size, section, 'filesize')
+        else:
+            file_size = fio_job_size(size, section, 'size') if size else 0
+            if file_size:
+                file_size //= nrfiles
+        if filename:
+            names = [filename]
+        else:
+            # Without a filename fio uses $jobname.$jobnum.$filenum
+            names = [f"{section}.{jobnum}.{filenum}"
+                     for jobnum in range(numjobs) for filenum in range(nrfiles)]
+        for name in names:
+            path = os.path.join(directory, name)
+            # Jobs sharing a file need it as large as the largest of them
+            known = files.get(path, 0)
+            files[path] = None if None in (known, file_size) else max(known, file_size)
+    return files
+
+
+def get_fio_required_space(config):
+    """Get how many bytes the data files of a config still need on disk."""
+    required = 0
+    for path, size in get_fio_file_sizes(config).items():
+        if size is None:
+            log.info(f"The free space check leaves out {path}, fio sizes it from the target")
+            continue
+        # fio reuses data files that are already there
+        existing = os.path.getsize(path) if os.path.isfile(path) else 0
+        required += max(size - existing, 0)
+    return required
+
+
+def preallocate_file(path, size, pattern='random'):
+    """Create a data file of size bytes once, returning how it was allocated."""
+    method = 'written'
+    with open(path, 'wb') as f:
+        if platform.system() == 'Windows':
+            try:
+                f.truncate(size)
+                # Needs the manage volume privilege, which admins may not hold
+                win32file.SetFileValidData(win32file._get_osfhandle(f.fileno()), size)
+                return 'SetFileValidData'
+            except Exception as e:
+                log.debug(f"SetFileValidData on {path} failed: {e}")
+        elif hasattr(os, 'posix_fallocate'):
+            try:
+                os.posix_fallocate(f.fileno(), 0, size)
+                method = 'fallocate'
+            except OSError as e:
+                log.debug(f"fallocate of {path} failed: {e.strerror}")
+        # Preallocated extents read back as zeros without touching the disk,
+        # so they get real data for the read jobs to measure
+        f.seek(0)
+        chunk = pattern_bytes(pattern, PREPARE_CHUNK)
+        written = 0
+        while written < size:
+            progress_bar(written, size, f"{f'Preparing {format_size(size)}':<32}")
+            written += f.write(chunk[:min(PREPARE_CHUNK, size - written)])
+        f.flush()
+        os.fsync(f.fileno())
+    return method
+
+
+def prepare_fio_files(config, pattern='random'):
+    """Lay out the data files of a config before any job, reusing earlier ones.
+
+    A marker next to each file records the size and data pattern it was
+    prepared with, files whose marker doesn't match are created again.
+    Returns the path, size, status (created or reused) and allocation
+    method of every file.
+    """
+    prepared = []
+    for path, size in get_fio_file_sizes(config).items():
+        if not size:
+            continue
+        marker_path = path + PREPARED_SUFFIX
+        try:
+            with open(marker_path) as f:
+                marker = json.load(f)
+        except (OSError, json.JSONDecodeError):
+            marker = None
+        if isinstance(marker, dict) and marker.get('size') == size and \
+                marker.get('pattern', 'random') == pattern and \
+                os.path.isfile(path) and os.path.getsize(path) == size:
+            log.info(f"Reusing the prepared test file {path}")
+            prepared.append({'path': path, 'size': size, 'status': 'reused',
+                             'method': marker.get('method')})
+            continue
+        if isinstance(marker, dict):
+            log.info(f"Preparing {path} again, it was prepared for a different size or pattern")
+        try:
+            with contextlib.suppress(FileNotFoundError):
+                os.remove(marker_path)
+            method = preallocate_file(path, size, pattern)
+            write_file_atomic(marker_path, json.dumps({
+                'size': size,
+                'pattern': pattern,
+                'method': method,
+                'created': time.strftime('%Y-%m-%dT%H:%M:%S%z'),
+            }) + "\n")
+        except OSError as e:
+            raise PdmError(f"Could not prepare the test file '{path}': {e.strerror}",
+                           EXIT_IO_ERROR)
+        prepared.append({'path': path, 'size': size, 'status': 'created', 'method': method})
+    return prepared
+
+
+def remove_fio_data_files(files, shred=False):
+    """Delete the data files fio left on the target.
+
+    With shred they are overwritten with zeros first, returning how like shred_files().
+    """
+    files = [path for path in files if os.path.exists(path)]
+    shredded = shred_files(files) if shred and files else None
+    for path in files:
+        try:
+            os.remove(path)
+            log.debug(f"Deleted fio data file {path}")
+        except OSError as e:
+            print(f"Error deleting fio file '{path}': {e.strerror}", file=sys.stderr)
+    return shredded
+
+
+def punch_hole(fd, size):
+    """Deallocate the first size bytes of a file, so the filesystem discards them.
+
+    Returns False where the OS or filesystem can't.
+    """
+    if platform.system() != 'Linux':
+        return False
+    libc = ctypes.CDLL(None, use_errno=True)
+    libc.fallocate.argtypes = [ctypes.c_int, ctypes.c_int, ctypes.c_int64, ctypes.c_int64]
+    if libc.fallocate(fd, FALLOC_FL_KEEP_SIZE | FALLOC_FL_PUNCH_HOLE, 0, size) != 0:
+        log.debug(f"Punching a hole failed: {os.strerror(ctypes.get_errno())}")
+        return False
+    return True
+
+
+def shred_file(path):
+    """Overwrite a file with zeros and punch a hole over it, returning the method used."""
+    size = os.path.getsize(path)
+    prefix = f"{f'Shredding {format_size(size)}':<32}"
+    chunk = bytes(SHRED_CHUNK)
+    with open(path, 'r+b') as f:
+        written = 0
+        while written < size:
+            progress_bar(written, size, prefix)
+            written += f.write(chunk[:min(SHRED_CHUNK, size - written)])
+        f.flush()
+        os.fsync(f.fileno())
+        if punch_hole(f.fileno(), size):
+            return 'zeros+punch-hole'
+    return 'zeros'
+
+
+def shred_files(files):
+    """Overwrite test files with zeros before they are deleted, for --shred.
+
+    Returns the method, bytes and seconds of the wipe for the run metadata,
+    the method is 'zeros+punch-hole' only if every file could be deallocated.
+    """
+    started = time.monotonic()
+    methods = set()
+    total = 0
+    for path in files:
+        try:
+            size = os.path.getsize(path)
+            methods.add(shred_file(path))
+        except OSError as e:
+            raise PdmError(f"Could not shred '{path}': {e.strerror}", EXIT_IO_ERROR)
+        total += size
+    seconds = round(time.monotonic() - started, 1)
+    method = 'zeros' if 'zeros' in methods else 'zeros+punch-hole'
+    log.info(f"Shredded {format_size(total)} of test data with {method} in {seconds}s")
+    return {'method': method, 'bytes': total, 'seconds': seconds}
+
+
+def check_shred_target(test_path):
+    """Refuse --shred on a block device, a test file wipe isn't a device sanitize."""
+    if is_block_device(test_path):
+        raise PdmError(f"--shred only wipes the test files, on the block device '{test_path}' "
+                       "it would overwrite part of the disk and give false confidence, sanitize "
+                       "the whole device with blkdiscard --secure, nvme sanitize or "
+                       "hdparm --security-erase instead", EXIT_BAD_ARGS)
+
+
+def check_free_space(test_path, required):
+    """Make sure the test files fit, with some margin, before anything is written."""
+    if not required or stat.S_ISBLK(os.stat(test_path).st_mode):
+        return
+    required = int(required * (1 + FREE_SPACE_MARGIN))
+    drive_stats = get_drive_stats(test_path)
+    if drive_stats != "Unknown" and drive_stats[2] < required:
+        raise PdmError(f"Not enough free space on '{test_path}': "
+                       f"{format_size(required)} required including a {FREE_SPACE_MARGIN:.0%} margin, "
+                       f"{format_size(drive_stats[2])} available. "
+                       "Use --ignore-free-space to run anyway.",
+                       EXIT_IO_ERROR)
+
+
+def check_fio_available():
+    """Check if fio is available in the system."""
+    try:
+        command_runner.run([fio_command['path'], '--version'])
+        return True
+    except FileNotFoundError:
+        return False
+
+
+def select_fio_binary(path, origin='--fio-binary'):
+    """Pick the fio binary that every fio command runs.
+
+    A given path must be an executable that answers --version like fio does,
+    otherwise fio is looked up on PATH. Returns the absolute path, or None if
+    there is no fio on PATH.
+    """
+    if path:
+        if not os.path.isfile(path):
+            raise PdmError(f"{origin} '{path}' does not exist", EXIT_MISSING_BACKEND)
+        if not os.access(path, os.X_OK):
+            raise PdmError(f"{origin} '{path}' is not executable", EXIT_MISSING_BACKEND)
+        try:
+            result = command_runner.run([path, '--version'], merge_stderr=True)
+        except OSError as e:
+            raise PdmError(f"{origin} '{path}' could not be run: {e.strerror}",
+                           EXIT_MISSING_BACKEND)
+        if result.returncode != 0 or not fio_version_tuple(result.stdout):
+            output = (result.stdout.strip().splitlines() or ['nothing'])[0]
+            answer = f"exited with code {result.returncode}" if result.returncode \
+                else f"printed {output!r}"
+            raise PdmError(f"{origin} '{path}' doesn't look like fio, --version {answer}",
+                           EXIT_MISSING_BACKEND)
+        resolved = os.path.abspath(path)
+    else:
+        resolved = shutil.which('fio')
+        if resolved is None:
+            return None
+    fio_command['path'] = resolved
+    log.info(f"Using fio at {resolved}")
+    return resolved
+
+
+def fio_too_old_reason(version):
+    """Tell why a fio version is too old to run the benchmark, None if it isn't.
+
+    A version that can't be read is given the benefit of the doubt.
+    """
+    parsed = fio_version_tuple(version)
+    if parsed is None:
+        log.debug(f"Could not read the fio version from {version!r}")
+        return None
+    if parsed < FIO_MIN_VERSION:
+        return f"{version} is too old, PyDiskMark needs fio " \
+            f"{fio_version_text(FIO_MIN_VERSION)} or newer."
+    return None
+
+
+def get_fio_version():
+    """Get the version string reported by fio."""
+    try:
+        return command_runner.run([fio_command['path'], '--version']).stdout.strip()
+    except FileNotFoundError:
+        return None
+
+
+def fio_version_tuple(version):
+    """Turn a fio version string into (major, minor, patch), None if it can't be read.
+
+    Handles fio-3.36, fio-2.2.10, git builds like fio-3.28-42-g1234abcd (the
+    commits since 3.28 aren't a patch level) and suffixes like -dirty.
+    """
+    match = re.search(r'(\d+)\.(\d+)(?:\.(\d+))?', version or '')
+    if not match:
+        return None
+    return tuple(int(part or 0) for part in match.groups())
+
+
+def fio_version_text(version):
+    """Format a version tuple the way fio does, 3.0 rather than 3.0.0."""
+    return '.'.join(map(str, version if version[2:] and version[2] else version[:2]))
+
+
+@functools.lru_cache(maxsize=None)
+def probe_fio_ioengines():
+    """Ask fio which I/O engines it supports, returning (engines, raw output)."""
+    try:
+        result = command_runner.run([fio_command['path'], '--enghelp'], merge_stderr=True)
+    except FileNotFoundError:
+        return [], ''
+    # The engines are listed one per indented line below a header
+    engines = [line.strip() for line in result.stdout.splitlines()
+               if line[:1].isspace() and line.strip()]
+    return engines, result.stdout
+
+
+def select_fio_ioengine(requested):
+    """Resolve --ioengine to an engine fio supports, probing for auto."""
+    engines, output = probe_fio_ioengines()
+    if requested != 'auto':
+        if requested not in engines:
+            raise PdmError(f"fio does not support the {requested} ioengine, "
+                           f"fio --enghelp reports:\n{output.strip()}", EXIT_MISSING_BACKEND)
+        return requested
+    preference = IOENGINE_PREFERENCE.get(platform.system(), IOENGINE_PREFERENCE['default'])
+    for engine in preference:
+        if engine in engines:
+            return engine
+    # Let fio fall back to its own default
+    return None
+
+
+def set_fio_ioengine(config, requested):
+    """Set the ioengine of every job, keeping one the config names when auto."""
+    configured = [section for section in config.sections()
+                  if config.has_option(section, 'ioengine')]
+    if requested == 'auto' and configured:
+        log.info("Using the ioengine set by the fio config")
+        return
+    engine = select_fio_ioengine(requested)
+    if engine is None:
+        log.info("No preferred ioengine found, leaving the choice to fio")
+        return
+    log.info(f"Using the {engine} ioengine")
+    for section in configured:
+        config.remove_option(section, 'ioengine')
+    if not config.has_section('global'):
+        config.add_section('global')
+    config.set('global', 'ioengine', engine)
+
+
+def get_available_disks():
+    """Detect all available disks in the system."""
+    disks = []
+
+    if platform.system() == 'Windows':
+        drives = win32api.GetLogicalDriveStrings().split('\000')[:-1]
+        for drive in drives:
+            try:
+                drive_type = win32file.GetDriveType(drive)
+                # Only include fixed drives (3) and removable drives (2)
+                if drive_type in (2, 3):
+                    drive_info = {
+                        'path': drive,
+                        'type': 'Fixed' if drive_type == 3 else 'Removable',
+                        'name': f"Drive {drive}",
+                        'size': get_disk_size(drive)
+                    }
+                    disks.append(drive_info)
+            except:
+                pass
+    else:
+        # For Linux/macOS using psutil
+        partitions = psutil.disk_partitions(all=False)
+        for p in partitions:
+            if p.fstype:  # Skip empty or special filesystems
+                try:
+                    drive_info = {
+                        'path': p.mountpoint,
+                        'type': 'Fixed',
+                        'name': f"{p.device} ({p.fstype})",
+                        'size': get_disk_size(p.mountpoint)
+                    }
+                    disks.append(drive_info)
+                except:
+                    pass
+
+    return disks
+
+
+def get_disk_size(path):
+    """Get the total size of a disk in GB."""
+    try:
+        if platform.system() == 'Windows':
+            sectors_per_cluster, bytes_per_sector, free_clusters, total_clusters = win32file.GetDiskFreeSpace(
+                path)
+            total_size = total_clusters * sectors_per_cluster * bytes_per_sector
+            return f"{total_size / (1024**3):.2f} GB"
+        else:
+            usage = psutil.disk_usage(path)
+            return f"{usage.total / (1024**3):.2f} GB"
+    except:
+        return "Unknown"
+
+
+def make_fio_job_config(config, job):
+    """Build a config holding the global section and a single job."""
+    job_config = new_fio_config()
+    for section in ('global', job):
+        if config.has_section(section):
+            job_config.add_section(section)
+            for key, value in config.items(section, raw=True):
+                job_config.set(section, key, value)
+    # The cooldown between jobs replaces any staggered start delays, wherever
+    # the file set them. Files without one are left alone.
+    for section in ('global', job):
+        if job_config.has_section(section):
+            job_config.remove_option(section, 'startdelay')
+    return job_config
+
+
+def parse_seconds(text):
+    """Parse a fio time value like 30, 30s, 5m or 1h into seconds."""
+    match = re.fullmatch(r'\s*(\d+)\s*(s|m|h)?\s*', str(text), re.IGNORECASE)
+    if not match:
+        return None
+    unit = (match.group(2) or 's').lower()
+    return int(match.group(1)) * {'s': 1, 'm': 60, 'h': 3600}[unit]
+
+
+def get_job_duration(config, job):
+    """Get how long a time based job runs in seconds, or None if unknown."""
+    def option(key):
+        if config.has_option(job, key):
+            return config.get(job, key)
+        if config.has_option('global', key):
+            return config.get('global', key)
+        return None
+
+    if not (config.has_option(job, 'time_based') or config.has_option('global', 'time_based')):
+        return None
+    runtime = option('runtime')
+    return parse_seconds(runtime) if runtime else None
+
+
+def estimate_fio_job_seconds(config, job):
+    """Guess how long a job runs from its runtime or size, or None if unknown."""
+    def option(key, default=None):
+        if config.has_option(job, key):
+            return config.get(job, key)
+        if config.has_option('global', key):
+            return config.get('global', key)
+        return default
+
+    ramp = parse_seconds(option('ramp_time', '0')) or 0
+    duration = get_job_duration(config, job)
+    if duration is not None:
+        return ramp + duration
+    runtime = option('runtime')
+    runtime = parse_seconds(runtime) if runtime else None
+    size = option('io_size') or option('filesize') or option('size')
+    try:
+        size = parse_size(size) if size else None
+    except ValueError:
+        # Percentages of the device and the like
+        size = None
+    if size is None:
+        return ramp + runtime if runtime is not None else None
+    loops = int(option('loops', '1'))
+    random_io = option('rw', 'read').split(':')[0].startswith('rand')
+    seconds = size * loops / (ESTIMATE_RND_SPEED if random_io else ESTIMATE_SEQ_SPEED)
+    # runtime still stops a size based job early
+    if runtime is not None:
+        seconds = min(seconds, runtime)
+    return ramp + seconds
+
+
+class FioStatusParser:
+    """Split the stream of JSON objects fio prints with --status-interval.
+
+    Objects may arrive cut across reads, and fio sometimes prints plain
+    text notes before the JSON, which are collected in preamble.
+    """
+
+    def __init__(self):
+        self.buffer = ''
+        self.preamble = ''
+        # Text of the last object, as fio printed it
+        self.last_text = ''
+        self.decoder = json.JSONDecoder()
+        self.utf8 = codecs.getincrementaldecoder('utf-8')(errors='replace')
+
+    def feed(self, data):
+        """Add bytes read from fio and return the objects completed by them."""
+        self.buffer += self.utf8.decode(data)
+        objects = []
+        while True:
+            start = self.buffer.find('{')
+            if start == -1:
+                self.preamble += self.buffer
+                self.buffer = ''
+                break
+            self.preamble += self.buffer[:start]
+            self.buffer = self.buffer[start:]
+            try:
+                obj, end = self.decoder.raw_decode(self.buffer)
+            except json.JSONDecodeError:
+                # Most likely the object isn't complete yet
+                break
+            objects.append(obj)
+            self.last_text = self.buffer[:end]
+            self.buffer = self.buffer[end:]
+        return objects
+
+    def finish(self):
+        """Parse what is left once fio exited, skipping stray braces."""
+        self.buffer += self.utf8.decode(b'', final=True)
+        objects = []
+        while self.buffer.strip():
+            objects.extend(self.feed(b''))
+            if not self.buffer:
+                break
+            # The buffer can't be parsed even though fio is done
+            next_start = self.buffer.find('{', 1)
+            if next_start == -1:
+                raise json.JSONDecodeError("Unterminated JSON object", self.buffer, 0)
+            self.preamble += self.buffer[:next_start]
+            self.buffer = self.buffer[next_start:]
+        return objects
+
+
+def read_pipe(pipe, chunks):
+    """Forward everything read from a pipe into a queue, then None, and close the pipe."""
+    with pipe:
+        for chunk in iter(lambda: pipe.read1(65536), b''):
+            chunks.put(chunk)
+    chunks.put(None)
+
+
+def cooldown(seconds, iteration, total, running=None):
+    """Wait between jobs, counting down on the progress bar."""
+    for remaining in range(seconds, 0, -1):
+        if running and running['cancelled']:
+            return
+        progress_bar(iteration, total, f"{f'Cooling down {remaining}s...':<32}")
+        time.sleep(1)
+
+
+@contextlib.contextmanager
+def handle_interrupts(running):
+    """Turn the first Ctrl+C into a request to stop, the second quits at once.
+
+    running['cancelled'] is set so the caller can stop between jobs, and
+    running['process'], the fio process if any, is terminated.
+    """
+    def signal_handler(sig, frame):
+        if running['cancelled']:
+            os._exit(EXIT_INTERRUPTED)
+        running['cancelled'] = True
+        print("\nCancelling test, press Ctrl+C again to quit immediately...", file=sys.stderr)
+        if running.get('process') is not None:
+            running['process'].terminate()
+
+    original_handler = signal.signal(signal.SIGINT, signal_handler)
+    try:
+        yield
+    finally:
+        signal.signal(signal.SIGINT, original_handler)
+
+
+def read_fio_bw_logs(prefix):
+    """Read the per second bandwidth logs fio wrote for a job, in MB/s per direction.
+
+    fio writes one log per thread (<prefix>_bw.1.log, ...), their values are
+    summed per second.
+    """
+    seconds = {}
+    for path in glob.glob(f"{glob.escape(prefix)}_bw.*.log"):
+        with open(path) as f:
+            for line in f:
+                fields = [field.strip() for field in line.split(',')]
+                if len(fields) < 3 or not fields[0].isdigit():
+                    continue
+                direction = FIO_LOG_DIRECTIONS.get(int(fields[2]))
+                if direction is None:
+                    continue
+                # With log_avg_msec=1000 every line stands for the second before it
+                second = max(round(int(fields[0]) / 1000) - 1, 0)
+                per_second = seconds.setdefault(direction, {})
+                per_second[second] = per_second.get(second, 0) + int(fields[1])
+    # fio logs KiB/s
+    return {direction: [round(per_second[second] * 1024 / 1024**2, 2)
+                        for second in sorted(per_second)]
+            for direction, per_second in seconds.items()}
+
+
+def remove_fio_bw_logs(prefix):
+    """Delete the bandwidth logs fio wrote for a job."""
+    for path in glob.glob(f"{glob.escape(prefix)}_bw.*.log"):
+        with contextlib.suppress(OSError):
+            os.remove(path)
+
+
+def run_fio_job(test_path, config, job, job_file_dir, running, *, timeout=None,
+                progress_prefix='', artifact=None, remote=None):
+    """Run a single job of the config with fio and return its JSON output.
+
+    fio reads the job file from stdin, so nothing is written to the working
+    directory. With job_file_dir the job file is written and kept there.
+    With artifact, a path without extension, the job file, fio's final JSON
+    and its stderr are also saved there as .fio, .json and .stderr files,
+    and the per second bandwidth logs as _bw.<thread>.log files. The
+    bandwidth of every second is added to the jobs of the output as bw_log.
+    """
+    job_name = re.sub(r'[^\w.-]', '_', job)
+    job_config = make_fio_job_config(config, job)
+    bw_log = None
+    if not remote:
+        # A remote fio writes its logs on the remote host
+        bw_log = artifact or os.path.join(tempfile.gettempdir(),
+                                          f"pdm-{os.getpid()}-{job_name}")
+        job_config.set(job, 'write_bw_log', bw_log)
+        job_config.set(job, 'log_avg_msec', '1000')
+    job_content = write_fio_config(job_config)
+    job_file = None
+    # The fio client sends the job file to the server itself and can't take it from stdin
+    if job_file_dir or remote:
+        # Per-process names so concurrent runs don't clobber each other
+        with tempfile.NamedTemporaryFile('w', prefix=f'pdm-{os.getpid()}-{job_name}-',
+                                         suffix='.fio', dir=job_file_dir,
+                                         delete=False) as f:
+            f.write(job_content)
+            job_file = f.name
+    if artifact:
+        save_artifact(f"{artifact}.fio", job_content)
+
+    cmd = fio_job_command(job_file or '-', remote)
+    duration = get_job_duration(config, job)
+
+    try:
+        # Run subprocess with appropriate handling
+        try:
+            running['process'] = command_runner.start(
+                cmd, stdin=None if job_file else job_content, env=fio_command['env'])
+        except FileNotFoundError:
+            raise PdmError("fio is not installed or not available in PATH.",
+                           EXIT_MISSING_BACKEND)
+        process = running['process']
+
+        # Read both pipes in threads so neither can fill up and block fio
+        chunks = queue.Queue()
+        stderr_chunks = queue.Queue()
+        for pipe, target in ((process.stdout, chunks), (process.stderr, stderr_chunks)):
+            threading.Thread(target=read_pipe, args=(pipe, target), daemon=True).start()
+
+        parser = FioStatusParser()
+        fio_output = None
+        last_status = (0, 0)
+        deadline = time.monotonic() + timeout if timeout else None
+        try:
+            while True:
+                if deadline and time.monotonic() > deadline:
+                    process.kill()
+                    process.wait()
+                    raise JobTimeoutError(
+                        f"{job} did not finish within {timeout} seconds and was killed")
+                try:
+                    chunk = chunks.get(timeout=0.5)
+                except queue.Empty:
+                    continue
+                if chunk is None:
+                    break
+                for fio_output in parser.feed(chunk):
+                    last_status = show_fio_status(fio_output, last_status, duration,
+                                                  progress_prefix)
+            process.wait()
+            remaining = parser.finish()
+        except json.JSONDecodeError as e:
+            raise PdmError(f"Could not parse fio output of {job}: {e}", EXIT_PARSE_ERROR)
+        finally:
+            running['process'] = None
+        if remaining:
+            fio_output = remaining[-1]
+        terse = fio_command['format'] == 'terse'
+        if terse:
+            # Nothing in terse output looks like JSON, so all of it is in the preamble
+            if process.returncode == 0 or re.search(r'^\d+;', parser.preamble, re.M):
+                fio_output = parse_fio_terse(parser.preamble, job_config, job)
+        elif parser.preamble.strip():
+            log.debug(f"fio {job} printed before its JSON: {parser.preamble.strip()}")
+
+        if running.get('cancelled'):
+            # Stopped by Ctrl+C, the caller throws the job away
+            return {}
+
+        stderr = b''.join(iter(stderr_chunks.get, None)).decode(errors='replace')
+        if process.returncode != 0 and fio_output is None and not terse and \
+                FIO_NO_JSON.search(stderr):
+            log.warning(f"{fio_command['path']} can't write JSON output, "
+                        "falling back to its terse output")
+            fio_command['format'] = 'terse'
+            return run_fio_job(test_path, config, job, job_file_dir, running, timeout=timeout,
+                               progress_prefix=progress_prefix, artifact=artifact,
+                               remote=remote)
+        if artifact:
+            if terse and parser.preamble.strip():
+                save_artifact(f"{artifact}.terse", parser.preamble)
+            if parser.last_text:
+                save_artifact(f"{artifact}.json", parser.last_text + "\n")
+            if stderr:
+                save_artifact(f"{artifact}.stderr", stderr)
+        stderr_tail = "\n".join(stderr.strip().splitlines()[-STDERR_TAIL_LINES:])
+        if process.returncode != 0:
+            log.debug(f"Job file of {job}:\n{job_content}")
+            # fio still reports the job if it failed after starting
+            if fio_output is None:
+                raise PdmError(f"fio exited with code {process.returncode} running "
+                               f"{job} on '{test_path}'{f' at {remote}' if remote else ''}:"
+                               f"\n{stderr_tail}", EXIT_IO_ERROR)
+            log.error(f"fio job {job} failed:\n{stderr_tail}")
+        elif stderr_tail:
+            for line in stderr_tail.splitlines():
+                log.warning(f"fio {job}: {line}")
+
+        if fio_output is None:
+            raise PdmError(f"fio printed no results for {job}", EXIT_PARSE_ERROR)
+        if 'client_stats' in fio_output:
+            # Client mode nests the jobs per host and adds their sum as "All clients"
+            fio_output['jobs'] = [entry for entry in fio_output.pop('client_stats')
+                                  if entry.get('jobname') != 'All clients']
+        if bw_log:
+            try:
+                series = read_fio_bw_logs(bw_log)
+            except (OSError, ValueError) as e:
+                log.warning(f"Could not read the bandwidth log of {job}: {e}")
+                series = {}
+            for entry in fio_output.get('jobs', []):
+                entry['bw_log'] = series
+        # The last object fio prints is the final report of the job
+        return fio_output
+
+    finally:
+        if job_file_dir:
+            log.info(f"Kept fio job file: {job_file}")
+        elif job_file:
+            os.remove(job_file)
+        if bw_log and not artifact:
+            remove_fio_bw_logs(bw_log)
+
+
+def fio_job_command(job_file, remote=None):
+    """Build the fio command line running a job file, '-' for stdin.
+
+    The --fio-arg arguments come last, after our own.
+    """
+    if fio_command['format'] == 'terse':
+        # Without progress reports, a --status-interval would mix into the results
+        cmd = [fio_command['path'], job_file, '--minimal']
+        if remote:
+            cmd.append(fio_client_arg(remote))
+        return cmd + fio_command['args']
+    cmd = [
+        fio_command['path'],
+        job_file,
+        '--output-format=json',
+    ]
+    if remote:
+        cmd.append(fio_client_arg(remote))
+    else:
+        cmd.append('--status-interval=1')
+    return cmd + fio_command['args']
+
+
+def fio_client_arg(remote):
+    """Build the --client argument for a host[:port]."""
+    # fio takes the port after a comma, the client sends the job file over
+    host, _, port = remote.rpartition(':')
+    return f"--client={host},{port}" if host and port.isdigit() and ':' not in host \
+        else f"--client={remote}"
+
+
+def save_artifact(path, content):
+    """Write one file of the --artifacts directory, warning if that fails."""
+    try:
+        with open(path, 'w', encoding='utf-8') as f:
+            f.write(content)
+    except OSError as e:
+        # Losing an artifact shouldn't fail the run
+        log.warning(f"Could not save {path}: {e.strerror}")
+
+
+def show_fio_status(status, last_status, duration, prefix):
+    """Show the progress of a running job from one of fio's status reports.
+
+    Returns the (elapsed, io_bytes) to compare the next report against.
+    """
+    jobs = status.get('jobs', [])
+    if not jobs:
+        return last_status
+    elapsed = max(job.get('elapsed', 0) for job in jobs)
+    io_bytes = sum(job.get(direction, {}).get('io_bytes', 0)
+                   for job in jobs for direction in ('read', 'write'))
+    last_elapsed, last_io_bytes = last_status
+    suffix = ''
+    if elapsed > last_elapsed:
+        speed = (io_bytes - last_io_bytes) / (elapsed - last_elapsed)
+        suffix = f" {make_humanreadable_speed(speed)} MB/s"
+    # Stay below 100% so the bar isn't finished before the job is
+    fraction = min(elapsed / duration, 0.99) if duration else 0
+    progress_bar(fraction, 1, prefix, suffix=f"{suffix:<16}")
+    return (elapsed, io_bytes)
+
+
+def run_fio_test(test_path, config, *, job_file_dir=None, keep_data=False,
+                 cooldown_time=0, continue_on_error=False, timeout=None, repeat=1,
+                 artifacts=None, remote=None, drop_caches=False, samplers=(),
+                 thermal_limit=None, state_path=None, resume=None, hooks=None,
+                 idle_probe=None, wait_idle=None, retries=0, shred=None):
+    """Run every job of the config one after another with fio.
+
+    Returns the merged fio output of each repetition of the whole suite.
+    The options are keyword only, there are too many to pass them in order.
+    With artifacts, the files of every job are kept in that directory as
+    job-<section>.fio/.json, numbered per repetition when repeating.
+    With remote, the jobs run on the fio server at that host[:port].
+    With drop_caches, the page cache is dropped before every read job.
+    The jobs are marked on the samplers, and with thermal_limit each job
+    waits for the disk to cool down to it.
+    With state_path, the outputs so far are saved there after every job
+    until the run finishes, and resume, a state loaded from such a file,
+    skips the jobs it already holds. hooks are run before and after every
+    job, see run_hook(). With wait_idle, every job waits until idle_probe
+    saw that many seconds without other I/O, see wait_for_idle(). A failing
+    job is tried again up to retries times, see retry_fio_job(). With
+    shred, a dict, the data files are overwritten with zeros before they
+    are deleted and shred gets how, see shred_files().
+    """
+    jobs = get_fio_jobs(config)
+    total = len(jobs) * repeat
+    completed = resume['completed'] if resume else 0
+
+    # Only files fio creates inside a target directory are ours to delete
+    data_files = get_fio_data_files(config) if os.path.isdir(test_path) and not remote else []
+
+    test_size = get_test_size(config)
+    size_name = f" ({format_size(test_size)})" if test_size else ""
+
+    # The fio process of the job that is currently running
+    running = {'process': None, 'cancelled': False}
+
+    fio_outputs = list(resume['outputs']) if resume else []
+    try:
+        with handle_interrupts(running):
+            for r in range(repeat):
+                if r < len(fio_outputs):
+                    fio_output = fio_outputs[r]
+                else:
+                    fio_output = {'jobs': [], 'caches_dropped': drop_caches}
+                    fio_outputs.append(fio_output)
+                for j, job in enumerate(jobs):
+                    if running['cancelled']:
+                        break
+                    i = r * len(jobs) + j
+                    if i < completed:
+                        continue
+                    progress_bar(i, total, f"{job + size_name:<32}")
+                    progress_step(i + 1, total, job)
+                    if drop_caches and not fio_job_writes(config, job) and \
+                            not drop_page_caches():
+                        fio_output['caches_dropped'] = False
+                    artifact = None
+                    if artifacts:
+                        artifact = os.path.join(
+                            artifacts, f"job-{re.sub(r'[^\w.-]', '_', job)}"
+                            f"{f'-{r + 1}' if repeat > 1 else ''}")
+                    if not wait_for_idle(idle_probe, wait_idle, i, total, running):
+                        fio_output.setdefault('idle_timeouts', []).append(job)
+                    if running['cancelled']:
+                        break
+                    run_hook(hooks, 'pre', job, test_path)
+                    started = time.monotonic()
+                    try:
+                        job_output = retry_fio_job(
+                            functools.partial(run_fio_job, test_path, config, job, job_file_dir,
+                                              running, timeout=timeout,
+                                              progress_prefix=f"{f'[{i + 1}/{total}] {job}':<32}",
+                                              artifact=artifact, remote=remote),
+                            job, retries, cooldown_time, i, total, running)
+                    except JobTimeoutError as e:
+                        # A hung job shouldn't cost the rest of the run
+                        log.warning(str(e))
+                        fio_output['jobs'].append({'jobname': job, 'error': 'timeout'})
+                        job_output = {}
+                    except PdmError as e:
+                        if running['cancelled']:
+                            break
+                        if not continue_on_error or e.exit_code == EXIT_MISSING_BACKEND:
+                            raise
+                        log.warning(f"Skipping {job}: {e}")
+                        fio_output['jobs'].append({'jobname': job, 'error': e.exit_code,
+                                                   'retries': retries})
+                        job_output = {}
+                    if running['cancelled']:
+                        # The interrupted job didn't finish, its numbers mean nothing
+                        break
+                    for sampler in samplers:
+                        sampler.mark(job, started, time.monotonic())
+
+                    # Keep the version and global options of the first job that ran
+                    for key, value in job_output.items():
+                        if key != 'jobs':
+                            fio_output.setdefault(key, value)
+                    fio_output['jobs'].extend(job_output.get('jobs', []))
+                    job_results = parse_fio_results(job_output) if job_output.get('jobs') else []
+                    announce_results(job_results)
+                    completed = i + 1
+                    if state_path:
+                        save_run_state(state_path, config, repeat, completed, fio_outputs)
+                    run_hook(hooks, 'post', job, test_path, job_results)
+
+                    # No point in cooling down after the last job
+                    if i < total - 1:
+                        cooldown(cooldown_time, i + 1, total, running)
+                        wait_for_temperature(samplers, thermal_limit, i + 1, total, running)
+                if running['cancelled']:
+                    break
+        if running['cancelled']:
+            fio_outputs[-1]['partial'] = True
+            resume_hint(state_path, completed)
+        else:
+            progress_bar(total, total, f"{'FIO Progress' + size_name:<32}")
+            if state_path and os.path.exists(state_path):
+                os.remove(state_path)
+    except PdmError:
+        resume_hint(state_path, completed)
+        raise
+    finally:
+        if keep_data:
+            kept = [path for path in data_files if os.path.exists(path)]
+            if kept:
+                total = sum(os.path.getsize(path) for path in kept)
+                print(f"\nKept {format_size(total)} of fio data files:")
+                for path in kept:
+                    print(f"  {path}")
+        else:
+            shredded = remove_fio_data_files(data_files, shred is not None)
+            if shredded:
+                shred.update(shredded)
+
+    return fio_outputs
+
+
+def retry_fio_job(attempt, job, retries, cooldown_time, iteration, total, running):
+    """Run a job with attempt() and try again after a cooldown when it fails.
+
+    A job fails when fio exits with an error or its output can't be read, or
+    when fio reports an error for it. After retries more attempts the last
+    error is raised, or the failed output returned. The attempts it took are
+    recorded as retries in the job entries of the output.
+    """
+    for retry in range(retries + 1):
+        try:
+            job_output = attempt()
+        except PdmError as e:
+            if retry == retries or running['cancelled'] or \
+                    isinstance(e, JobTimeoutError) or e.exit_code == EXIT_MISSING_BACKEND:
+                raise
+            reason = str(e)
+        else:
+            failed = next((entry for entry in job_output.get('jobs', [])
+                           if entry.get('error')), None)
+            if failed is None or retry == retries or running['cancelled']:
+                for entry in job_output.get('jobs', []):
+                    entry['retries'] = retry
+                return job_output
+            reason = f"fio reported error {failed['error']}"
+        # The message of a failed fio ends with the last lines of its stderr
+        log.warning(f"{job} failed, retrying ({retry + 1} of {retries}): {reason}")
+        cooldown(cooldown_time, iteration, total, running)
+        if running['cancelled']:
+            return {}
+
+
+def fio_config_hash(config, repeat):
+    """Hash what decides the jobs of a run, to tell whether a state file fits it."""
+    return hash_data([write_fio_config(config), repeat])
+
+
+def save_run_state(path, config, repeat, completed, fio_outputs):
+    """Record the jobs that finished so far, for --resume."""
+    state = {
+        'config_hash': fio_config_hash(config, repeat),
+        'jobs': get_fio_jobs(config),
+        'repeat': repeat,
+        'completed': completed,
+        'outputs': fio_outputs,
+    }
+    try:
+        write_file_atomic(path, json.dumps(state) + "\n")
+    except OSError as e:
+        # Losing the state only costs the ability to resume
+        log.warning(f"Could not save the run state to '{path}': {e}")
+
+
+def load_run_state(path, config, repeat):
+    """Load a state file written by an interrupted run of the same config."""
+    try:
+        with open(path) as f:
+            state = json.load(f)
+    except OSError as e:
+        raise PdmError(f"Could not read the state file '{path}': {e.strerror}", EXIT_BAD_ARGS)
+    except json.JSONDecodeError as e:
+        raise PdmError(f"Could not parse the state file '{path}': {e}", EXIT_PARSE_ERROR)
+    if not isinstance(state, dict) or not {'config_hash', 'completed', 'outputs'} <= state.keys():
+        raise PdmError(f"'{path}' is not a PyDiskMark state file", EXIT_PARSE_ERROR)
+    config_hash = fio_config_hash(config, repeat)
+    if state['config_hash'] != config_hash:
+        raise PdmError(f"Can't resume from '{path}', it was written for a different job "
+                       f"configuration (hash {state['config_hash']}, now {config_hash}). "
+                       "Resume with the same target and options, or start over without "
+                       "--resume.", EXIT_BAD_ARGS)
+    return state
+
+
+def resume_hint(state_path, completed):
+    """Tell how to pick up a run that stopped after some jobs were saved."""
+    if state_path and completed and os.path.exists(state_path):
+        print(f"\n{completed} finished job{'s' if completed > 1 else ''} saved, run the same "
+              f"command with --resume {state_path} to continue.", file=sys.stderr)
+
+
+def interval_arg(text):
+    """Parse an interval like 90, 30m or 2h into seconds for argparse."""
+    seconds = parse_seconds(text)
+    if not seconds:
+        raise argparse.ArgumentTypeError(f"invalid interval '{text}', use e.g. 90, 30m or 2h")
+    return seconds
+
+
+def hook_commands(args):
+    """Collect the hook options for run_hook()."""
+    return {'pre': args.pre_hook, 'post': args.post_hook, 'policy': args.hook_policy}
+
+
+def job_file_dir(args):
+    """Get where the fio job files are kept, or None to pass them on stdin."""
+    if not args.job_file_dir:
+        return tempfile.gettempdir() if args.keep_job_files else None
+    try:
+        os.makedirs(args.job_file_dir, exist_ok=True)
+    except OSError as e:
+        raise PdmError(f"Could not create --job-file-dir '{args.job_file_dir}': {e.strerror}",
+                       EXIT_IO_ERROR)
+    return args.job_file_dir
+
+
+def run_hook(hooks, phase, job, target, results=None):
+    """Run the --pre-hook or --post-hook command around a job, if one is set.
+
+    hooks holds the 'pre' and 'post' commands and the 'policy', abort or
+    warn. The post hook finds the results of the job in the JSON file that
+    PYDISKMARK_RESULT_JSON points at.
+    """
+    command = (hooks or {}).get(phase)
+    if not command:
+        return
+    env = {'PYDISKMARK_JOB': job, 'PYDISKMARK_TARGET': target, 'PYDISKMARK_PHASE': phase}
+    result_path = None
+    try:
+        if results is not None:
+            fd, result_path = tempfile.mkstemp(prefix='pdm-result-', suffix='.json')
+            with os.fdopen(fd, 'w') as f:
+                json.dump({'job': job, 'results': results}, f, indent=4)
+            env['PYDISKMARK_RESULT_JSON'] = result_path
+        # The output goes to the log so it doesn't end up in the progress bar
+        process = run_command(CommandSpec(command, shell=True, env=env))
+        for line in (process.stdout + process.stderr).splitlines():
+            log.debug(f"{phase} hook: {line}")
+        error = f"exited with code {process.returncode}" if process.returncode else None
+    except OSError as e:
+        error = str(e)
+    finally:
+        if result_path:
+            os.remove(result_path)
+    if error:
+        message = f"The {phase} hook of {job} failed: {error}"
+        if hooks['policy'] == 'abort':
+            raise PdmError(message, EXIT_HOOK_FAILED)
+        log.warning(message)
+
+
+def make_humanreadable_speed(speed_bytes):
+    """Convert speed in bytes to a human-readable format (MB)."""
+    return f"{speed_bytes / (1024**2):.2f}"
+
+
+def make_humanreadable_time(time_ns):
+    """Convert time in nanoseconds to a human-readable format (us)."""
+    return f"{time_ns / 1000:.2f}"
+
+
+def group_fio_jobs(jobs):
+    """Group the per-thread entries fio reports for numjobs > 1 by job."""
+    groups = {}
+    for job in jobs:
+        key = (job['jobname'], job.get('groupid'))
+        groups.setdefault(key, []).append(job)
+    return list(groups.values())
+
+
+def merge_fio_direction(threads, direction):
+    """Sum bandwidth and IOPS of the threads of a job, weighting latency by IOPS."""
+    stats = [thread[direction] for thread in threads if direction in thread]
+    iops = sum(stat['iops'] for stat in stats)
+    weights = [stat['iops'] for stat in stats] if iops else [1] * len(stats)
+    latency = sum(stat['lat_ns']['mean'] * weight
+                  for stat, weight in zip(stats, weights)) / sum(weights)
+    # Percentiles can't be combined exactly, the slowest thread is the honest bound
+    percentiles = {}
+    for p in LATENCY_PERCENTILES:
+        values = [value for value in (fio_percentile(stat, p) for stat in stats)
+                  if value is not None]
+        percentiles[f"p{p:g}"] = max(values) / 1000 if values else None
+    maximums = [stat.get('clat_ns', stat['lat_ns']).get('max') for stat in stats]
+    maximums = [value for value in maximums if value is not None]
+    return {
+        'io_bytes': sum(stat.get('io_bytes', 0) for stat in stats),
+        'bw_bytes': sum(stat['bw_bytes'] for stat in stats),
+        'iops': iops,
+        'lat_ns': {'mean': latency},
+        'percentiles_us': percentiles,
+        'max_us': max(maximums) / 1000 if maximums else None,
+    }
+
+
+def fio_percentile(stat, percentile):
+    """Look up a latency percentile in ns, None if fio didn't report it."""
+    # fio keys the percentiles with strings like "99.900000"
+    for key in ('clat_ns', 'lat_ns'):
+        value = stat.get(key, {}).get('percentile', {}).get(f"{percentile:.6f}")
+        if value is not None:
+            return value
+    return None
+
+
+def parse_fio_sync(threads):
+    """Get the fsync statistics of a job with fsync set, None if it didn't sync."""
+    stats = [thread['sync'] for thread in threads if thread.get('sync', {}).get('total_ios')]
+    if not stats:
+        return None
+    syncs = sum(stat['total_ios'] for stat in stats)
+    mean = sum(stat['lat_ns']['mean'] * stat['total_ios'] for stat in stats) / syncs
+    p99 = [stat['lat_ns'].get('percentile', {}).get(f"{99:.6f}") for stat in stats]
+    p99 = [value for value in p99 if value is not None]
+    # The runtime of the writes is the time the syncs were spread over
+    seconds = max(thread.get('write', {}).get('runtime', 0) for thread in threads) / 1000
+    return fsync_stats(mean / 1000, max(p99) / 1000 if p99 else None,
+                       max(stat['lat_ns'].get('max', 0) for stat in stats) / 1000,
+                       syncs, seconds or max(thread.get('elapsed', 0) for thread in threads))
+
+
+def parse_fio_steady_state(threads):
+    """Get the peak and settled bandwidth of a job run with --steady-state, or None."""
+    thread = next((thread for thread in threads if 'steadystate' in thread), None)
+    if thread is None:
+        return None
+    steadystate = thread['steadystate']
+    write = thread.get('write', {})
+    return {
+        'attained': bool(steadystate.get('attained')),
+        'criterion': steadystate.get('ss'),
+        # fio samples the bandwidth every 500 ms, the fastest sample is the peak
+        'peak_mbs': make_humanreadable_speed(write.get('bw_max', 0) * 1024),
+        'steady_mbs': make_humanreadable_speed(steadystate.get('data', {}).get('bw_mean', 0)),
+        'seconds': write.get('runtime', 0) / 1000 or thread.get('elapsed'),
+    }
+
+
+def parse_fio_terse(text, job_config, job):
+    """Turn fio's terse output of a job into the structure of its JSON output.
+
+    Every thread prints a line of FIO_TERSE_FIELDS separated by ';'. The
+    options, which terse output leaves out, come from job_config.
+    """
+    lines = [line for line in text.splitlines() if re.match(r'\d+;', line)]
+    if not lines:
+        raise PdmError(f"fio printed no terse results for {job}", EXIT_PARSE_ERROR)
+    entries = []
+    version = None
+    for line in lines:
+        fields = line.split(';')
+        if fields[0] != '3':
+            raise PdmError(f"fio printed terse output version {fields[0]} for {job}, "
+                           f"only version 3 can be read", EXIT_PARSE_ERROR)
+        if len(fields) < len(FIO_TERSE_FIELDS):
+            raise PdmError(f"Terse output of {job} has {len(fields)} fields, field "
+                           f"{len(fields) + 1} ({FIO_TERSE_FIELDS[len(fields)]}) is missing",
+                           EXIT_PARSE_ERROR)
+
+        def number(name):
+            index = FIO_TERSE_FIELDS.index(name)
+            try:
+                return float(fields[index].rstrip('%'))
+            except ValueError:
+                raise PdmError(f"Field {index + 1} ({name}) of the terse output of {job} "
+                               f"isn't a number: {fields[index]!r}", EXIT_PARSE_ERROR)
+
+        version = fields[1]
+        entry = {'jobname': fields[2], 'groupid': int(number('groupid')),
+                 'error': int(number('error')),
+                 'job options': dict(job_config.items(job, raw=True))}
+        for direction in ('read', 'write', 'trim'):
+            def stat(name):
+                return number(f"{direction}_{name}")
+
+            percentiles = {}
+            for n in range(1, 21):
+                index = FIO_TERSE_FIELDS.index(f"{direction}_clat_percentile_{n}")
+                percent, has_value, value = fields[index].partition('%=')
+                try:
+                    if has_value and float(percent) > 0:
+                        percentiles[f"{float(percent):.6f}"] = float(value) * 1000
+                except ValueError:
+                    has_value = False
+                if not has_value:
+                    raise PdmError(f"Field {index + 1} ({FIO_TERSE_FIELDS[index]}) of the terse "
+                                   f"output of {job} isn't a percentile: {fields[index]!r}",
+                                   EXIT_PARSE_ERROR)
+            entry[direction] = {
+                'io_bytes': int(stat('io_kib') * 1024),
+                'bw_bytes': int(stat('bw_kib') * 1024),
+                'iops': stat('iops'),
+                'runtime': int(stat('runtime_ms')),
+                'lat_ns': {'mean': stat('lat_mean') * 1000, 'max': stat('lat_max') * 1000},
+                'clat_ns': {'mean': stat('clat_mean') * 1000, 'max': stat('clat_max') * 1000,
+                            'percentile': percentiles},
+                'bw_max': stat('bw_max'),
+                'bw_mean': stat('bw_mean'),
+            }
+        entries.append(entry)
+    global_options = dict(job_config.items('global', raw=True)) \
+        if job_config.has_section('global') else {}
+    return {'fio version': version, 'global options': global_options, 'jobs': entries}
+
+
+def parse_fio_results(job_results):
+    # we need to get all jobs names, speed, iops, and latencies
+    if 'jobs' not in job_results:
+        print("No jobs found in the fio results.")
+        return []
+    global_options = job_results.get('global options', {})
+    parsed_results = []
+    for threads in group_fio_jobs(job_results['jobs']):
+        job_name = threads[0]['jobname']
+        job_options = {**global_options, **threads[0].get('job options', {})}
+
+        # Failed attempts before the one reported, see retry_fio_job()
+        retries = max(thread.get('retries', 0) for thread in threads)
+
+        # A failed job is still reported so the rest of the run isn't lost
+        error = next((thread['error'] for thread in threads
+                      if thread.get('error', 0) != 0), 0)
+        if error:
+            parsed_results.append({
+                'name': job_name,
+                'direction': job_direction(job_name),
+                'error': error,
+                **({'retries': retries} if retries else {}),
+            })
+            continue
+
+        # Only report the directions the job actually did I/O in
+        for direction in ('read', 'write', 'trim'):
+            if not any(thread.get(direction, {}).get('io_bytes', 0) for thread in threads):
+                continue
+            stats = merge_fio_direction(threads, direction)
+            job_speed = make_humanreadable_speed(stats['bw_bytes'])
+            job_iops = stats['iops']
+            job_lat = make_humanreadable_time(stats['lat_ns']['mean'])
+
+            series = next((thread['bw_log'][direction] for thread in threads
+                           if direction in thread.get('bw_log', {})), None)
+            parsed_results.append({
+                'name': job_name,
+                'direction': direction,
+                'speed_mbs': job_speed,
+                'iops': job_iops,
+                'latency_us': job_lat,
+                'threads': len(threads),
+                'clat_percentiles_us': stats['percentiles_us'],
+                'latency_max_us': stats['max_us'],
+                **({'bw_series_mbs': series} if series else {}),
+                **({'retries': retries} if retries else {}),
+                'block_size': job_options['bs'].upper() if job_options.get('bs') else None,
+                'iodepth': job_options.get('iodepth', '1')
+            })
+            fsync = parse_fio_sync(threads) if direction == 'write' else None
+            if fsync:
+                parsed_results[-1]['fsync'] = fsync
+            steady_state = parse_fio_steady_state(threads) if direction == 'write' else None
+            if steady_state:
+                parsed_results[-1]['steady_state'] = steady_state
+    return parsed_results
+
+
+def aggregate_repetitions(repetitions):
+    """Combine the parsed results of repeated runs into one entry per test.
+
+    Each entry gets the mean as its speed, IOPS and latency plus the
+    standard deviation, min, max and per-repetition values.
+    """
+    if len(repetitions) == 1:
+        return repetitions[0]
+    grouped = {}
+    for tests in repetitions:
+        for test in tests:
+            grouped.setdefault((test['name'], test['direction']), []).append(test)
+
+    results = []
+    for runs in grouped.values():
+        passed = [run for run in runs if 'error' not in run]
+        if not passed:
+            results.append(runs[0])
+            continue
+        speeds = [float(run['speed_mbs']) for run in passed]
+        iops = [run['iops'] for run in passed]
+        latencies = [float(run['latency_us']) for run in passed]
+        result = dict(passed[0])
+        result.update({
+            'speed_mbs': f"{statistics.mean(speeds):.2f}",
+            'iops': statistics.mean(iops),
+            'latency_us': f"{statistics.mean(latencies):.2f}",
+            'stdev_mbs': statistics.stdev(speeds) if len(speeds) > 1 else 0.0,
+            'min_mbs': min(speeds),
+            'max_mbs': max(speeds),
+            'stdev_iops': statistics.stdev(iops) if len(iops) > 1 else 0.0,
+            'min_iops': min(iops),
+            'max_iops': max(iops),
+            'repetitions': [{key: run[key] for key in ('speed_mbs', 'iops', 'latency_us', 'error')
+                             if key in run} for run in runs],
+        })
+        if 'passes' in result:
+            # The native passes of every repetition together
+            result['passes'] = native_pass_stats(
+                [speed for run in passed for speed in run['passes']['speeds_mbs']],
+                sum(run['passes']['bytes'] for run in passed),
+                sum(run['passes']['seconds'] for run in passed))
+        results.append(result)
+    return results
+
+
+class FioBackend(Backend):
+    """Runs the benchmark with fio using a preset or user job file."""
+    name = 'fio'
+
+    def __init__(self, remote=None):
+        # With a remote the jobs run on a fio --server there
+        self.remote = remote
+
+    def is_available(self):
+        if not check_fio_available():
+            return "fio is not installed or not available in PATH."
+        return fio_too_old_reason(get_fio_version())
+
+    def install_hint(self):
+        if check_fio_available():
+            return "Upgrade fio from https://github.com/axboe/fio/releases or your package " \
+                "manager, or run with --backend native."
+        if platform.system() == 'Windows':
+            return "Install fio.exe from https://github.com/axboe/fio/releases and add it to PATH."
+        if platform.system() == 'Darwin':
+            return "Install it with: brew install fio"
+        package_managers = [
+            ('apt-get', 'sudo apt-get install fio'),
+            ('dnf', 'sudo dnf install fio'),
+            ('yum', 'sudo yum install fio'),
+            ('pacman', 'sudo pacman -S fio'),
+            ('zypper', 'sudo zypper install fio'),
+            ('apk', 'sudo apk add fio'),
+        ]
+        for tool, command in package_managers:
+            if shutil.which(tool):
+                return f"Install it with: {command}"
+        return "Install fio with your distribution's package manager."
+
+    def build_config(self, test_path, args):
+        """Load the job file and apply the command line overrides."""
+        if args.mode == 'latency':
+            config = make_latency_config()
+        else:
+            config = load_fio_config(args.config or PRESETS[args.preset][0])
+            if args.sweep:
+                make_sweep_config(config, dict(args.sweep), args.sweep_pattern)
+            if args.jobs:
+                filter_fio_jobs(config, args.jobs.split(','))
+            if args.mixed:
+                add_fio_mixed_jobs(config, args.mixed)
+            if args.fsync_test:
+                add_fio_fsync_job(config)
+            if args.trim_test and not trim_unsupported_reason(test_path, self.remote):
+                add_fio_trim_job(config)
+            filter_fio_mode(config, args.mode)
+        if args.steady_state:
+            set_fio_steady_state(config)
+        set_fio_direct(config)
+        set_fio_data_pattern(config, args.data_pattern)
+        if args.size:
+            set_test_size(config, args.size)
+        if args.duration:
+            set_fio_duration(config, args.duration)
+        set_fio_ioengine(config, args.ioengine)
+        apply_fio_overrides(config, args.set)
+        if args.regions:
+            if self.remote or not is_block_device(test_path):
+                raise PdmError(f"--regions needs a local block device as the target, a file on "
+                               f"a filesystem like '{test_path}' isn't at a known place of the "
+                               "disk", EXIT_BAD_ARGS)
+            make_region_config(config, args.regions, get_block_device_size(test_path))
+        if self.remote:
+            # The path is on the remote host, only a trailing slash tells a directory
+            is_directory = test_path.endswith(('/', '\\'))
+            set_fio_target(config, test_path, is_directory)
+            if is_directory and not args.keep_data:
+                # The data files can't be deleted from here, let fio do it
+                config.set('global', 'unlink', '1')
+        else:
+            set_fio_target(config, test_path)
+        enable_fio_percentiles(config)
+        return config
+
+    def writes(self, test_path, args):
+        config = self.build_config(test_path, args)
+        return any(fio_job_writes(config, job) for job in get_fio_jobs(config))
+
+    def estimate_runtime(self, test_path, args):
+        config = self.build_config(test_path, args)
+        jobs = [estimate_fio_job_seconds(config, job) for job in get_fio_jobs(config)]
+        runs = len(jobs) * args.repeat
+        seconds = sum(job for job in jobs if job is not None) * args.repeat
+        unknown = sum(job is None for job in jobs) * args.repeat
+        return seconds + max(runs - 1, 0) * args.cooldown, unknown
+
+    def dry_run(self, test_path, args):
+        config = self.build_config(test_path, args)
+        jobs = get_fio_jobs(config)
+        sb_string = ""
+        for key, value in fio_command['env'].items():
+            sb_string += f"# Environment: {key}={value}\n"
+        sb_string += f"# Command: {shlex.join(fio_job_command('-', self.remote))}\n\n"
+        for i, job in enumerate(jobs):
+            sb_string += f"# Job {i + 1}/{len(jobs)}: {job}\n"
+            sb_string += write_fio_config(make_fio_job_config(config, job)) + "\n"
+        return sb_string
+
+    def run(self, test_path, args, artifacts=None):
+        config = self.build_config(test_path, args)
+        if not args.ignore_free_space and not self.remote:
+            check_free_space(test_path, get_fio_required_space(config))
+        prepared = []
+        if args.prepare_file and (self.remote or not os.path.isdir(test_path)):
+            log.warning("--prepare-file only prepares data files in a local directory")
+        elif args.prepare_file:
+            prepared = prepare_fio_files(config, args.data_pattern)
+        # Prepared files are there to be reused by the next run
+        keep_data = args.keep_data or (bool(prepared) and not args.cleanup)
+        steady_job = next((job for job in get_fio_jobs(config)
+                           if config.has_option(job, 'steadystate')), None)
+        steady_reason = steady_state_unsupported_reason(get_fio_version()) if steady_job else None
+        if steady_reason:
+            # An old fio would run the job for the full half hour and not report on it
+            log.warning(f"Skipping the steady state test: {steady_reason}")
+            config.remove_section(steady_job)
+            if not get_fio_jobs(config):
+                raise PdmError(steady_reason.capitalize(), EXIT_MISSING_BACKEND)
+        elif args.steady_state and not steady_job:
+            log.warning("--steady-state found no sequential write job to run until it settles")
+        jobs = get_fio_jobs(config)
+        if TRIM_JOB in jobs:
+            log.warning(f"The trim test discards the data of {test_path} in the tested range")
+        buffered_jobs = [job for job in jobs if not fio_job_direct(config, job)]
+        if self.remote:
+            if buffered_jobs:
+                warn_buffered_io(test_path, buffered_jobs)
+            if args.drop_caches:
+                log.warning(f"--drop-caches can't reach the page cache of {self.remote}")
+        else:
+            warn_buffered_io(test_path, buffered_jobs)
+
+        resume = load_run_state(args.resume, config, args.repeat) if args.resume else None
+        # --watch runs start over anyway, there is nothing to resume
+        state_path = None
+        if not args.watch:
+            state_path = args.resume or os.path.join(
+                artifacts or 'out', f"resume-{fio_config_hash(config, args.repeat)}.json")
+            os.makedirs(os.path.dirname(state_path) or '.', exist_ok=True)
+
+        # The load and temperature of a remote host can't be sampled from here
+        samplers = [] if self.remote else start_samplers(test_path)
+        idle_probe = None
+        shred = {} if args.shred else None
+        if args.wait_idle and self.remote:
+            log.warning(f"--wait-idle can't watch the disks of {self.remote}")
+        elif args.wait_idle:
+            idle_probe = disk_activity_probe(test_path)
+        try:
+            fio_outputs = run_fio_test(
+                test_path, config, job_file_dir=job_file_dir(args), keep_data=keep_data,
+                cooldown_time=args.cooldown, continue_on_error=args.continue_on_error,
+                timeout=args.timeout, repeat=args.repeat, artifacts=artifacts,
+                remote=self.remote, drop_caches=args.drop_caches and not self.remote,
+                samplers=samplers, thermal_limit=args.thermal_limit, state_path=state_path,
+                resume=resume, hooks=hook_commands(args), idle_probe=idle_probe,
+                wait_idle=args.wait_idle, retries=args.retries, shred=shred)
+        finally:
+            stop_samplers(samplers)
+            for file in prepared:
+                if not os.path.exists(file['path']):
+                    with contextlib.suppress(OSError):
+                        os.remove(file['path'] + PREPARED_SUFFIX)
+        fio_output = fio_outputs[0]
+        global_options = fio_output.get('global options', {})
+        test_size = global_options.get('filesize', global_options.get('size'))
+        try:
+            test_size = parse_size(test_size) if test_size else None
+        except ValueError:
+            # Sizes like 50% of the target are kept the way fio reported them
+            pass
+        report = BenchmarkReport(
+            backend=self.name,
+            version=fio_output.get('fio version') or get_fio_version(),
+            target=test_path,
+            tests=aggregate_repetitions([parse_fio_results(output) for output in fio_outputs]),
+            settings={
+                'test_size': test_size,
+                'loops': global_options.get('loops', '1'),
+                'runtime': global_options.get('runtime'),
+                'engine': config.get('global', 'ioengine', fallback=None)
+                or global_options.get('ioengine'),
+                'repeat': args.repeat,
+                'overrides': args.set,
+                'remote': self.remote,
+                'direct': not buffered_jobs,
+                'caches_dropped': all(output.get('caches_dropped') for output in fio_outputs),
+                'thermal_limit': args.thermal_limit,
+                'wait_idle': args.wait_idle,
+                'data_pattern': args.data_pattern,
+                'prepared_files': prepared,
+                # How the data files were wiped with --shred
+                'shred': shred or None,
+                # Jobs that started on a busy disk after waiting too long for idle
+                'idle_timeouts': [job for output in fio_outputs
+                                  for job in output.get('idle_timeouts', [])],
+                # How many jobs came from the state file of an interrupted run
+                'resumed_jobs': resume['completed'] if resume else 0,
+                # Jobs run one after another in the order of the job file
+                'job_order': jobs,
+                'fio_binary': fio_command['path'],
+                'fio_args': fio_command['args'],
+                'fio_env': fio_command['env'],
+                'fio_output_format': fio_command['format'],
+            },
+            raw=fio_output if args.repeat == 1 else {'repetitions': fio_outputs},
+            partial=any(output.get('partial') for output in fio_outputs))
+        for sampler in samplers:
+            sampler.attach(report.tests)
+        if self.remote:
+            for test in report.tests:
+                test['host'] = self.remote
+        if args.sweep:
+            report.sweep = build_sweep_series(report.tests, list(dict(args.sweep)))
+        if args.regions:
+            report.regions = build_region_series(report.tests, config)
+        if args.trim_test and TRIM_JOB not in jobs and args.mode != 'read':
+            report.tests.append(unsupported_trim_result(
+                trim_unsupported_reason(test_path, self.remote)))
+        if steady_reason:
+            report.tests.append({'name': steady_job, 'direction': 'write',
+                                 'error': 'unsupported', 'reason': steady_reason})
+        return report
+
+
+def open_direct(path, flags):
+    """Open a file bypassing the page cache, returning (fd, is_direct)."""
+    if hasattr(os, 'O_DIRECT'):
+        try:
+            return os.open(path, flags | os.O_DIRECT), True
+        except OSError as e:
+            log.debug(f"O_DIRECT open of {path} failed: {e.strerror}")
+    fd = os.open(path, flags | getattr(os, 'O_BINARY', 0))
+    if fcntl is not None and hasattr(fcntl, 'F_NOCACHE'):
+        try:
+            fcntl.fcntl(fd, fcntl.F_NOCACHE, 1)
+            return fd, True
+        except OSError as e:
+            log.debug(f"F_NOCACHE on {path} failed: {e.strerror}")
+    return fd, False
+
+
+def pread_into(fd, buf, offset):
+    """Read len(buf) bytes at offset into buf."""
+    if hasattr(os, 'preadv'):
+        return os.preadv(fd, [buf], offset)
+    os.lseek(fd, offset, os.SEEK_SET)
+    data = os.read(fd, len(buf))
+    buf[:len(data)] = data
+    return len(data)
+
+
+def pwrite_from(fd, buf, offset):
+    """Write all of buf at offset."""
+    if hasattr(os, 'pwritev'):
+        return os.pwritev(fd, [buf], offset)
+    os.lseek(fd, offset, os.SEEK_SET)
+    return os.write(fd, buf)
+
+
+def block_label(block_size):
+    """Format a block size like the cdm8 job names do (4K, 1M)."""
+    if block_size % 1024**2 == 0:
+        return f"{block_size // 1024**2}M"
+    return f"{block_size // 1024}K"
+
+
+def run_native_pass(fd, buf, size, sequential, write, is_direct, duration=None,
+                    latencies=None, progress=None, part=(0, 1)):
+    """Do one timed pass over the file and return (bytes, ops, seconds).
+
+    With a duration the pass starts over until that many seconds went by.
+    Given a latencies list, the seconds every operation took are appended to it.
+    Every NATIVE_PROGRESS_SECONDS progress is called with (fraction done, bytes, seconds).
+    part is (index, parts) when several threads share the pass, a sequential
+    pass then covers the index-th stretch of the file and a random one does
+    its share of the operations.
+    """
+    index, parts = part
+    block_size = len(buf)
+    blocks = size // block_size
+    first, last = blocks * index // parts, blocks * (index + 1) // parts
+    count = last - first if sequential else min(size, NATIVE_RANDOM_BYTES) // block_size // parts
+
+    ops = 0
+    start = time.perf_counter()
+    deadline = start + duration if duration else None
+    next_report = start + NATIVE_PROGRESS_SECONDS
+    while True:
+        if sequential:
+            offsets = (i * block_size for i in range(first, last))
+        else:
+            offsets = (random.randrange(blocks) * block_size for _ in range(count))
+        for offset in offsets:
+            if latencies is not None:
+                issued = time.perf_counter()
+            if write:
+                pwrite_from(fd, buf, offset)
+            else:
+                pread_into(fd, buf, offset)
+            if latencies is not None:
+                latencies.append(time.perf_counter() - issued)
+            ops += 1
+            if progress and time.perf_counter() >= next_report:
+                now = time.perf_counter()
+                done = (now - start) / duration if deadline else ops / max(count, 1)
+                progress(min(done, 1.0), ops * block_size, now - start)
+                next_report = now + NATIVE_PROGRESS_SECONDS
+            if deadline and time.perf_counter() >= deadline:
+                break
+        if not deadline or time.perf_counter() >= deadline:
+            break
+    if write and not is_direct:
+        # Without direct I/O the writes only reached the page cache
+        os.fsync(fd)
+    elapsed = time.perf_counter() - start
+    return ops * block_size, ops, elapsed
+
+
+def remove_native_test_file(file_path, keep_data, quiet, shred=False):
+    """Delete the test file a native run created and tell how much space it took.
+
+    With shred it is overwritten with zeros first, returning how like shred_files().
+    """
+    used = os.path.getsize(file_path)
+    if keep_data:
+        if not quiet:
+            print(f"\nKept the {format_size(used)} test file {file_path}")
+        return None
+    shredded = shred_files([file_path]) if shred else None
+    os.remove(file_path)
+    if not quiet:
+        print(f"\nRemoved the {format_size(used)} test file {file_path}")
+    return shredded
+
+
+def run_native_threads(fd, bufs, size, sequential, write, is_direct, duration=None,
+                       latencies=None, progress=None):
+    """Run a native pass on one thread per buffer and return (bytes, ops, seconds).
+
+    The threads split the pass between them, see run_native_pass(). Python
+    lets go of the GIL while a thread waits for the disk, so as many
+    operations are in flight as there are threads. The first thread reports
+    the progress, with its bytes scaled up to all of them.
+    """
+    parts = len(bufs)
+    if parts == 1:
+        return run_native_pass(fd, bufs[0], size, sequential, write, is_direct, duration,
+                               latencies, progress)
+    results = [None] * parts
+    errors = []
+
+    def scaled(done, io_bytes, seconds):
+        progress(done, io_bytes * parts, seconds)
+
+    def worker(index):
+        try:
+            results[index] = run_native_pass(
+                fd, bufs[index], size, sequential, write, is_direct, duration, latencies,
+                scaled if progress and index == 0 else None, (index, parts))
+        except OSError as e:
+            errors.append(e)
+
+    threads = [threading.Thread(target=worker, args=(index,), daemon=True)
+               for index in range(parts)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    if errors:
+        raise errors[0]
+    # The threads ran side by side, the pass took as long as the slowest of them
+    return (sum(result[0] for result in results), sum(result[1] for result in results),
+            max(result[2] for result in results))
+
+
+def native_buffer(pattern, size):
+    """Page aligned buffer for direct I/O, filled with the --data-pattern data."""
+    buf = mmap.mmap(-1, size)
+    buf.write(pattern_bytes(pattern, size))
+    return buf
+
+
+def native_progress(step, total, prefix):
+    """Progress callback for run_native_pass that moves the bar within one step.
+
+    The bar shows the live speed of the pass after its percentage.
+    """
+    def report(done, io_bytes, seconds):
+        # Stay short of the next step, a full bar ends the progress line
+        progress_bar(step + min(done, 0.999), total, prefix,
+                     suffix=f"{' ' + make_humanreadable_speed(io_bytes / seconds) + ' MB/s':<16}")
+    return report
+
+
+def native_pass_stats(speeds, io_bytes, seconds):
+    """Summarize the MB/s of every measured native pass of a test.
+
+    speed_mbs of the test is the mean over all of them, this adds their spread.
+    """
+    return {
+        'speeds_mbs': [round(speed, 2) for speed in speeds],
+        'min_mbs': min(speeds),
+        'max_mbs': max(speeds),
+        'stdev_mbs': statistics.stdev(speeds) if len(speeds) > 1 else 0.0,
+        'bytes': io_bytes,
+        'seconds': seconds,
+    }
+
+
+def native_pass_duration(args):
+    """Seconds each native pass runs, None to cover the whole file once.
+
+    Latency runs split LATENCY_SECONDS over the measured passes unless --duration is given.
+    """
+    if args.mode == 'latency' and not args.duration:
+        return LATENCY_SECONDS / args.runs
+    return args.duration
+
+
+def sample_percentile(samples, percentile):
+    """Nearest-rank percentile of a sorted list of samples."""
+    rank = math.ceil(percentile / 100 * len(samples))
+    return samples[min(max(rank, 1), len(samples)) - 1]
+
+
+def run_native_sync_pass(fd, buf, size, duration, latencies):
+    """Write buf over and over, syncing after each write, for duration seconds.
+
+    The writes wrap around within the first size bytes of the file.
+    Returns (bytes, syncs, seconds) and appends the seconds every sync took to latencies.
+    """
+    sync = getattr(os, 'fdatasync', os.fsync)
+    blocks = size // len(buf)
+    syncs = 0
+    start = time.perf_counter()
+    while time.perf_counter() - start < duration:
+        pwrite_from(fd, buf, (syncs % blocks) * len(buf))
+        issued = time.perf_counter()
+        sync(fd)
+        latencies.append(time.perf_counter() - issued)
+        syncs += 1
+    return syncs * len(buf), syncs, time.perf_counter() - start
+
+
+def native_test_file(test_path, args):
+    """Pick the file the native backend tests at a target, as (path, created).
+
+    A directory gets a test file named after our pid, or NATIVE_FILE when the
+    data is kept for later runs. created tells that the file is made by this
+    run, so it is deleted afterwards unless --keep-data. An existing regular
+    file other than NATIVE_FILE isn't overwritten without --force.
+    """
+    read_only = args.mode == 'read'
+    if os.path.isdir(test_path):
+        name = NATIVE_FILE if args.keep_data or read_only else \
+            NATIVE_TEMP_FILE.format(pid=os.getpid())
+        file_path = os.path.join(test_path, name)
+    else:
+        file_path = test_path
+    try:
+        mode = os.stat(file_path).st_mode
+    except FileNotFoundError:
+        return file_path, True
+    if (stat.S_ISREG(mode) and not read_only and not args.force
+            and os.path.basename(file_path) != NATIVE_FILE):
+        raise PdmError(f"'{file_path}' already exists and wasn't created by PyDiskMark, "
+                       "pass --force to overwrite it", EXIT_BAD_ARGS)
+    return file_path, False
+
+
+class NativeBackend(Backend):
+    """Runs sequential and random tests directly from Python, without fio."""
+    name = 'native'
+    creates_target = True
+
+    def is_available(self):
+        return None
+
+    def writes(self, test_path, args):
+        return args.mode != 'read'
+
+    def dry_run(self, test_path, args):
+        size = args.size or NATIVE_TEST_SIZE
+        file_path, created = native_test_file(test_path, args)
+        sb_string = f"Target: {file_path}\n"
+        if created:
+            after = 'shredded and deleted' if args.shred else 'deleted'
+            sb_string += "Test file: created by the run, " \
+                f"{'kept' if args.keep_data else after} afterwards\n"
+        sb_string += f"Size: {format_size(size)}\n"
+        sb_string += f"Passes per test: {args.warmup} warmup + {args.runs} measured\n"
+        if args.duration:
+            sb_string += f"Pass duration: {args.duration}s\n"
+        if args.repeat > 1:
+            sb_string += f"Repetitions: {args.repeat} (warmup only in the first)\n"
+        sb_string += f"SEQ-R/SEQ-W: {format_size(args.block_size)} blocks over the whole file\n"
+        if args.threads > 1:
+            sb_string += f"Threads: {args.threads} per SEQ and RND test, splitting the file " \
+                "or the random operations between them\n"
+        sb_string += f"RND-R/RND-W: {format_size(NATIVE_RANDOM_BLOCK)} blocks, " \
+            f"{format_size(min(size, NATIVE_RANDOM_BYTES))} per pass\n"
+        if args.fsync_test and args.mode != 'read':
+            sb_string += f"SYNC-W: {format_size(NATIVE_RANDOM_BLOCK)} writes each followed by " \
+                f"an fsync for {args.duration or FSYNC_SECONDS}s per pass\n"
+        if args.mode == 'latency':
+            sb_string += f"Mode: latency, RND-R only for ~{LATENCY_SECONDS}s\n"
+        elif args.mode != 'readwrite':
+            sb_string += f"Mode: {args.mode} tests only\n"
+        return sb_string
+
+    def estimate_runtime(self, test_path, args):
+        size = args.size or NATIVE_TEST_SIZE
+        # Per pass for SEQ and RND of one direction
+        if args.mode == 'latency':
+            passes = [native_pass_duration(args)]
+        elif args.duration:
+            passes = [args.duration, args.duration]
+        else:
+            passes = [size / ESTIMATE_SEQ_SPEED,
+                      min(size, NATIVE_RANDOM_BYTES) / ESTIMATE_RND_SPEED]
+        directions = 2 if args.mode == 'readwrite' else 1
+        seconds = sum(passes) * directions * (args.warmup + args.runs * args.repeat)
+        if args.fsync_test and args.mode != 'read':
+            seconds += (args.duration or FSYNC_SECONDS) * (args.warmup + args.runs * args.repeat)
+        if args.mode in ('readwrite', 'latency'):
+            # Laying out the file
+            seconds += size / ESTIMATE_SEQ_SPEED
+        tests = len(passes) * directions * args.repeat
+        return seconds + max(tests - 1, 0) * args.cooldown, 0
+
+    def run(self, test_path, args, artifacts=None):
+        size = args.size or NATIVE_TEST_SIZE
+        block_size = args.block_size
+        file_path, created = native_test_file(test_path, args)
+        read_only = args.mode == 'read'
+        shredded = None
+        if not args.ignore_free_space and not read_only:
+            existing = os.path.getsize(file_path) if os.path.isfile(file_path) else 0
+            check_free_space(os.path.dirname(file_path), max(size - existing, 0))
+
+        tests = [
+            ('SEQ', 'R', block_size, True, False),
+            ('SEQ', 'W', block_size, True, True),
+            ('RND', 'R', NATIVE_RANDOM_BLOCK, False, False),
+            ('RND', 'W', NATIVE_RANDOM_BLOCK, False, True),
+        ]
+        if args.mode == 'latency':
+            tests = [test for test in tests if f"{test[0]}-{test[1]}" == 'RND-R']
+        elif args.mode != 'readwrite':
+            tests = [test for test in tests if test[4] == (args.mode == 'write')]
+        if args.fsync_test and args.mode != 'read':
+            tests.append(('SYNC', 'W', NATIVE_RANDOM_BLOCK, True, True))
+        duration = native_pass_duration(args)
+        # Later repetitions skip the warmup, the file is warm by then
+        total = len(tests) * (args.warmup + args.runs * args.repeat)
+        repetitions = []
+
+        if read_only and not os.path.exists(file_path):
+            raise PdmError(f"Nothing to read at '{file_path}', lay the file out first with "
+                           "a readwrite run using --keep-data", EXIT_BAD_ARGS)
+        try:
+            flags = os.O_RDONLY if read_only else os.O_RDWR | os.O_CREAT
+            fd, is_direct = open_direct(file_path, flags)
+        except OSError as e:
+            raise PdmError(f"Could not open '{file_path}': {e.strerror}", EXIT_IO_ERROR)
+        if read_only:
+            # Reads past the end of the file would measure nothing
+            available = os.lseek(fd, 0, os.SEEK_END)
+            if available < size:
+                os.close(fd)
+                raise PdmError(f"'{file_path}' holds {format_size(available)}, less than the "
+                               f"{format_size(size)} to read, lay it out with a readwrite run "
+                               "using --keep-data and the same --size", EXIT_BAD_ARGS)
+        if not is_direct:
+            print("WARNING: direct I/O is not available, falling back to buffered I/O. "
+                  "Results will include the OS page cache and may be far too high.",
+                  file=sys.stderr)
+
+        running = {'cancelled': False}
+        caches_dropped = args.drop_caches
+        samplers = start_samplers(test_path)
+        hooks = hook_commands(args)
+        idle_probe = disk_activity_probe(test_path) if args.wait_idle else None
+        idle_timeouts = []
+        try:
+            with handle_interrupts(running):
+                buf = native_buffer(args.data_pattern, block_size)
+                # Lay out the whole file once so reads have data to work on
+                if args.mode in ('readwrite', 'latency'):
+                    prefix = f"{f'Preparing {format_size(size)}':<32}"
+                    progress_bar(0, total, prefix)
+                    run_native_pass(fd, buf, size, True, True, is_direct,
+                                    progress=native_progress(0, total, prefix))
+
+                step = 0
+                for r in range(args.repeat):
+                    if running['cancelled']:
+                        break
+                    warmup = args.warmup if r == 0 else 0
+                    results = []
+                    repetitions.append(results)
+                    for t, (kind, rw, bs, sequential, write) in enumerate(tests):
+                        if step > 0:
+                            cooldown(args.cooldown, step, total, running)
+                            wait_for_temperature(samplers, args.thermal_limit, step, total, running)
+                        # The fsync test is about one writer waiting for each sync
+                        workers = 1 if kind == 'SYNC' else args.threads
+                        name = f"{kind}-{rw}-{block_label(bs)}-Q1-T{workers}"
+                        if args.drop_caches and not write and not drop_page_caches():
+                            caches_dropped = False
+                        test_buf = buf if bs == block_size else native_buffer(args.data_pattern, bs)
+                        # Each thread reads into a buffer of its own
+                        test_bufs = [test_buf] + [native_buffer(args.data_pattern, bs)
+                                                  for _ in range(workers - 1)]
+                        measured = []
+                        # Why each measured pass that failed with --continue-on-error did
+                        failed = []
+                        # Timing every operation costs a little, only latency runs need it
+                        latencies = [] if args.mode == 'latency' or kind == 'SYNC' else None
+                        if not wait_for_idle(idle_probe, args.wait_idle, step, total, running):
+                            idle_timeouts.append(name)
+                        run_hook(hooks, 'pre', name, test_path)
+                        started = time.monotonic()
+                        for i in range(warmup + args.runs):
+                            if running['cancelled']:
+                                break
+                            phase = 'warmup' if i < warmup else 'run'
+                            prefix = f"{f'{name} {phase} ({format_size(size)})':<32}"
+                            progress_bar(step, total, prefix)
+                            progress_step(step + 1, total, f"{name} {phase}")
+                            # Kept apart until the pass is done, a failed one doesn't count
+                            pass_latencies = [] if latencies is not None and \
+                                (i >= warmup or kind == 'SYNC') else None
+                            try:
+                                if kind == 'SYNC':
+                                    io_bytes, ops, elapsed = run_native_sync_pass(
+                                        fd, test_buf, size, args.duration or FSYNC_SECONDS,
+                                        pass_latencies)
+                                else:
+                                    io_bytes, ops, elapsed = run_native_threads(
+                                        fd, test_bufs, size, sequential, write, is_direct,
+                                        duration, pass_latencies,
+                                        native_progress(step, total, prefix))
+                            except OSError as e:
+                                step += 1
+                                number, count = (i + 1, warmup) if i < warmup else \
+                                    (i + 1 - warmup, args.runs)
+                                failure = f"{name} {phase} pass {number} of {count} failed " \
+                                    f"on '{file_path}': {e.strerror}"
+                                if not args.continue_on_error:
+                                    raise PdmError(failure, EXIT_IO_ERROR)
+                                log.warning(f"Skipping the pass, {failure}")
+                                if i >= warmup:
+                                    failed.append(e.strerror)
+                                continue
+                            if i >= warmup:
+                                measured.append((io_bytes, ops, elapsed))
+                                if latencies is not None:
+                                    latencies.extend(pass_latencies)
+                            step += 1
+                        if running['cancelled']:
+                            # The interrupted test didn't finish, its numbers mean nothing
+                            break
+                        for sampler in samplers:
+                            sampler.mark(name, started, time.monotonic())
+                        if failed:
+                            outcome = f"its results come from the other {len(measured)}" \
+                                if measured else "it has no results"
+                            log.warning(f"{len(failed)} of {args.runs} measured passes of {name} "
+                                        f"failed, {outcome}")
+                        if not measured:
+                            results.append({'name': name,
+                                            'direction': 'write' if write else 'read',
+                                            'error': EXIT_IO_ERROR, 'reason': failed[-1]})
+                            run_hook(hooks, 'post', name, test_path, [])
+                            continue
+
+                        io_bytes = sum(m[0] for m in measured)
+                        ops = sum(m[1] for m in measured)
+                        elapsed = sum(m[2] for m in measured)
+                        results.append({
+                            'name': name,
+                            'direction': 'write' if write else 'read',
+                            'speed_mbs': make_humanreadable_speed(io_bytes / elapsed),
+                            'iops': ops / elapsed,
+                            # Every thread waits for its own operations
+                            'latency_us': make_humanreadable_time(elapsed * workers / ops * 1e9),
+                            'threads': workers,
+                            'block_size': block_label(bs),
+                            'iodepth': '1',
+                            'passes': native_pass_stats(
+                                [m[0] / m[2] / 1024**2 for m in measured], io_bytes, elapsed),
+                        })
+                        if failed:
+                            results[-1]['failed_passes'] = len(failed)
+                        if kind == 'SYNC' and latencies:
+                            results[-1]['fsync'] = fsync_stats(
+                                statistics.mean(latencies) * 1e6,
+                                sample_percentile(sorted(latencies), 99) * 1e6,
+                                max(latencies) * 1e6, ops, elapsed)
+                        elif latencies:
+                            latencies.sort()
+                            results[-1]['clat_percentiles_us'] = {
+                                f"p{p:g}": sample_percentile(latencies, p) * 1e6
+                                for p in LATENCY_PERCENTILES}
+                            results[-1]['latency_max_us'] = latencies[-1] * 1e6
+                        announce_results(results[-1:])
+                        run_hook(hooks, 'post', name, test_path, results[-1:])
+            if not running['cancelled']:
+                progress_bar(total, total, f"{'Native Progress':<32}")
+        except OSError as e:
+            raise PdmError(f"I/O error on '{file_path}': {e.strerror}", EXIT_IO_ERROR)
+        finally:
+            os.close(fd)
+            stop_samplers(samplers)
+            if created:
+                shredded = remove_native_test_file(file_path, args.keep_data, args.quiet,
+                                                   args.shred)
+
+        tests = aggregate_repetitions(repetitions)
+        for sampler in samplers:
+            sampler.attach(tests)
+        if args.trim_test and args.mode != 'read':
+            tests.append(unsupported_trim_result("the native backend can't trim, use fio"))
+        return BenchmarkReport(
+            backend=self.name,
+            version=f"Python {platform.python_version()}",
+            target=test_path,
+            tests=tests,
+            settings={
+                'test_size': size,
+                'loops': args.runs,
+                'runtime': duration,
+                'engine': 'direct' if is_direct else 'buffered',
+                'repeat': args.repeat,
+                'direct': is_direct,
+                'caches_dropped': caches_dropped,
+                'thermal_limit': args.thermal_limit,
+                'wait_idle': args.wait_idle,
+                'idle_timeouts': idle_timeouts,
+                'data_pattern': args.data_pattern,
+                'threads': args.threads,
+                # How the test file was wiped with --shred
+                'shred': shredded,
+            },
+            raw={'direct_io': is_direct, 'warmup': args.warmup,
+                 'runs': args.runs, 'repeat': args.repeat, 'results': repetitions},
+            partial=running['cancelled'])
+
+
+BACKENDS = {
+    'fio': FioBackend,
+    'native': NativeBackend,
+}
+
+
+def job_direction(job_name):
+    """Guess the direction of a job from a cdm8-style name (SEQ-R-1M-Q8-T1)."""
+    spl = job_name.split('-')
+    if len(spl) > 1 and spl[1] == 'W':
+        return 'write'
+    if len(spl) > 1 and spl[1] == 'T':
+        return 'trim'
+    return 'read'
+
+
+def job_label(job_name):
+    """Turn a cdm8-style job name into a CrystalDiskMark label (SEQ1M Q8T1).
+
+    Mixed jobs like RND-M70-4K-Q32-T1 keep their read share (RND4K Q32T1 M70),
+    --regions jobs like SEQ-R-1M-Q8-T1@2 their region (SEQ1M Q8T1 @2).
+    """
+    name, _, region = job_name.partition('@')
+    if region:
+        label = job_label(name)
+        return job_name if label == name else f"{label} @{region}"
+    spl = job_name.split('-')
+    if len(spl) != 5 or not (spl[1] in ('R', 'W', 'T') or re.fullmatch(r'M\d+', spl[1])):
+        return job_name
+    label = f"{spl[0]}{spl[2].upper()} {spl[3]}{spl[4]}"
+    return label if spl[1] in ('R', 'W', 'T') else f"{label} {spl[1]}"
+
+
+def spprint_summary_table(data_json):
+    """Format parsed results as a table with one row per test."""
+    rows = {}
+    for job in data_json:
+        label = job_label(job['name'])
+        # cdm8-style names carry the thread count already
+        if job.get('threads', 1) > 1 and label == job['name']:
+            label += f" ({job['threads']} threads)"
+        row = rows.setdefault(label, {})
+        row[job['direction']] = job
+
+    # Repeated runs get a column with the standard deviation of the speed,
+    # so do native tests of several passes, over the passes
+    repeated = any('stdev_mbs' in job or len(job.get('passes', {}).get('speeds_mbs', [])) > 1
+                   for job in data_json)
+
+    def cells(job):
+        if job is None:
+            return ('-', '-', '-')
+        if job.get('error') in ('timeout', 'unsupported'):
+            return ('timed out' if job['error'] == 'timeout' else 'unsupported', '-', '-')
+        if 'error' in job:
+            return ('failed', '-', f"err {job['error']}")
+        stdev = job.get('stdev_mbs', job.get('passes', {}).get('stdev_mbs', 0))
+        return (job['speed_mbs'], f"{stdev:.2f}", f"{job['iops']:.1f}")
+
+    # Read or write only runs leave out the columns of the other direction
+    directions = [direction for direction in ('read', 'write', 'trim')
+                  if any(job['direction'] == direction for job in data_json)] or ['read', 'write']
+
+    def line(label, columns):
+        text = f"{label:<16}"
+        for speed, stdev, iops in columns:
+            text += f" {speed:>12}" + (f" {stdev:>8}" if repeated else "") + f" {iops:>12}"
+        return text + "\n"
+
+    sb_string = line('Test', [(f'{direction.title()} MB/s', '±', f'{direction.title()} IOPS')
+                              for direction in directions])
+    sb_string += "-" * (16 + len(directions) * (35 if repeated else 26)) + "\n"
+    for label, row in rows.items():
+        sb_string += line(label, [cells(row.get(direction)) for direction in directions])
+
+    # Only fio reports latency percentiles
+    percentiles = [job for job in data_json if 'clat_percentiles_us' in job]
+    if percentiles:
+        keys = [f"p{p:g}" for p in LATENCY_PERCENTILES]
+        sb_string += f"\n{'Latency (us)':<16} {'':<6}" + "".join(f"{key:>11}" for key in keys) + "\n"
+        sb_string += "-" * (23 + 11 * len(keys)) + "\n"
+        for job in percentiles:
+            values = [job['clat_percentiles_us'].get(key) for key in keys]
+            sb_string += f"{job_label(job['name']):<16} {job['direction']:<6}" + "".join(
+                f"{'n/a' if value is None else f'{value:.2f}':>11}" for value in values) + "\n"
+    return sb_string
+
+
+def latency_class(p99_us):
+    """Name the class of drive a p99 random read latency in us is typical of."""
+    for limit, name in LATENCY_CLASSES:
+        if p99_us < limit:
+            return f"{name} (p99 < {limit} us)"
+    return f"slower than a typical HDD (p99 >= {LATENCY_CLASSES[-1][0]} us)"
+
+
+def classify_latency(tests):
+    """Add the drive class to the read result of the --mode latency job."""
+    for test in tests:
+        p99 = test.get('clat_percentiles_us', {}).get('p99')
+        if test['name'] == LATENCY_JOB and test['direction'] == 'read' and p99 is not None:
+            test['latency_class'] = latency_class(p99)
+
+
+def spprint_latency(tests):
+    """Format the mean, p99 and max latency and IOPS of a --mode latency run."""
+    sb_string = ""
+    for test in tests:
+        if 'latency_class' not in test:
+            continue
+        latency_max = test.get('latency_max_us')
+        sb_string += f"Latency of {job_label(test['name'])} reads:\n"
+        sb_string += f"{'Mean':>8}: {float(test['latency_us']):>10.2f} us\n"
+        sb_string += f"{'p99':>8}: {test['clat_percentiles_us']['p99']:>10.2f} us\n"
+        sb_string += f"{'Max':>8}: " + \
+            ("n/a" if latency_max is None else f"{latency_max:>10.2f} us") + "\n"
+        sb_string += f"{'IOPS':>8}: {test['iops']:>10.1f}\n"
+        sb_string += f"{'Class':>8}: {test['latency_class']}\n"
+    return sb_string
+
+
+def spprint_fsync(tests):
+    """Format the fsync latency and rate of an --fsync-test run."""
+    sb_string = ""
+    for test in tests:
+        fsync = test.get('fsync')
+        if not fsync:
+            continue
+        p99 = fsync['p99_us']
+        rate = fsync['syncs_per_second']
+        sb_string += f"fsync of {job_label(test['name'])}: mean {fsync['mean_us']:.2f} us, " \
+            f"p99 {'n/a' if p99 is None else f'{p99:.2f} us'}, " \
+            f"{'n/a' if rate is None else f'{rate:.1f}'} syncs/s\n"
+        if fsync['volatile_cache_suspected']:
+            sb_string += f"WARNING: fsync took under {FSYNC_VOLATILE_US} us, the drive likely " \
+                "acknowledges it from a volatile write cache, data may be lost on power loss\n"
+    return sb_string
+
+
+def spprint_steady_state(tests):
+    """Format the peak and steady state bandwidth of a --steady-state run."""
+    sb_string = ""
+    for test in tests:
+        steady_state = test.get('steady_state')
+        if not steady_state:
+            continue
+        sb_string += f"Steady state of {job_label(test['name'])} {test['direction']}: " \
+            f"peak {steady_state['peak_mbs']} MB/s, "
+        if steady_state['attained']:
+            sb_string += f"steady {steady_state['steady_mbs']} MB/s " \
+                f"after {steady_state['seconds']:.0f} s\n"
+        else:
+            sb_string += f"not steady after {steady_state['seconds']:.0f} s, " \
+                f"last {steady_state['steady_mbs']} MB/s\n"
+    return sb_string
+
+
+def sparkline(values, width=SPARKLINE_WIDTH):
+    """Draw values as a line of block characters, averaging them down to width."""
+    if len(values) > width:
+        values = [statistics.mean(values[i * len(values) // width:(i + 1) * len(values) // width])
+                  for i in range(width)]
+    low, high = min(values), max(values)
+    steps = len(SPARKLINE_CHARS) - 1
+    return "".join(SPARKLINE_CHARS[round((value - low) / (high - low) * steps) if high > low
+                                   else steps] for value in values)
+
+
+def spprint_bandwidth_series(tests):
+    """Format the per second bandwidth of every test as a sparkline with min/avg/max."""
+    sb_string = ""
+    for test in tests:
+        series = test.get('bw_series_mbs')
+        if not series:
+            continue
+        sb_string += f"{job_label(test['name']):<16} {test['direction']:<6} {sparkline(series)} " \
+            f"min {min(series):.2f} avg {statistics.mean(series):.2f} " \
+            f"max {max(series):.2f} MB/s\n"
+    return sb_string
+
+
+def spprint_target_comparison(targets):
+    """Format the speeds of several targets side by side, one row per test.
+
+    targets is a list of (name, results) pairs, the fastest speed of each
+    row and direction is marked.
+    """
+    rows = {}
+    for t, (_, results) in enumerate(targets):
+        for job in results:
+            if 'error' not in job:
+                rows.setdefault(job_label(job['name']), {})[(t, job['direction'])] = \
+                    float(job['speed_mbs'])
+    directions = [direction for direction in ('read', 'write', 'trim')
+                  if any(key[1] == direction for row in rows.values() for key in row)]
+    width = 12 * len(directions)
+
+    sb_string = f"{'':<16}" + "".join(
+        f" {name[-width:]:>{width}}" for name, _ in targets) + "\n"
+    header = "".join(f"{direction.title() + ' MB/s':>12}" for direction in directions)
+    sb_string += f"{'Test':<16}" + f" {header}" * len(targets) + "\n"
+    sb_string += "-" * (16 + (width + 1) * len(targets)) + "\n"
+    for label, row in rows.items():
+        fastest = {direction: max((speed for (_, d), speed in row.items() if d == direction),
+                                  default=None) for direction in directions}
+        sb_string += f"{label:<16}"
+        for t in range(len(targets)):
+            sb_string += " "
+            for direction in directions:
+                speed = row.get((t, direction))
+                if speed is None:
+                    sb_string += f"{'-':>12}"
+                elif speed == fastest[direction] and len(targets) > 1:
+                    sb_string += colorize(f"{f'{speed:.2f}*':>12}", 'green')
+                else:
+                    sb_string += f"{f'{speed:.2f}':>12}"
+        sb_string += "\n"
+    sb_string += "* fastest target\n"
+    return sb_string
+
+
+def spprint_sweep_table(sweep):
+    """Format the results of a sweep as a table ordered by the swept values."""
+    def cell(point, key, spec):
+        if f"{key.split('_')[0]}_error" in point:
+            return 'failed'
+        return format(point[key], spec) if key in point else '-'
+
+    directions = [direction for direction in ('read', 'write')
+                  if any(f"{direction}_mbs" in point or f"{direction}_error" in point
+                         for point in sweep['points'])]
+    sb_string = f"{'BS':>6} {'QD':>4}" + "".join(
+        f" {direction.title() + ' MB/s':>12} {direction.title() + ' IOPS':>12}"
+        for direction in directions) + "\n"
+    sb_string += "-" * (11 + 26 * len(directions)) + "\n"
+    for point in sweep['points']:
+        sb_string += f"{point['bs']:>6} {point['qd']:>4}" + "".join(
+            f" {cell(point, f'{direction}_mbs', '.2f'):>12} {cell(point, f'{direction}_iops', '.1f'):>12}"
+            for direction in directions) + "\n"
+    return sb_string
+
+
+def spprint_region_table(regions):
+    """Format the speed of every region with a bar and the falloff, one block per job."""
+    sb_string = ""
+    for entry in regions:
+        fastest = max(point['speed_mbs'] for point in entry['points']) or 1
+        sb_string += f"{job_label(entry['job'])} {entry['direction']} by region\n"
+        sb_string += f"{'Region':>6} {'Offset':>12} {'MB/s':>10}\n"
+        sb_string += "-" * (31 + REGION_BAR_WIDTH) + "\n"
+        for point in entry['points']:
+            bar = '#' * round(point['speed_mbs'] / fastest * REGION_BAR_WIDTH)
+            sb_string += f"{point['region']:>6} {format_size(point['offset']):>12} " \
+                f"{point['speed_mbs']:>10.2f} {bar}\n"
+        if entry['falloff_percent'] is not None:
+            sb_string += f"Falloff from the first to the last region: " \
+                f"{entry['falloff_percent']:+.1f}%\n"
+        sb_string += "\n"
+    return sb_string
+
+
+def spprint_cdm8(report):
+    sb_string = ""
+
+    git_hash = git.Repo(search_parent_directories=True).head.object.hexsha[:7]
+    sb_string += f'{f"PDM ({git_hash}): https://github.com/Kseen715/pydiskmark":>80}\n'
+
+    version = report.version or 'Unknown'
+    if report.backend == 'fio':
+        sb_string += f'{f"Flexible I/O Tester ({version}): https://github.com/axboe/fio":>80}\n'
+    else:
+        sb_string += f'{f"Backend: {report.backend} ({version})":>80}\n'
+
+    spl_out = []
+    for job in report.tests:
+        # Only cdm8-style names (SEQ-R-1M-Q8-T1) fit the CDM layout, the rest go below
+        if 'error' in job or job_label(job['name']) == job['name'] or '@' in job['name'] or \
+                'fsync' in job:
+            continue
+        spl = job['name'].split('-')
+        spl[0] = spl[0].replace('SEQ', 'Sequential').replace('RND', 'Random')
+        # split 1M into [[1, M]
+        spl[2] = [int(spl[2][:-1]), spl[2]
+                  [-1].replace('K', 'KiB').replace('M', 'MiB')]
+        spl[3] = int(spl[3].split('Q')[1])
+        spl[4] = int(spl[4].split('T')[1])
+        spl.append(job['speed_mbs'])
+        spl.append(job['iops'])
+        spl.append(job['latency_us'])
+        spl.append(job['direction'])
+        spl_out.append(spl)
+
+    sb_string += "--------------------------------------------------------------------------------\n"
+    sb_string += "* MB/s = 1,000,000 bytes/s [SATA/600 = 600,000,000 bytes/s]\n"
+    sb_string += "* KB = 1000 bytes, KiB = 1024 bytes\n\n"
+    sb_string += "[Read]\n"
+    for job in spl_out:
+        if job[1] == 'R':
+            sb_string += f"{job[0]:>10} {job[2][0]:>3} {job[2][1]} (Q= {job[3]:>2}, T= {job[4]}): {job[5]:>8} MB/s [ {round(job[6], 1):>8} IOPS] < {job[7]:>8} us>\n"
+
+    sb_string += "\n[Write]\n"
+    for job in spl_out:
+        if job[1] == 'W':
+            sb_string += f"{job[0]:>10} {job[2][0]:>3} {job[2][1]} (Q= {job[3]:>2}, T= {job[4]}): {job[5]:>8} MB/s [ {round(job[6], 1):>8} IOPS] < {job[7]:>8} us>\n"
+
+    mixes = [job for job in spl_out if job[1].startswith('M')]
+    if mixes:
+        sb_string += "\n[Mix]\n"
+    for job in mixes:
+        share = int(job[1][1:]) if job[8] == 'read' else 100 - int(job[1][1:])
+        sb_string += f"{job[0]:>10} {job[2][0]:>3} {job[2][1]} (Q= {job[3]:>2}, T= {job[4]}) {job[8].title():<5} {share:>2}%: {job[5]:>8} MB/s [ {round(job[6], 1):>8} IOPS] < {job[7]:>8} us>\n"
+
+    if report.regions:
+        sb_string += "\n[Regions]\n" + spprint_region_table(report.regions).rstrip("\n") + "\n"
+    if spprint_fsync(report.tests):
+        sb_string += "\n[Fsync]\n" + spprint_fsync(report.tests)
+    if spprint_steady_state(report.tests):
+        sb_string += "\n[Steady state]\n" + spprint_steady_state(report.tests)
+    trims = [job for job in report.tests if job['direction'] == 'trim']
+    if trims:
+        sb_string += "\n[Trim]\n"
+    for job in trims:
+        if 'error' in job:
+            sb_string += f"{job_label(job['name'])}: {job.get('reason') or job['error']}\n"
+        else:
+            sb_string += f"{job_label(job['name'])}: {job['speed_mbs']:>8} MB/s [ {round(job['iops'], 1):>8} trims/s] < {job['latency_us']:>8} us>\n"
+
+    settings = report.settings
+    sb_string += "\n" + f"{'Test: ':>12}" + format_test_size(settings.get('test_size')) + \
+        f" (x{settings.get('loops') or 1}" + \
+        f") [Measure: {settings.get('runtime') or 'unknown'} sec]\n"
+    if settings.get('data_pattern'):
+        sb_string += f"{'Data: ':>12}{settings['data_pattern']}\n"
+    sb_string += f"{'Date: ':>12}" + time.strftime("%Y-%m-%d %H:%M:%S") + "\n"
+
+    if settings.get('remote'):
+        # The OS and disk of the remote host can't be looked up from here
+        sb_string += f"{'Target: ':>12}{settings['remote']}:{report.target}\n"
+        sb_string += f"{'Engine: ':>12}" + (settings.get('engine') or report.backend) + "\n"
+        return sb_string
+
+    if platform.system() == 'Windows':
+        sb_string += f"{'OS: ':>12}" + platform.system() + " " + \
+            platform.release()
+    else:
+        os_release = platform.freedesktop_os_release()
+        os_name = os_release['PRETTY_NAME']
+        if 'BUILD_ID' in os_release:
+            os_name += " " + os_release['BUILD_ID']
+        sb_string += f"{'OS: ':>12}" + os_name + \
+            " [" + platform.platform() + "]\n"
+
+    target = report.target
+    # A file target may be gone by now, the native backend deletes the file it created
+    target_dir = target if os.path.isdir(target) else os.path.dirname(target)
+    target_max_space, target_used_space, _ = get_drive_stats(target_dir)
+    sb_string += f"{'Target: ':>12}" + target + \
+        f" {target_used_space/target_max_space:.0%} ({target_used_space/1024**3:.2f}/{target_max_space/1024**3:.2f} GiB)\n"
+    sb_string += f"{'Engine: ':>12}" + \
+        (settings.get('engine') or report.backend) + "\n"
+
+    try:
+        device_info = pathinfo(target_dir)
+        sb_string += f"{'Device: ':>12}" + device_info['device'] + " " + device_info['fstype'] + "\n"
+    except Exception as e:
+        print(f"Error getting device info: {e}", file=sys.stderr)
+        sb_string += f"{'Device: ':>12}unknown\n"
+    try:
+        i_type, i_gen, i_speed = get_disk_interface(device_info['device'])
+        sb_string += f"{'Interface: ':>12}" + i_type + " " + i_gen + " " + i_speed + "\n"
+    except Exception as e:
+        print(f"Error getting disk interface: {e}", file=sys.stderr)
+        sb_string += f"{'Interface: ':>12}unknown\n"
+
+    return sb_string
+
+
+def build_result_document(report, tags=None):
+    """Collect the run metadata and per-job results into one document."""
+    try:
+        score = compute_score(report.tests)
+    except PdmError as e:
+        log.info(f"No score: {e}")
+        score = None
+    return {
+        'timestamp': time.strftime("%Y-%m-%dT%H:%M:%S%z"),
+        'hostname': platform.node(),
+        'os': platform.platform(),
+        'tags': tags or [],
+        'backend': report.backend,
+        'backend_version': report.version,
+        'test_path': report.target,
+        'settings': report.settings,
+        'partial': report.partial,
+        'system': report.system,
+        'results': report.tests,
+        **({'sweep': report.sweep} if report.sweep else {}),
+        **({'regions': report.regions} if report.regions else {}),
+        **({'timing': report.timing} if report.timing else {}),
+        **({'smart': report.smart} if report.smart else {}),
+        **({'score': round(score, 1)} if score is not None else {}),
+    }
+
+
+# Fields of a result document that are the same for every target of a run
+SHARED_DOCUMENT_KEYS = ['timestamp', 'hostname', 'os', 'tags', 'backend', 'backend_version']
+
+
+def build_multi_target_document(documents):
+    """Combine the result documents of several targets into one."""
+    shared = {key: documents[0][key] for key in SHARED_DOCUMENT_KEYS}
+    return {
+        **shared,
+        'targets': [{key: value for key, value in document.items()
+                     if key not in SHARED_DOCUMENT_KEYS} for document in documents],
+    }
+
+
+CSV_COLUMNS = ['timestamp', 'hostname', 'backend', 'target', 'test', 'block_size',
+               'queue_depth', 'read_MBps', 'write_MBps', 'read_IOPS', 'write_IOPS',
+               'mean_latency_us']
+
+
+def build_result_csv(document, header=True):
+    """Format a result document as CSV with one row per test."""
+    rows = {}
+    for test in document['results']:
+        rows.setdefault(test['name'], {})[test['direction']] = test
+
+    def value(test, key, spec):
+        if test is None or 'error' in test:
+            return ''
+        return format(float(test[key]), spec)
+
+    out = io.StringIO()
+    # The csv module quotes fields with commas and ends lines with CRLF per RFC 4180
+    writer = csv.writer(out)
+    if header:
+        writer.writerow(CSV_COLUMNS)
+    for name, row in rows.items():
+        read, write = row.get('read'), row.get('write')
+        done = [test for test in (read, write) if test is not None and 'error' not in test]
+        first = done[0] if done else {}
+        latency = ''
+        if done:
+            latency = f"{sum(float(test['latency_us']) for test in done) / len(done):.2f}"
+        writer.writerow([
+            document['timestamp'], document['hostname'], document['backend'],
+            document['test_path'], name, first.get('block_size') or '',
+            first.get('iodepth') or '',
+            value(read, 'speed_mbs', '.2f'), value(write, 'speed_mbs', '.2f'),
+            value(read, 'iops', '.1f'), value(write, 'iops', '.1f'), latency,
+        ])
+    return out.getvalue()
+
+
+def chart_ticks(low, high, log_scale):
+    """Pick the ticks of a chart axis covering low to high, from 0 unless on a log scale."""
+    if log_scale:
+        start = math.floor(math.log10(low))
+        return [10 ** power for power in range(start, max(math.ceil(math.log10(high)), start + 1) + 1)]
+    magnitude = 10 ** math.floor(math.log10(high))
+    step = next(magnitude * m for m in (0.1, 0.2, 0.5, 1, 2) if high / (magnitude * m) <= 6)
+    return [step * i for i in range(math.ceil(high / step) + 1)]
+
+
+def svg_text(x, y, text, size=11, anchor='middle', weight='normal'):
+    """An SVG text element, in a generic font so no font has to be installed."""
+    return f'<text x="{x:.1f}" y="{y:.1f}" font-family="sans-serif" font-size="{size}" ' \
+        f'font-weight="{weight}" text-anchor="{anchor}">{html.escape(str(text))}</text>'
+
+
+def svg_axis(left, top, width, height, ticks, scale, unit):
+    """Draw the y axis of a chart panel with a grid line per tick."""
+    parts = [f'<rect x="{left}" y="{top}" width="{width}" height="{height}" fill="none" '
+             'stroke="#999"/>']
+    for tick in ticks:
+        y = scale(tick)
+        parts.append(f'<line x1="{left}" y1="{y:.1f}" x2="{left + width}" y2="{y:.1f}" '
+                     'stroke="#ddd"/>')
+        parts.append(svg_text(left - 6, y + 4, f"{tick:g}", 10, 'end'))
+    parts.append(svg_text(14, top + height / 2, unit, 11).replace(
+        '<text ', f'<text transform="rotate(-90 14 {top + height / 2:.1f})" ', 1))
+    return parts
+
+
+def build_chart_svg(document, log_scale=False):
+    """Draw the results of a document as an SVG bar chart of MB/s per test.
+
+    When the sequential tests have a per second bandwidth series, a second panel
+    shows it over time.
+    """
+    rows = {}
+    for test in document['results']:
+        if 'error' not in test and float(test['speed_mbs']) > 0:
+            rows.setdefault(job_label(test['name']), {})[test['direction']] = \
+                float(test['speed_mbs'])
+    if not rows:
+        raise PdmError("There are no results to chart", EXIT_BAD_ARGS)
+    directions = [direction for direction in CHART_COLORS
+                  if any(direction in row for row in rows.values())]
+    series = [(f"{job_label(test['name'])} {test['direction']}", test['bw_series_mbs'])
+              for test in document['results']
+              if test['name'].startswith('SEQ') and test.get('bw_series_mbs')]
+
+    system = document.get('system') or {}
+    title = f"{system.get('model') or system.get('device') or document['test_path']}, " \
+        f"{document['timestamp'][:10]}"
+    left, width, top, height = 70, CHART_WIDTH - 90, 60, 260
+    total_height = top + height + 50 + (height + 90 if series else 0)
+    parts = [f'<svg xmlns="http://www.w3.org/2000/svg" width="{CHART_WIDTH}" '
+             f'height="{total_height}" viewBox="0 0 {CHART_WIDTH} {total_height}">',
+             f'<rect width="{CHART_WIDTH}" height="{total_height}" fill="white"/>',
+             svg_text(CHART_WIDTH / 2, 26, title, 16, weight='bold')]
+
+    values = [value for row in rows.values() for value in row.values()]
+    ticks = chart_ticks(min(values), max(values), log_scale)
+
+    def scale(value):
+        if log_scale:
+            low, high = math.log10(ticks[0]), math.log10(ticks[-1])
+            share = (math.log10(max(value, ticks[0])) - low) / (high - low)
+        else:
+            share = value / ticks[-1]
+        return top + height - share * height
+
+    parts += svg_axis(left, top, width, height, ticks, scale, 'MB/s')
+    for d, direction in enumerate(directions):
+        x = left + width - 90 * (len(directions) - d)
+        parts.append(f'<rect x="{x}" y="38" width="12" height="12" '
+                     f'fill="{CHART_COLORS[direction]}"/>')
+        parts.append(svg_text(x + 16, 48, direction.title(), 11, 'start'))
+    group = width / len(rows)
+    bar = group * 0.8 / len(directions)
+    for g, (label, row) in enumerate(rows.items()):
+        x = left + g * group + group * 0.1
+        for d, direction in enumerate(directions):
+            if direction not in row:
+                continue
+            y = scale(row[direction])
+            parts.append(f'<rect x="{x + d * bar:.1f}" y="{y:.1f}" width="{bar:.1f}" '
+                         f'height="{top + height - y:.1f}" fill="{CHART_COLORS[direction]}"/>')
+            parts.append(svg_text(x + (d + 0.5) * bar, y - 3, f"{row[direction]:.0f}", 9))
+        parts.append(svg_text(left + (g + 0.5) * group, top + height + 16, label, 11))
+
+    if series:
+        top += height + 90
+        parts.append(svg_text(CHART_WIDTH / 2, top - 14, "Sequential bandwidth over time", 13,
+                              weight='bold'))
+        seconds = max(len(values) for _, values in series)
+        line_ticks = chart_ticks(0, max(max(values) for _, values in series), False)
+
+        def line_scale(value):
+            return top + height - value / line_ticks[-1] * height
+
+        parts += svg_axis(left, top, width, height, line_ticks, line_scale, 'MB/s')
+        parts.append(svg_text(left + width / 2, top + height + 30, 'Seconds', 11))
+        for second in range(0, seconds, max(seconds // 10, 1)):
+            parts.append(svg_text(left + second / max(seconds - 1, 1) * width,
+                                  top + height + 14, second, 10))
+        for i, (label, values) in enumerate(series):
+            color = CHART_LINE_COLORS[i % len(CHART_LINE_COLORS)]
+            points = " ".join(f"{left + t / max(seconds - 1, 1) * width:.1f},"
+                              f"{line_scale(value):.1f}" for t, value in enumerate(values))
+            parts.append(f'<polyline points="{points}" fill="none" stroke="{color}" '
+                         'stroke-width="1.5"/>')
+            parts.append(f'<rect x="{left + 10}" y="{top + 10 + i * 16}" width="12" height="3" '
+                         f'fill="{color}"/>')
+            parts.append(svg_text(left + 28, top + 15 + i * 16, label, 10, 'start'))
+    parts.append('</svg>')
+    return "\n".join(parts) + "\n"
+
+
+def write_chart(path, document, log_scale=False):
+    """Save the chart of a document as SVG or PNG, returning the path written.
+
+    PNG needs cairosvg and the cairo library, without them the SVG is saved instead.
+    """
+    svg = build_chart_svg(document, log_scale)
+    if path.lower().endswith('.png'):
+        try:
+            import cairosvg
+            write_file_atomic(path, cairosvg.svg2png(bytestring=svg.encode()))
+            return path
+        except (ImportError, OSError) as e:
+            path = os.path.splitext(path)[0] + '.svg'
+            log.warning(f"Could not render a PNG chart ({e}), saving it as {path} instead")
+    write_file_atomic(path, svg)
+    return path
+
+
+def escape_markdown(text):
+    """Escape pipes so user strings can't break a Markdown table."""
+    return str(text).replace('|', '\\|')
+
+
+def markdown_code(text):
+    """Format text as inline code, picking a fence the text doesn't contain."""
+    fence = '`'
+    while fence in str(text):
+        fence += '`'
+    if len(fence) > 1:
+        return f"{fence} {text} {fence}"
+    return f"`{text}`"
+
+
+def build_result_markdown(document):
+    """Format a result document as a GitHub-flavored Markdown report."""
+    settings = document.get('settings', {})
+    md = "# PyDiskMark results\n\n"
+    md += f"- **Date:** {document['timestamp']}\n"
+    md += f"- **Host:** {escape_markdown(document['hostname'])}\n"
+    md += f"- **OS:** {escape_markdown(document.get('os', 'unknown'))}\n"
+    md += f"- **Backend:** {document['backend']} ({escape_markdown(document['backend_version'])})\n"
+    md += f"- **Target:** {markdown_code(document['test_path'])}\n"
+    md += f"- **Test size:** {format_test_size(settings.get('test_size'))}\n"
+    if document.get('tags'):
+        md += f"- **Tags:** {', '.join(escape_markdown(tag) for tag in document['tags'])}\n"
+
+    rows = {}
+    for test in document['results']:
+        rows.setdefault(job_label(test['name']), {})[test['direction']] = test
+
+    def cells(test):
+        if test is None:
+            return ('-', '-')
+        if 'error' in test:
+            return ('failed', '-')
+        return (test['speed_mbs'], f"{test['iops']:.1f}")
+
+    md += "\n| Test | Read MB/s | Read IOPS | Write MB/s | Write IOPS |\n"
+    md += "|:-----|----------:|----------:|-----------:|-----------:|\n"
+    for name, row in rows.items():
+        read_speed, read_iops = cells(row.get('read'))
+        write_speed, write_iops = cells(row.get('write'))
+        md += f"| {escape_markdown(name)} | {read_speed} | {read_iops} | {write_speed} | {write_iops} |\n"
+
+    # Only fio reports completion latency percentiles
+    percentiles = [test for test in document['results'] if 'clat_percentiles_us' in test]
+    if percentiles:
+        keys = list(percentiles[0]['clat_percentiles_us'])
+        md += "\n| Test | Direction | " + " | ".join(f"{key} (us)" for key in keys) + " |\n"
+        md += "|:-----|:----------|" + "|".join("-" * (len(key) + 6) + ":" for key in keys) + "|\n"
+        for test in percentiles:
+            values = [test['clat_percentiles_us'].get(key) for key in keys]
+            md += f"| {escape_markdown(test['name'])} | {test['direction']} | " + \
+                " | ".join('n/a' if value is None else f"{value:.2f}" for value in values) + " |\n"
+    return md
+
+
+def build_result_cdm_text(document):
+    """Format a result document like the text CrystalDiskMark copies to the clipboard.
+
+    Only the cdm8-style tests that finished are listed, with MB/s in
+    1,000,000 bytes as CrystalDiskMark counts them.
+    """
+    separator = "-" * 78 + "\n"
+    text = separator
+    text += f"PyDiskMark ({document['backend_version'] or document['backend']}) " \
+        "https://github.com/Kseen715/pydiskmark\n"
+    text += separator
+    text += "* MB/s = 1,000,000 bytes/s [SATA/600 = 600,000,000 bytes/s]\n"
+    text += "* KB = 1000 bytes, KiB = 1024 bytes\n"
+    for direction in ('read', 'write'):
+        tests = [test for test in document['results']
+                 if test['direction'] == direction and 'error' not in test
+                 and re.fullmatch(r'(SEQ|RND)-[RW]-\d+[KM]-Q\d+-T\d+', test['name'])]
+        if not tests:
+            continue
+        text += f"\n[{direction.title()}]\n"
+        for test in tests:
+            speed = float(test['speed_mbs']) * 1024**2 / 1000**2
+            text += f"{job_label(test['name']):<11}: {speed:>9.3f} MB/s [ {test['iops']:>8.1f} IOPS]"
+            if test.get('latency_us') is not None:
+                text += f" < {float(test['latency_us']):>8.2f} us>"
+            text += "\n"
+
+    settings = document.get('settings', {})
+    test_size = settings.get('test_size')
+    date = re.match(r'(\d{4})-(\d{2})-(\d{2})T(\d{2}:\d{2}:\d{2})', document['timestamp'] or '')
+    text += "\n"
+    text += f"{'Test: ':>9}{format_test_size(test_size)} " \
+        f"(x{settings.get('loops') or 1}) [{document['test_path']}]\n"
+    if date:
+        text += f"{'Date: ':>9}{date.group(1)}/{date.group(2)}/{date.group(3)} {date.group(4)}\n"
+    if document.get('os'):
+        text += f"{'OS: ':>9}{document['os']}\n"
+    return text
+
+
+# Page of --format html, everything inline so the file works offline. The results
+# are embedded as JSON so compare can read the file like a JSON one.
+HTML_TEMPLATE = string.Template("""<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>$title</title>
+<style>
+body { font-family: sans-serif; margin: 2em auto; max-width: 860px; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.25em; margin-top: 2em; border-bottom: 1px solid #ccc; }
+dl { display: grid; grid-template-columns: max-content auto; gap: 0.2em 1em; }
+dt { font-weight: bold; }
+dd { margin: 0; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; }
+th { background: #f4f4f4; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+code { background: #f4f4f4; padding: 0 0.2em; }
+svg { max-width: 100%; height: auto; }
+</style>
+</head>
+<body>
+<h1>PyDiskMark results</h1>
+$sections
+<script type="application/json" id="pydiskmark-results">
+$data
+</script>
+</body>
+</html>
+""")
+
+
+def html_table(header, rows, numeric_from=1):
+    """Build an HTML table, the cells from numeric_from on are right aligned."""
+    out = "<table>\n<tr>" + "".join(f"<th>{html.escape(cell)}</th>" for cell in header) + "</tr>\n"
+    for row in rows:
+        out += "<tr>" + "".join(
+            f"<td{' class=\"number\"' if i >= numeric_from else ''}>{html.escape(str(cell))}</td>"
+            for i, cell in enumerate(row)) + "</tr>\n"
+    return out + "</table>\n"
+
+
+def build_html_section(document):
+    """Format the metadata, tables and chart of one result document as HTML."""
+    settings = document.get('settings', {})
+    system = document.get('system') or {}
+    test_size = settings.get('test_size')
+    fields = [
+        ('Date', document['timestamp']),
+        ('Host', document['hostname']),
+        ('OS', document.get('os', 'unknown')),
+        ('Backend', f"{document['backend']} ({document['backend_version']})"),
+        ('Target', document['test_path']),
+        ('Device', system.get('model') or system.get('device')),
+        ('Test size', format_test_size(test_size)),
+        ('Tags', ", ".join(document.get('tags') or [])),
+    ]
+    out = f"<h2>{html.escape(document['test_path'])}</h2>\n<dl>\n"
+    out += "".join(f"<dt>{name}</dt><dd>{html.escape(str(value))}</dd>\n"
+                   for name, value in fields if value)
+    out += "</dl>\n"
+
+    rows = {}
+    for test in document['results']:
+        rows.setdefault(job_label(test['name']), {})[test['direction']] = test
+    directions = [direction for direction in ('read', 'write', 'trim')
+                  if any(direction in row for row in rows.values())] or ['read', 'write']
+
+    def cells(test):
+        if test is None:
+            return ['-', '-']
+        if 'error' in test:
+            return [test['error'] if test['error'] == 'unsupported' else 'failed', '-']
+        return [test['speed_mbs'], f"{test['iops']:.1f}"]
+
+    out += html_table(['Test'] + [f"{direction.title()} {unit}" for direction in directions
+                                  for unit in ('MB/s', 'IOPS')],
+                      [[label] + [cell for direction in directions
+                                  for cell in cells(row.get(direction))]
+                       for label, row in rows.items()])
+
+    percentiles = [test for test in document['results'] if 'clat_percentiles_us' in test]
+    if percentiles:
+        keys = [f"p{p:g}" for p in LATENCY_PERCENTILES]
+        out += "<h3>Completion latency</h3>\n" + html_table(
+            ['Test', 'Direction'] + [f"{key} (us)" for key in keys],
+            [[job_label(test['name']), test['direction']] +
+             ['n/a' if test['clat_percentiles_us'].get(key) is None
+              else f"{test['clat_percentiles_us'][key]:.2f}" for key in keys]
+             for test in percentiles], numeric_from=2)
+    try:
+        out += build_chart_svg(document)
+    except PdmError:
+        # Nothing finished, so there is nothing to draw
+        pass
+    return out
+
+
+def build_result_html(documents):
+    """Format the result documents of a run as one self-contained HTML page."""
+    data = documents[0] if len(documents) == 1 else build_multi_target_document(documents)
+    return HTML_TEMPLATE.substitute(
+        title=html.escape(f"PyDiskMark results, {documents[0]['timestamp'][:10]}"),
+        sections="".join(build_html_section(document) for document in documents),
+        # A </script> in a path or tag must not end the block early
+        data=json.dumps(data, indent=4).replace('</', '<\\/'))
+
+
+# Metrics written by --export, with their type and help text
+PROMETHEUS_METRICS = {
+    'pydiskmark_bandwidth_bytes': ('gauge', 'Throughput of the test in bytes per second'),
+    'pydiskmark_iops': ('gauge', 'I/O operations per second of the test'),
+    'pydiskmark_latency_seconds': ('gauge', 'Mean latency of the test'),
+    'pydiskmark_latency_percentile_seconds': ('gauge', 'Completion latency percentiles of the test'),
+    'pydiskmark_score': ('gauge', 'Composite score of the run, see the score subcommand'),
+    'pydiskmark_last_run_timestamp_seconds': ('gauge', 'Time the run finished'),
+}
+
+
+def prometheus_name(text):
+    """Turn a fio job name into something safe as a metric or label name part."""
+    text = re.sub(r'\s+', '', text)
+    return re.sub(r'[^A-Za-z0-9_]+', '_', text).strip('_') or '_'
+
+
+def prometheus_labels(labels):
+    """Format labels as {key="value",...}, escaping the values."""
+    def escape(value):
+        return str(value).replace('\\', '\\\\').replace('"', '\\"').replace('\n', '\\n')
+    return "{" + ",".join(f'{key}="{escape(value)}"' for key, value in labels.items()) + "}"
+
+
+def build_prometheus_metrics(documents):
+    """Format result documents in the Prometheus text exposition format."""
+    samples = {name: [] for name in PROMETHEUS_METRICS}
+    finished = time.time()
+    for document in documents:
+        device = (document.get('system') or {}).get('device')
+        target = {'device': os.path.basename(device) if device else 'unknown',
+                  'path': document['test_path']}
+        samples['pydiskmark_last_run_timestamp_seconds'].append((target, round(finished)))
+        if 'score' in document:
+            samples['pydiskmark_score'].append((target, document['score']))
+        for test in document['results']:
+            if 'error' in test:
+                continue
+            labels = {'test': prometheus_name(job_label(test['name'])),
+                      'direction': test['direction'], **target}
+            samples['pydiskmark_bandwidth_bytes'].append(
+                (labels, float(test['speed_mbs']) * 1024**2))
+            samples['pydiskmark_iops'].append((labels, test['iops']))
+            samples['pydiskmark_latency_seconds'].append(
+                (labels, float(test['latency_us']) / 1e6))
+            for key, value in test.get('clat_percentiles_us', {}).items():
+                if value is not None:
+                    quantile = f"{float(key[1:]) / 100:g}"
+                    samples['pydiskmark_latency_percentile_seconds'].append(
+                        ({**labels, 'quantile': quantile}, value / 1e6))
+
+    text = ""
+    for name, (kind, description) in PROMETHEUS_METRICS.items():
+        if not samples[name]:
+            continue
+        text += f"# HELP {name} {description}\n# TYPE {name} {kind}\n"
+        for labels, value in samples[name]:
+            text += f"{name}{prometheus_labels(labels)} {value}\n"
+    return text
+
+
+def push_metrics(url, job, content):
+    """POST metrics to a Prometheus Pushgateway under the given job."""
+    request = urllib.request.Request(
+        f"{url.rstrip('/')}/metrics/job/{urllib.parse.quote(job, safe='')}",
+        data=content.encode(), method='POST',
+        headers={'Content-Type': 'text/plain; version=0.0.4'})
+    with urllib.request.urlopen(request, timeout=10):
+        pass
+
+
+def build_run_summary(outcomes, elapsed, interrupted=False):
+    """Sum a run up in a title and a few lines, for notifications.
+
+    outcomes holds (target name, results or None, error message or None)
+    per target.
+    """
+    if interrupted:
+        title = 'PyDiskMark run interrupted'
+    elif any(error for _, _, error in outcomes):
+        title = 'PyDiskMark run failed'
+    else:
+        title = 'PyDiskMark run finished'
+    lines = []
+    for name, results, error in outcomes:
+        if error:
+            lines.append(f"{name}: {error}")
+        else:
+            read, write = headline_speeds(results)
+            lines.append(f"{name}: SEQ1M read {read} MB/s, write {write} MB/s")
+    lines.append(f"Took {format_duration(elapsed)}")
+    return title, "\n".join(lines)
+
+
+# Shows a balloon tip, PowerShell gets the text through the environment
+WINDOWS_NOTIFY_SCRIPT = (
+    "Add-Type -AssemblyName System.Windows.Forms; "
+    "$n = New-Object System.Windows.Forms.NotifyIcon; "
+    "$n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; "
+    "$n.ShowBalloonTip(10000, $env:PDM_NOTIFY_TITLE, $env:PDM_NOTIFY_BODY, 'Info'); "
+    "Start-Sleep -Seconds 5; $n.Dispose()")
+
+
+def send_desktop_notification(title, body):
+    """Show a desktop notification, giving up quietly where there is no desktop."""
+    env = None
+    if platform.system() == 'Windows':
+        command = ['powershell', '-NoProfile', '-NonInteractive', '-Command',
+                   WINDOWS_NOTIFY_SCRIPT]
+        env = {'PDM_NOTIFY_TITLE': title, 'PDM_NOTIFY_BODY': body}
+    elif platform.system() == 'Darwin':
+        command = ['osascript', '-e', 'on run argv', '-e',
+                   'display notification (item 2 of argv) with title (item 1 of argv)',
+                   '-e', 'end run', title, body]
+    else:
+        if not (os.environ.get('DISPLAY') or os.environ.get('WAYLAND_DISPLAY')):
+            log.debug("No desktop session, not showing a notification")
+            return
+        command = ['notify-send', '--app-name=PyDiskMark', title, body]
+    try:
+        result = run_command(CommandSpec(command, env=env, timeout=15))
+    except OSError as e:
+        log.debug(f"Could not show a desktop notification: {e}")
+        return
+    if result.timed_out or result.returncode:
+        log.debug(f"Could not show a desktop notification: {command[0]} "
+                  f"{'timed out' if result.timed_out else f'exited with {result.returncode}'}")
+
+
+def get_data_dir():
+    """Return the per-user directory PyDiskMark keeps its data in."""
+    if platform.system() == 'Windows':
+        base = os.environ.get('LOCALAPPDATA') or os.path.expanduser('~\\AppData\\Local')
+    elif platform.system() == 'Darwin':
+        base = os.path.expanduser('~/Library/Application Support')
+    else:
+        base = os.environ.get('XDG_DATA_HOME') or os.path.expanduser('~/.local/share')
+    return os.path.join(base, 'pydiskmark')
+
+
+def get_history_file():
+    """Return the path of the results history."""
+    return os.path.join(get_data_dir(), 'history.jsonl')
+
+
+def append_history(run_id, fingerprint, document):
+    """Add a finished run to the history as one JSON line."""
+    path = get_history_file()
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    with open(path, 'a') as f:
+        f.write(json.dumps({'id': run_id, 'fingerprint': fingerprint, **document}) + "\n")
+
+
+def load_history():
+    """Load every run recorded in the history, skipping damaged lines."""
+    entries = []
+    try:
+        with open(get_history_file()) as f:
+            for number, line in enumerate(f, 1):
+                if not line.strip():
+                    continue
+                try:
+                    entries.append(json.loads(line))
+                except json.JSONDecodeError:
+                    log.warning(f"Skipping damaged line {number} of the history")
+    except FileNotFoundError:
+        pass
+    except OSError as e:
+        raise PdmError(f"Could not read the history: {e.strerror}", EXIT_IO_ERROR)
+    return entries
+
+
+def headline_speeds(results):
+    """Get the SEQ1M read and write MB/s of a run, as shown in the history."""
+    speeds = {'read': '-', 'write': '-'}
+    for test in results:
+        direction = test['direction']
+        if job_label(test['name']).startswith('SEQ1M ') and speeds[direction] == '-' \
+                and 'error' not in test:
+            speeds[direction] = test['speed_mbs']
+    return speeds['read'], speeds['write']
+
+
+def history_main(argv):
+    """Entry point of the history subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} history",
+        description='List past runs, or show one of them with "history show <id>".')
+    parser.add_argument('target', nargs='?',
+                        help='Only list runs of the disk this path is on')
+    parser.add_argument('id', nargs='?', help=argparse.SUPPRESS)
+    args = parser.parse_args(argv)
+
+    try:
+        entries = load_history()
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+
+    if args.target == 'show':
+        if not args.id:
+            parser.error("history show needs the id of a run")
+        for entry in entries:
+            if entry.get('id') == args.id:
+                print(json.dumps(entry, indent=4))
+                return 0
+        print(f"Error: No run with id '{args.id}' in the history.", file=sys.stderr)
+        return EXIT_BAD_ARGS
+    if args.id:
+        parser.error(f"unrecognized arguments: {args.id}")
+
+    if args.target:
+        if not os.path.exists(args.target):
+            print(f"Error: The specified path '{args.target}' does not exist.", file=sys.stderr)
+            return EXIT_BAD_ARGS
+        fingerprint = target_fingerprint(args.target)
+        entries = [entry for entry in entries if entry.get('fingerprint') == fingerprint]
+    if not entries:
+        print("No runs recorded yet.")
+        return 0
+
+    print(f"{'ID':<10} {'Date':<26} {'SEQ1M R MB/s':>13} {'SEQ1M W MB/s':>13}  Target")
+    print("-" * 90)
+    for entry in entries:
+        read, write = headline_speeds(entry.get('results', []))
+        print(f"{entry.get('id', '?'):<10} {entry.get('timestamp', '?'):<26} "
+              f"{read:>13} {write:>13}  {entry.get('test_path', '?')}")
+    return 0
+
+
+def load_result_document(path):
+    """Load a results file written with --output, as JSON or HTML."""
+    try:
+        with open(path) as f:
+            content = f.read()
+        if content.lstrip().startswith('<'):
+            match = re.search(r'<script type="application/json" id="pydiskmark-results">'
+                              r'(.*?)</script>', content, re.DOTALL)
+            if not match:
+                raise PdmError(f"'{path}' is not a PyDiskMark results file", EXIT_PARSE_ERROR)
+            content = match.group(1)
+        document = json.loads(content)
+    except OSError as e:
+        raise PdmError(f"Could not read '{path}': {e.strerror}", EXIT_BAD_ARGS)
+    except json.JSONDecodeError as e:
+        raise PdmError(f"Could not parse '{path}': {e}", EXIT_PARSE_ERROR)
+    if not isinstance(document, dict) or 'results' not in document:
+        raise PdmError(f"'{path}' is not a PyDiskMark results file", EXIT_PARSE_ERROR)
+    return document
+
+
+def colorize(text, color):
+    """Wrap text in an ANSI color when printing to a terminal."""
+    codes = {'red': '31', 'green': '32'}
+    if not color or not color_enabled(sys.stdout):
+        return text
+    return f"\033[{codes[color]}m{text}\033[0m"
+
+
+def percent_delta(old, new):
+    """Return the change from old to new in percent, or None if old is zero."""
+    if not old:
+        return None
+    return (new - old) / old * 100
+
+
+def load_baseline(path):
+    """Load a --baseline file mapping test names to minimum speeds and IOPS.
+
+    The file is TOML or JSON, e.g. {"SEQ1M Q8T1": {"read_mbs": 3000}}.
+    """
+    try:
+        with open(path, 'rb') as f:
+            data = f.read()
+    except OSError as e:
+        raise PdmError(f"Could not read '{path}': {e.strerror}", EXIT_BAD_ARGS)
+    try:
+        if path.lower().endswith('.toml'):
+            baseline = tomllib.loads(data.decode())
+        else:
+            baseline = json.loads(data)
+    except (tomllib.TOMLDecodeError, json.JSONDecodeError, UnicodeDecodeError) as e:
+        raise PdmError(f"Could not parse '{path}': {e}", EXIT_PARSE_ERROR)
+    if not isinstance(baseline, dict) or not baseline:
+        raise PdmError(f"'{path}' doesn't map any test to its minimums", EXIT_PARSE_ERROR)
+    for test, minimums in baseline.items():
+        if not isinstance(minimums, dict):
+            raise PdmError(f"'{path}': expected a table of minimums for {test}", EXIT_PARSE_ERROR)
+        for key, value in minimums.items():
+            if key not in BASELINE_METRICS:
+                raise PdmError(f"'{path}': unknown minimum {key} for {test}, expected one of "
+                               f"{', '.join(BASELINE_METRICS)}", EXIT_PARSE_ERROR)
+            if not isinstance(value, (int, float)) or isinstance(value, bool):
+                raise PdmError(f"'{path}': {test} {key} must be a number", EXIT_PARSE_ERROR)
+    return baseline
+
+
+def check_baseline(baseline, results):
+    """Compare results against the minimums of a baseline, one check per minimum.
+
+    Tests are matched by fio job name or label, a missing or failed test
+    fails its checks.
+    """
+    checks = []
+    for test, minimums in baseline.items():
+        for key, required in minimums.items():
+            direction, result_key, unit = BASELINE_METRICS[key]
+            measured = None
+            for job in results:
+                if test in (job['name'], job_label(job['name'])) and \
+                        job['direction'] == direction and 'error' not in job:
+                    measured = float(job[result_key])
+            checks.append({
+                'test': test,
+                'metric': key,
+                'unit': unit,
+                'required': required,
+                'measured': measured,
+                'passed': measured is not None and measured >= required,
+            })
+    return checks
+
+
+def spprint_checks(checks):
+    """Format baseline checks as PASS/FAIL lines."""
+    sb_string = ""
+    for check in checks:
+        status = colorize('PASS', 'green') if check['passed'] else colorize('FAIL', 'red')
+        measured = 'missing' if check['measured'] is None else \
+            f"{check['measured']:.2f} {check['unit']}"
+        sb_string += f"{status} {check['test']:<16} {check['metric']:<10} " \
+            f"{measured:>18} (required {check['required']:g} {check['unit']})\n"
+    return sb_string
+
+
+def spprint_comparison(old, new, threshold):
+    """Format a table comparing two result documents.
+
+    Returns the table and whether any test regressed beyond threshold.
+    """
+    def index(document):
+        return {(test['name'], test['direction']): test for test in document['results']}
+
+    old_tests = index(old)
+    new_tests = index(new)
+    regressed = False
+
+    def delta_cell(old_value, new_value):
+        nonlocal regressed
+        delta = percent_delta(old_value, new_value)
+        if delta is None:
+            return f"{'-':>8}"
+        color = None
+        if delta < -threshold:
+            color = 'red'
+            regressed = True
+        elif delta > threshold:
+            color = 'green'
+        return colorize(f"{delta:>+7.1f}%", color)
+
+    sb_string = f"{'Test':<22} {'Old MB/s':>10} {'New MB/s':>10} {'Delta':>8} " \
+        f"{'Old IOPS':>10} {'New IOPS':>10} {'Delta':>8}\n"
+    sb_string += "-" * 84 + "\n"
+    skipped = []
+    for key, old_test in old_tests.items():
+        new_test = new_tests.get(key)
+        if new_test is None:
+            continue
+        label = f"{job_label(key[0])} {key[1]}"
+        if 'error' in old_test or 'error' in new_test:
+            skipped.append(label)
+            continue
+        old_speed, new_speed = float(old_test['speed_mbs']), float(new_test['speed_mbs'])
+        sb_string += f"{label:<22} {old_speed:>10.2f} {new_speed:>10.2f} " \
+            f"{delta_cell(old_speed, new_speed)} " \
+            f"{old_test['iops']:>10.1f} {new_test['iops']:>10.1f} " \
+            f"{delta_cell(old_test['iops'], new_test['iops'])}\n"
+
+    # Tests without a counterpart are listed instead of silently dropped
+    for title, tests, other in (("Only in the old results", old_tests, new_tests),
+                                ("Only in the new results", new_tests, old_tests)):
+        missing = [f"{job_label(name)} {direction}"
+                   for name, direction in tests if (name, direction) not in other]
+        if missing:
+            sb_string += f"\n{title}:\n" + "".join(f"  {label}\n" for label in missing)
+    if skipped:
+        sb_string += "\nFailed in one of the runs:\n" + "".join(f"  {label}\n" for label in skipped)
+    return sb_string, regressed
+
+
+def compare_main(argv):
+    """Entry point of the compare subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} compare",
+        description='Compare two results files written with --output.')
+    parser.add_argument('before', help='Results of the earlier run')
+    parser.add_argument('after', help='Results of the later run')
+    parser.add_argument('--threshold', type=float, default=5.0,
+                        help='Percentage change that counts as a regression or '
+                        'improvement (default: 5)')
+    parser.add_argument('--force', action='store_true',
+                        help='Compare results of different backends or test sizes')
+    parser.add_argument('--color', choices=['auto', 'always', 'never'], default='auto',
+                        help='Color the deltas (default: auto, honoring NO_COLOR)')
+    args = parser.parse_args(argv)
+    console['color'] = args.color
+
+    try:
+        old = load_result_document(args.before)
+        new = load_result_document(args.after)
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+
+    if not args.force:
+        if old.get('backend') != new.get('backend'):
+            print(f"Error: The results come from different backends "
+                  f"({old.get('backend')} and {new.get('backend')}), use --force to compare them.",
+                  file=sys.stderr)
+            return EXIT_BAD_ARGS
+        old_size = old.get('settings', {}).get('test_size')
+        new_size = new.get('settings', {}).get('test_size')
+        if old_size != new_size:
+            print(f"Error: The results use different test sizes "
+                  f"({format_test_size(old_size)} and {format_test_size(new_size)}), "
+                  "use --force to compare them.",
+                  file=sys.stderr)
+            return EXIT_BAD_ARGS
+
+    table, regressed = spprint_comparison(old, new, args.threshold)
+    print(table, end="")
+    if regressed:
+        print(f"\nRegression beyond {args.threshold:g}% detected.")
+        return EXIT_REGRESSION
+    return 0
+
+
+# A result line of a CrystalDiskMark text export, like
+#   SEQ    1MiB (Q=  8, T= 1):  3500.123 MB/s [   3338.0 IOPS] <  2394.00 us>
+# or of --format cdm-text, like SEQ1M Q8T1 : 3500.123 MB/s [ ... ].
+# IOPS and latency are missing in older versions and some locales use decimal commas
+CDM_LINE = re.compile(
+    r'\s*(SEQ|RND|Sequential|Random)\s*(\d+)\s*(KiB|MiB|K|M)\s*'
+    r'(?:\(\s*Q\s*=\s*(\d+)\s*,\s*T\s*=\s*(\d+)\s*\)|Q(\d+)T(\d+))\s*:\s*([\d.,]+)\s*MB/s'
+    r'(?:\s*\[\s*([\d.,]+)\s*IOPS\s*\])?(?:\s*<\s*([\d.,]+)\s*us\s*>)?\s*$',
+    re.IGNORECASE)
+
+
+def parse_cdm_number(text):
+    """Parse a number of a CrystalDiskMark export, with a decimal point or comma."""
+    return float(text.replace(',', '.'))
+
+
+def parse_cdm_text(text, source):
+    """Turn a CrystalDiskMark 8 text export into a result document.
+
+    CrystalDiskMark counts MB/s in 1,000,000 bytes, the speeds are converted to
+    the MiB/s pydiskmark reports so the documents compare.
+    """
+    results = []
+    document = {
+        'timestamp': None,
+        'hostname': None,
+        'os': None,
+        'tags': [],
+        'backend': 'crystaldiskmark',
+        'backend_version': None,
+        'test_path': source,
+        'settings': {'test_size': None},
+        'partial': False,
+        'system': {},
+        'results': results,
+    }
+    direction = None
+    for number, line in enumerate(text.splitlines(), 1):
+        stripped = line.strip()
+        header = re.fullmatch(r'\[(\w+)\]', stripped)
+        if header:
+            direction = {'read': 'read', 'write': 'write'}.get(header.group(1).lower())
+            continue
+        version = re.match(r'CrystalDiskMark\s+([\w.]+)', stripped)
+        if version:
+            document['backend_version'] = f"CrystalDiskMark {version.group(1)}"
+            continue
+        field, _, value = stripped.partition(':')
+        # The settings below the results end the last section
+        if field in ('Profile', 'Test', 'Mode', 'Time', 'Date', 'OS', 'Comment'):
+            direction = None
+            if field == 'Test':
+                size = re.match(r'\s*(\d+)\s*([KMGT]iB)', value)
+                document['settings']['test_size'] = \
+                    parse_size(f"{size.group(1)}{size.group(2)[0]}") if size else None
+                drive = re.search(r'\[([^\]\s]+)', value)
+                if drive:
+                    document['test_path'] = drive.group(1)
+            elif field == 'Date':
+                date = re.search(r'(\d{4})/(\d{2})/(\d{2}) (\d{1,2}:\d{2}:\d{2})', value)
+                if date:
+                    document['timestamp'] = f"{date.group(1)}-{date.group(2)}-" \
+                        f"{date.group(3)}T{date.group(4)}"
+            elif field == 'OS':
+                document['os'] = value.strip()
+            continue
+        if direction is None or not stripped:
+            continue
+        match = CDM_LINE.fullmatch(line)
+        if not match:
+            raise PdmError(f"Could not parse line {number} of '{source}': {stripped}",
+                           EXIT_PARSE_ERROR)
+        kind, size, unit, depth, threads, depth_short, threads_short, speed, iops, latency = \
+            match.groups()
+        depth, threads = depth or depth_short, threads or threads_short
+        block_size = f"{size}{unit[0].upper()}"
+        # CrystalDiskMark MB are 1,000,000 bytes
+        speed_bytes = parse_cdm_number(speed) * 1000**2
+        results.append({
+            'name': f"{'SEQ' if kind.upper().startswith('S') else 'RND'}-"
+                    f"{direction[0].upper()}-{block_size}-Q{depth}-T{threads}",
+            'direction': direction,
+            'speed_mbs': make_humanreadable_speed(speed_bytes),
+            'iops': parse_cdm_number(iops) if iops else speed_bytes / parse_size(block_size),
+            **({'latency_us': f"{parse_cdm_number(latency):.2f}"} if latency else {}),
+            'block_size': block_size,
+            'iodepth': depth,
+            'threads': int(threads),
+        })
+    if not results:
+        raise PdmError(f"'{source}' holds no CrystalDiskMark [Read] or [Write] results",
+                       EXIT_PARSE_ERROR)
+    return document
+
+
+def import_main(argv):
+    """Entry point of the import subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} import",
+        description='Convert the results of another benchmark into a PyDiskMark results '
+        'file. They come from another backend, so compare them with --force.')
+    parser.add_argument('kind', choices=['cdm'],
+                        help='What the file holds, cdm for a CrystalDiskMark 8 text export')
+    parser.add_argument('file', help="File to import, '-' for stdin")
+    parser.add_argument('-o', '--output', default='-',
+                        help="Write the results file here (default: '-' for stdout)")
+    args = parser.parse_args(argv)
+
+    try:
+        if args.file == '-':
+            text = sys.stdin.read()
+        else:
+            # CrystalDiskMark saves its text export as UTF-8 with a BOM on Windows
+            with open(args.file, encoding='utf-8-sig', errors='replace') as f:
+                text = f.read()
+        document = parse_cdm_text(text, args.file)
+    except OSError as e:
+        print(f"Error: Could not read '{args.file}': {e.strerror}", file=sys.stderr)
+        return EXIT_BAD_ARGS
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+
+    content = json.dumps(document, indent=4) + "\n"
+    if args.output == '-':
+        sys.stdout.write(content)
+        return 0
+    try:
+        write_file_atomic(args.output, content)
+    except OSError as e:
+        print(f"Error saving results to '{args.output}': {e}", file=sys.stderr)
+        return EXIT_IO_ERROR
+    return 0
+
+
+def compute_score(results):
+    """Compute the score as the weighted geometric mean of the speeds in MB/s.
+
+    Raises PdmError if a test of SCORE_WEIGHTS is missing or failed, since
+    leaving it out would put the score on a different scale.
+    """
+    speeds = {(job_label(job['name']), job['direction']): float(job['speed_mbs'])
+              for job in results if 'error' not in job}
+    missing = [f"{label} {direction}" for label, direction in SCORE_WEIGHTS
+               if not speeds.get((label, direction))]
+    if missing:
+        raise PdmError(f"The results lack {', '.join(missing)}, which the score needs, "
+                       "run the default preset to get a score", EXIT_BAD_ARGS)
+    total = sum(SCORE_WEIGHTS.values())
+    return math.exp(sum(weight * math.log(speeds[key])
+                        for key, weight in SCORE_WEIGHTS.items()) / total)
+
+
+def score_main(argv):
+    """Entry point of the score subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} score",
+        description='Compute the score of a results file written with --output.')
+    parser.add_argument('result', help='Results file to score')
+    args = parser.parse_args(argv)
+
+    try:
+        score = compute_score(load_result_document(args.result)['results'])
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+    print(f"Score: {score:.0f}")
+    return 0
+
+
+def disks_main(argv):
+    """Entry point of the disks subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} disks",
+        description='List the disks and mounted filesystems that can be benchmarked.')
+    parser.add_argument('--json', action='store_true',
+                        help='Print the list as JSON for scripting')
+    args = parser.parse_args(argv)
+
+    disks = list_disks()
+    for disk in disks:
+        disk['warnings'] = disk_warnings(disk)
+    if args.json:
+        print(json.dumps(disks, indent=4))
+        return 0
+    if not disks:
+        print("No disks found.")
+        return 0
+
+    def size(value):
+        return format_size(value) if value else '?'
+
+    print(f"{'Device':<14} {'Model':<24} {'Size':>11} {'Type':<4} {'FS':<6} "
+          f"{'Free':>11}  Mount point")
+    print("-" * 90)
+    for disk in disks:
+        kind = {True: 'HDD', False: 'SSD'}.get(disk['rotational'], '?')
+        notes = f"  ({', '.join(disk['warnings'])})" if disk['warnings'] else ''
+        print(f"{disk['device']:<14} {(disk['model'] or '')[:24]:<24} "
+              f"{size(disk['size']):>11} {kind:<4}{notes}")
+        for fs in disk['filesystems']:
+            print(f"  {fs['device']:<12} {'':<24} {size(fs['total']):>11} {'':<4} "
+                  f"{fs['fstype'][:6]:<6} {size(fs['free']):>11}  {fs['mountpoint']}")
+    return 0
+
+
+def write_file_atomic(path, content):
+    """Write content to path via a temp file so it is never left truncated."""
+    directory = os.path.dirname(os.path.abspath(path))
+    fd, tmp_path = tempfile.mkstemp(
+        dir=directory, prefix='.' + os.path.basename(path) + '.')
+    try:
+        if isinstance(content, bytes):
+            with os.fdopen(fd, 'wb') as f:
+                f.write(content)
+        else:
+            with os.fdopen(fd, 'w', newline='') as f:
+                f.write(content)
+        # mkstemp makes the file owner-only, give it the mode a plain open() would
+        try:
+            mode = stat.S_IMODE(os.stat(path).st_mode)
+        except FileNotFoundError:
+            umask = os.umask(0)
+            os.umask(umask)
+            mode = 0o666 & ~umask
+        os.chmod(tmp_path, mode)
+        os.replace(tmp_path, path)
+    except BaseException:
+        # A failed cleanup mustn't hide why the write failed
+        with contextlib.suppress(OSError):
+            os.remove(tmp_path)
+        raise
+
+
+def read_sysfs(path):
+    """Read a sysfs attribute, returning None if it can't be read."""
+    try:
+        with open(path) as f:
+            return f.read().strip() or None
+    except OSError:
+        return None
+
+
+def get_block_device(path):
+    """Find the whole-disk block device (sda, nvme0n1) a path lives on."""
+    if platform.system() != 'Linux':
+        return None
+    try:
+        st = os.stat(path)
+    except OSError:
+        return None
+    dev = st.st_rdev if stat.S_ISBLK(st.st_mode) else st.st_dev
+    sys_path = f'/sys/dev/block/{os.major(dev)}:{os.minor(dev)}'
+    if not os.path.exists(sys_path):
+        return None
+    real_path = os.path.realpath(sys_path)
+    # Partitions live in a directory below their disk
+    if os.path.exists(os.path.join(real_path, 'partition')):
+        real_path = os.path.dirname(real_path)
+    return os.path.basename(real_path)
+
+
+def find_temperature_sensor(device):
+    """Find the hwmon temperature input of a disk (nvme0n1, sda), or None."""
+    match = re.match(r'(nvme\d+)', device)
+    if match:
+        patterns = [f"/sys/class/nvme/{match.group(1)}/hwmon*/temp1_input",
+                    f"/sys/class/nvme/{match.group(1)}/device/hwmon/hwmon*/temp1_input"]
+    else:
+        # SATA disks with the drivetemp module loaded
+        patterns = [f"/sys/block/{device}/device/hwmon/hwmon*/temp1_input"]
+    for pattern in patterns:
+        found = sorted(glob.glob(pattern))
+        if found:
+            return found[0]
+    return None
+
+
+class BackgroundSampler:
+    """Take readings in a background thread and summarize them per test.
+
+    Subclasses implement read(), returning a dict of named values. Tests
+    are marked with the time they ran so each gets the min, max and mean
+    of the readings taken meanwhile. Failing reads are skipped.
+    """
+    interval = 1
+
+    def __init__(self):
+        self.samples = []
+        self.windows = {}
+        self.stopped = threading.Event()
+        self.thread = threading.Thread(target=self.sample, daemon=True)
+
+    def read(self):
+        raise NotImplementedError
+
+    def start(self):
+        self.thread.start()
+        return self
+
+    def sample(self):
+        while not self.stopped.is_set():
+            try:
+                values = self.read()
+            except Exception as e:
+                log.debug(f"{type(self).__name__} failed to read: {e}")
+                values = None
+            if values:
+                self.samples.append((time.monotonic(), values))
+            self.stopped.wait(self.interval)
+
+    def stop(self):
+        self.stopped.set()
+        self.thread.join(timeout=self.interval + 1)
+
+    def mark(self, test, start, end):
+        """Record that a test ran from start to end (time.monotonic())."""
+        self.windows.setdefault(test, []).append((start, end))
+
+    def stats(self, test):
+        """Return the min, max and mean of each value during a test."""
+        readings = {}
+        for when, values in self.samples:
+            if any(start <= when <= end for start, end in self.windows.get(test, [])):
+                for name, value in values.items():
+                    readings.setdefault(name, []).append(value)
+        return {name: {'min': round(min(values), 1), 'max': round(max(values), 1),
+                       'mean': round(statistics.mean(values), 1)}
+                for name, values in readings.items()}
+
+    def attach(self, tests):
+        """Add the summarized readings of each test to the parsed results."""
+        for test in tests:
+            test.update(self.stats(test['name']))
+
+
+class TemperatureSampler(BackgroundSampler):
+    """Read the temperature of the target disk."""
+    interval = TEMPERATURE_INTERVAL
+
+    def __init__(self, sensor):
+        super().__init__()
+        self.sensor = sensor
+
+    @classmethod
+    def for_target(cls, test_path):
+        """Start sampling the disk behind a path, None where that isn't possible."""
+        device = get_block_device(test_path)
+        sensor = find_temperature_sensor(device) if device else None
+        if sensor is None:
+            log.debug(f"No temperature sensor found for {test_path}")
+            return None
+        return cls(sensor).start()
+
+    def temperature(self):
+        """Read the temperature in degrees Celsius, or None."""
+        try:
+            with open(self.sensor) as f:
+                return int(f.read().strip()) / 1000
+        except (OSError, ValueError) as e:
+            log.debug(f"Could not read {self.sensor}: {e}")
+            return None
+
+    def read(self):
+        temperature = self.temperature()
+        return {'temperature_c': temperature} if temperature is not None else None
+
+
+class LoadSampler(BackgroundSampler):
+    """Read the CPU utilization and load average of the machine."""
+
+    def start(self):
+        # The first reading only sets the reference point of the next one
+        psutil.cpu_percent(interval=None)
+        return super().start()
+
+    def read(self):
+        values = {'cpu_percent': psutil.cpu_percent(interval=None)}
+        if hasattr(os, 'getloadavg'):
+            values['load_1m'] = os.getloadavg()[0]
+        return values
+
+
+def start_samplers(test_path):
+    """Start the samplers that work for a local target."""
+    samplers = []
+    try:
+        samplers.append(LoadSampler().start())
+    except Exception as e:
+        # psutil is only imported off Windows
+        log.debug(f"Not sampling the CPU load: {e}")
+    temperature = TemperatureSampler.for_target(test_path)
+    if temperature:
+        samplers.append(temperature)
+    return samplers
+
+
+def stop_samplers(samplers):
+    """Stop the background threads of the samplers."""
+    for sampler in samplers:
+        sampler.stop()
+
+
+def small_block_test(test):
+    """Check whether a test used small blocks, False for fio block sizes like 4K,64K."""
+    try:
+        return parse_size(test.get('block_size') or '0') <= SMALL_BLOCK_SIZE
+    except ValueError:
+        return False
+
+
+def warn_cpu_bound(tests):
+    """Warn when small block tests may have been limited by the CPU."""
+    busy = [job_label(test['name']) for test in tests
+            if 'cpu_percent' in test and test['cpu_percent']['max'] > CPU_BOUND_PERCENT
+            and small_block_test(test)]
+    if busy:
+        print(f"WARNING: The CPU was over {CPU_BOUND_PERCENT}% busy during "
+              f"{', '.join(dict.fromkeys(busy))}, their IOPS may be limited by the CPU "
+              "rather than the disk.", file=sys.stderr)
+
+
+def wait_for_temperature(samplers, limit, iteration, total, running=None):
+    """Wait between tests until the disk cooled down to the --thermal-limit."""
+    sampler = next((sampler for sampler in samplers
+                    if isinstance(sampler, TemperatureSampler)), None)
+    if sampler is None or not limit:
+        return
+    deadline = time.monotonic() + THERMAL_WAIT_MAX
+    while not (running and running['cancelled']):
+        temperature = sampler.temperature()
+        if temperature is None or temperature <= limit:
+            return
+        if time.monotonic() > deadline:
+            log.warning(f"The disk is still at {temperature:.0f}\u00b0C after "
+                        f"{THERMAL_WAIT_MAX} seconds, going on anyway")
+            return
+        message = f"Cooling: {temperature:.0f}\u00b0C \u2192 waiting for \u2264{limit:g}\u00b0C"
+        progress_bar(iteration, total, f"{message:<32}")
+        time.sleep(TEMPERATURE_INTERVAL)
+
+
+def read_diskstats_bytes(device):
+    """Get the bytes read and written so far by a disk (sda) from /proc/diskstats."""
+    try:
+        with open('/proc/diskstats') as f:
+            for line in f:
+                fields = line.split()
+                # Sectors read and written are counted in 512 bytes, whatever the disk
+                if len(fields) > 9 and fields[2] == device:
+                    return (int(fields[5]) + int(fields[9])) * 512
+    except (OSError, ValueError):
+        pass
+    return None
+
+
+def disk_activity_probe(test_path):
+    """Make a function measuring the I/O rate of the disk a path is on, or None.
+
+    Each call returns the bytes per second read and written since the
+    previous call, or since the probe was made.
+    """
+    if platform.system() == 'Windows':
+        try:
+            import win32pdh
+            drive = os.path.splitdrive(os.path.abspath(test_path))[0]
+            query = win32pdh.OpenQuery()
+            counter = win32pdh.AddCounter(query, f"\\LogicalDisk({drive})\\Disk Bytes/sec")
+            win32pdh.CollectQueryData(query)
+        except Exception as e:
+            log.debug(f"No I/O counters for {test_path}, not waiting for idle: {e}")
+            return None
+
+        def probe():
+            win32pdh.CollectQueryData(query)
+            return win32pdh.GetFormattedCounterValue(counter, win32pdh.PDH_FMT_DOUBLE)[1]
+        return probe
+
+    device = get_block_device(test_path)
+    last = {'bytes': read_diskstats_bytes(device) if device else None, 'time': time.monotonic()}
+    if last['bytes'] is None:
+        log.debug(f"No I/O counters for {test_path}, not waiting for idle")
+        return None
+
+    def probe():
+        now, io_bytes = time.monotonic(), read_diskstats_bytes(device)
+        if io_bytes is None:
+            return None
+        rate = (io_bytes - last['bytes']) / max(now - last['time'], 0.001)
+        last.update(bytes=io_bytes, time=now)
+        return rate
+    return probe
+
+
+def wait_for_idle(probe, seconds, iteration, total, running=None):
+    """Wait before a test until the disk saw next to no I/O for seconds in a row.
 
+    Returns False if it was still busy after IDLE_WAIT_MAX seconds.
+    """
+    if probe is None or not seconds:
+        return True
+    deadline = time.monotonic() + IDLE_WAIT_MAX
+    # Start measuring afresh, the previous test's own I/O doesn't count
+    probe()
+    idle = 0
+    while idle < seconds and not (running and running['cancelled']):
+        if time.monotonic() > deadline:
+            log.warning(f"The disk is still busy after {IDLE_WAIT_MAX} seconds, going on anyway")
+            return False
+        time.sleep(1)
+        rate = probe()
+        if rate is None:
+            return True
+        idle = idle + 1 if rate < IDLE_BYTES_PER_SECOND else 0
+        message = f"Waiting for disk idle\u2026 {make_humanreadable_speed(rate)} MB/s"
+        progress_bar(iteration, total, f"{message:<32}")
+    return True
 
-def run_fio_test(test_path):
-    """Run a disk test using fio with the specified parameters."""
-    # Set platform-specific parameters for Windows
-    ioengine = "windowsaio" if platform.system() == 'Windows' else "libaio"
 
-    cmd = [
-        'fio',
-        f'--directory={test_path}',
-        f'{FIO_CONFIG}',
-        '--output-format=json',
-        f'--ioengine={ioengine}',
-    ]
+# ATA attributes counted as media errors: reallocated, reported uncorrectable,
+# pending and offline uncorrectable sectors
+ATA_MEDIA_ERROR_ATTRIBUTES = (5, 187, 197, 198)
 
+
+def read_smart(device):
+    """Run smartctl on a disk (sda, nvme0n1) and return its JSON, or None."""
+    if not shutil.which('smartctl'):
+        log.info("smartctl is not installed, skipping the SMART snapshot")
+        return None
     try:
-        # Shared flag to control the progress bar thread
-        stop_progress = threading.Event()
-
-        def run_progress_bar(total_time, name, stop_event):
-            for i in range(total_time):
-                if stop_event.is_set():
-                    return
-                time.sleep(1)
-                progress_bar(i, total_time, name)
-
-        # Set up signal handler for Ctrl+C
-        def signal_handler(sig, frame):
-            print("\nCancelling test...")
-            stop_progress.set()  # Signal the thread to stop
-            # If subprocess is still running, terminate it
-            if 'process' in locals():
-                process.terminate()
-            sys.exit(0)
-
-        # Register the signal handler
-        original_handler = signal.getsignal(signal.SIGINT)
-        signal.signal(signal.SIGINT, signal_handler)
-
-        # run a progress bar for 270 seconds in a separate thread
-        total_time = 70
-        progress_thread = threading.Thread(
-            target=run_progress_bar, args=(total_time, "FIO Progress", stop_progress))
-        # Make it a daemon thread so it exits when the main thread exits
-        progress_thread.daemon = True
-        progress_thread.start()
+        result = run_command(CommandSpec(['smartctl', '-j', '-a', f'/dev/{device}'],
+                                         timeout=30))
+        if result.timed_out:
+            log.info(f"Could not read SMART data of {device}: smartctl timed out")
+            return None
+        data = json.loads(result.stdout)
+    except (OSError, json.JSONDecodeError) as e:
+        log.info(f"Could not read SMART data of {device}: {e}")
+        return None
+    # Bits 0 and 1 of the exit status mean smartctl couldn't talk to the device
+    if result.returncode & 0b11:
+        messages = [message.get('string', '') for message in
+                    data.get('smartctl', {}).get('messages', [])]
+        log.info(f"Could not read SMART data of {device}: "
+                 f"{'; '.join(messages) or f'smartctl exited with {result.returncode}'}")
+        return None
+    return data
 
-        # Run subprocess with appropriate handling
-        process = subprocess.Popen(
-            cmd,
-            stdout=subprocess.PIPE,
-            stderr=subprocess.PIPE,
-            text=True
-        )
-        stdout, stderr = process.communicate()
 
-        # Stop progress bar
-        stop_progress.set()
+def smart_snapshot(data):
+    """Pick the values worth comparing from smartctl JSON, None where unknown."""
+    snapshot = {
+        'bytes_written': None,
+        'percentage_used': None,
+        'media_errors': None,
+        'temperature_c': data.get('temperature', {}).get('current'),
+    }
+    nvme = data.get('nvme_smart_health_information_log')
+    if nvme:
+        # NVMe data units are thousands of 512 byte blocks
+        if 'data_units_written' in nvme:
+            snapshot['bytes_written'] = nvme['data_units_written'] * 512000
+        snapshot['percentage_used'] = nvme.get('percentage_used')
+        snapshot['media_errors'] = nvme.get('media_errors')
+        return snapshot
+    attributes = {attribute['id']: attribute.get('raw', {}).get('value')
+                  for attribute in data.get('ata_smart_attributes', {}).get('table', [])}
+    if attributes.get(241) is not None:
+        snapshot['bytes_written'] = attributes[241] * data.get('logical_block_size', 512)
+    errors = [attributes[key] for key in ATA_MEDIA_ERROR_ATTRIBUTES
+              if attributes.get(key) is not None]
+    if errors:
+        snapshot['media_errors'] = sum(errors)
+    endurance = data.get('endurance_used', {}).get('current_percent')
+    snapshot['percentage_used'] = endurance
+    return snapshot
 
-        if process.returncode != 0:
-            print(f"Error running fio: {stderr}")
-            return {}
 
-        # Parse JSON output
-        fio_output = json.loads(stdout)
+def smart_delta(before, after):
+    """Subtract two snapshots, None where either doesn't know the value."""
+    return {key: after[key] - before[key]
+            if before.get(key) is not None and after.get(key) is not None else None
+            for key in before}
 
-        # Restore the original signal handler
-        signal.signal(signal.SIGINT, original_handler)
 
-        # delete fio file if it exists
-        try:
-            file_path = fio_output["global options"]["directory"] + \
-                fio_output["global options"]["filename"]
-            if os.path.exists(file_path):
-                os.remove(file_path)
-        except Exception as e:
-            print(f"Error deleting fio file: {e}")
+def spprint_smart_delta(delta):
+    """Format what the disk reports the run did to it in one line."""
+    parts = []
+    if delta.get('bytes_written') is not None:
+        parts.append(f"device wrote {format_size(delta['bytes_written'])}")
+    if delta.get('media_errors') is not None:
+        parts.append(f"{delta['media_errors']} new media errors")
+    if delta.get('percentage_used'):
+        parts.append(f"wear went up by {delta['percentage_used']}%")
+    return "SMART: " + ", ".join(parts) if parts else None
 
-        return fio_output
 
-    except Exception as e:
-        print(f"Error running fio: {e}")
-        return {}
+def get_device_identity(path):
+    """Get the model, serial and firmware of the disk behind a path, where known."""
+    identity = {'device': get_block_device(path), 'model': None,
+                'serial': None, 'firmware': None}
+    if identity['device']:
+        device_dir = f"/sys/block/{identity['device']}/device"
+        identity['model'] = read_sysfs(f"{device_dir}/model")
+        # virtio disks keep the serial next to the device directory
+        identity['serial'] = read_sysfs(f"{device_dir}/serial") or \
+            read_sysfs(f"/sys/block/{identity['device']}/serial") or \
+            read_sysfs(f"{device_dir}/wwid")
+        identity['firmware'] = read_sysfs(f"{device_dir}/firmware_rev") or \
+            read_sysfs(f"{device_dir}/rev")
+    return identity
 
 
-def make_humanreadable_speed(speed_bytes):
-    """Convert speed in bytes to a human-readable format (MB)."""
-    return f"{speed_bytes / (1024**2):.2f}"
+def get_device_capacity(device):
+    """Get the size of a whole disk like sda in bytes, or None."""
+    sectors = read_sysfs(f"/sys/block/{device}/size")
+    # sysfs counts the size in 512 byte sectors regardless of the disk
+    return int(sectors) * 512 if sectors and sectors.isdigit() else None
 
 
-def make_humanreadable_time(time_ns):
-    """Convert time in nanoseconds to a human-readable format (us)."""
-    return f"{time_ns / 1000:.2f}"
+def get_discard_max_bytes(device):
+    """Get how much a disk like sda discards at once, 0 if it doesn't support discard."""
+    value = read_sysfs(f"/sys/block/{device}/queue/discard_max_bytes")
+    return int(value) if value and value.isdigit() else None
 
 
-def parse_fio_results(job_results):
-    # we need to get all jobs names, speed, iops, and latencies
-    if 'jobs' not in job_results:
-        print("No jobs found in the fio results.")
+def get_mounted_partitions(path):
+    """List the mountpoints of filesystems on the disk a block device belongs to."""
+    device = get_block_device(path)
+    if device is None:
         return []
-    parsed_results = []
-    for job in job_results['jobs']:
-        job_name = job['jobname']
-        job_speed = make_humanreadable_speed(job['read']['bw_bytes'])
-        job_iops = job['read']['iops']
-        job_lat = make_humanreadable_time(job['read']['lat_ns']['mean'])
-
-        parsed_results.append({
-            'name': job_name,
-            'speed_mbs': job_speed,
-            'iops': job_iops,
-            'latency_us': job_lat
-        })
-    return parsed_results
+    mounted = []
+    for partition in psutil.disk_partitions(all=True):
+        if partition.device.startswith('/dev/') and get_block_device(partition.device) == device:
+            mounted.append(partition.mountpoint)
+    return mounted
 
 
-def spprint_fio_to_cdm8(data_json, fio_result=None):
-    sb_string = ""
+def list_filesystems():
+    """List the mounted filesystems with their free space."""
+    filesystems = []
+    if platform.system() == 'Windows':
+        for drive in win32api.GetLogicalDriveStrings().split('\000')[:-1]:
+            if win32file.GetDriveType(drive) not in (2, 3):
+                continue
+            try:
+                info = win32api.GetVolumeInformation(drive)
+                free, total, _ = win32file.GetDiskFreeSpaceEx(drive)
+            except Exception:
+                continue
+            filesystems.append({'device': drive, 'fstype': info[4], 'mountpoint': drive,
+                                'total': total, 'free': free, 'read_only': False})
+        return filesystems
+    for partition in psutil.disk_partitions(all=False):
+        if not partition.fstype:
+            continue
+        try:
+            usage = psutil.disk_usage(partition.mountpoint)
+        except OSError:
+            continue
+        filesystems.append({'device': partition.device, 'fstype': partition.fstype,
+                            'mountpoint': partition.mountpoint,
+                            'total': usage.total, 'free': usage.free,
+                            'read_only': 'ro' in partition.opts.split(',')})
+    return filesystems
 
-    git_hash = git.Repo(search_parent_directories=True).head.object.hexsha[:7]
-    sb_string += f'{f"PDM ({git_hash}): https://github.com/Kseen715/pydiskmark":>80}\n'
 
-    fio_version = fio_result['fio version'] if fio_result and 'fio version' in fio_result else 'Unknown'
-    sb_string += f'{f"Flexible I/O Tester ({fio_version}): https://github.com/axboe/fio":>80}\n'
+def list_disks():
+    """List the disks and mounted filesystems that can be benchmarked.
 
-    spl_out = []
-    for job in data_json:
-        spl = job['name'].split('-')
-        spl[0] = spl[0].replace('SEQ', 'Sequential').replace('RND', 'Random')
-        # split 1M into [[1, M]
-        spl[2] = [int(spl[2][:-1]), spl[2]
-                  [-1].replace('K', 'KiB').replace('M', 'MiB')]
-        spl[3] = int(spl[3].split('Q')[1])
-        spl[4] = int(spl[4].split('T')[1])
-        spl.append(job['speed_mbs'])
-        spl.append(job['iops'])
-        spl.append(job['latency_us'])
-        spl_out.append(spl)
+    On Linux every whole disk in /sys/block is listed with the filesystems
+    mounted from it, elsewhere only the mounted filesystems are known.
+    """
+    filesystems = list_filesystems()
+    if platform.system() != 'Linux':
+        return [{'device': fs['device'], 'model': None, 'size': fs['total'],
+                 'rotational': None, 'removable': None, 'read_only': fs['read_only'],
+                 'loop': False, 'filesystems': [fs]} for fs in filesystems]
 
-    sb_string += "--------------------------------------------------------------------------------\n"
-    sb_string += "* MB/s = 1,000,000 bytes/s [SATA/600 = 600,000,000 bytes/s]\n"
-    sb_string += "* KB = 1000 bytes, KiB = 1024 bytes\n\n"
-    sb_string += "[Read]\n"
-    for job in spl_out:
-        if job[1] == 'R':
-            sb_string += f"{job[0]:>10} {job[2][0]:>3} {job[2][1]} (Q= {job[3]:>2}, T= {job[4]}): {job[5]:>8} MB/s [ {round(job[6], 1):>8} IOPS] < {job[7]:>8} us>\n"
+    disks = []
+    for device in sorted(os.listdir('/sys/block')):
+        if device.startswith(('ram', 'zram')):
+            continue
+        rotational = read_sysfs(f"/sys/block/{device}/queue/rotational")
+        removable = read_sysfs(f"/sys/block/{device}/removable")
+        disks.append({
+            'device': device,
+            'model': read_sysfs(f"/sys/block/{device}/device/model"),
+            'size': get_device_capacity(device),
+            'rotational': rotational == '1' if rotational else None,
+            'removable': removable == '1' if removable else None,
+            'read_only': read_sysfs(f"/sys/block/{device}/ro") == '1',
+            'loop': device.startswith('loop'),
+            'filesystems': [],
+        })
+    by_name = {disk['device']: disk for disk in disks}
+    for fs in filesystems:
+        disk = by_name.get(get_block_device(fs['device'])) if fs['device'].startswith('/dev/') else None
+        if disk is None:
+            continue
+        disk['filesystems'].append(fs)
+    # Unused loop devices have no size and nothing to benchmark
+    return [disk for disk in disks if disk['size'] or disk['filesystems']]
 
-    sb_string += "\n[Write]\n"
-    for job in spl_out:
-        if job[1] == 'W':
-            sb_string += f"{job[0]:>10} {job[2][0]:>3} {job[2][1]} (Q= {job[3]:>2}, T= {job[4]}): {job[5]:>8} MB/s [ {round(job[6], 1):>8} IOPS] < {job[7]:>8} us>\n"
 
-    sb_string += "\n" + f"{'Test: ':>12}" + fio_result["global options"]['filesize'].replace(
-        'g', ' GiB') + " (x" + fio_result["global options"]['loops'] + f") [Measure: {fio_result["global options"]['runtime']} sec]\n"
-    sb_string += f"{'Date: ':>12}" + time.strftime("%Y-%m-%d %H:%M:%S") + "\n"
+def disk_warnings(disk):
+    """List the reasons a disk is a poor benchmark target."""
+    warnings = []
+    if disk['loop']:
+        warnings.append('loop device')
+    if disk['read_only']:
+        warnings.append('read-only')
+    elif any(fs['read_only'] for fs in disk['filesystems']):
+        warnings.append('mounted read-only')
+    if any(is_system_mount(fs['mountpoint']) for fs in disk['filesystems']):
+        warnings.append('system drive')
+    return warnings
 
-    if platform.system() == 'Windows':
-        sb_string += f"{'OS: ':>12}" + platform.system() + " " + \
-            platform.release()
-    else:
-        os_name = platform.freedesktop_os_release(
-        )['PRETTY_NAME'] + " " + platform.freedesktop_os_release()['BUILD_ID']
-        sb_string += f"{'OS: ':>12}" + os_name + \
-            " [" + platform.platform() + "]\n"
 
-    target_max_space, target_used_space, _ = get_drive_stats(
-        fio_result["global options"]['directory'])
-    sb_string += f"{'Target: ':>12}" + fio_result["global options"]['directory'] + \
-        f" {target_used_space/target_max_space:.0%} ({target_used_space/1024**3:.2f}/{target_max_space/1024**3:.2f} GiB)\n"
-    sb_string += f"{'Engine: ':>12}" + \
-        fio_result["global options"]['ioengine'] + "\n"
+def confirm_block_device(path, writes, allow_destructive, force, assume_yes):
+    """Make sure the user really means to benchmark a raw disk.
+
+    Raises PdmError unless the run is allowed and, if it writes, confirmed.
+    """
+    mounted = get_mounted_partitions(path)
+    if mounted and not force:
+        raise PdmError(f"'{path}' has mounted filesystems ({', '.join(mounted)}), "
+                       "unmount them or pass --force", EXIT_BAD_ARGS)
+    if not writes:
+        return
+    if not allow_destructive:
+        raise PdmError(f"Benchmarking '{path}' overwrites the data on it, "
+                       "pass --allow-destructive to do it anyway", EXIT_BAD_ARGS)
 
+    identity = get_device_identity(path)
+    capacity = get_device_capacity(identity['device']) if identity['device'] else None
+    description = ", ".join(part for part in (
+        identity['model'], format_size(capacity) if capacity else None) if part)
+    print(f"WARNING: All data on {path}{f' ({description})' if description else ''} "
+          "may be destroyed.", file=sys.stderr)
+    if assume_yes:
+        return
     try:
-        device_info = pathinfo(fio_result["global options"]['directory'])
-        sb_string += f"{'Device: ':>12}" + device_info['device'] + " " + device_info['fstype'] + "\n"
-    except Exception as e:
-        print(f"Error getting device info: {e}")
-        sb_string += f"{'Device: ':>12}unknown\n"
+        answer = input("Type YES to continue: ")
+    except EOFError:
+        answer = ''
+    if answer != 'YES':
+        raise PdmError("Aborted, the disk was not touched", EXIT_BAD_ARGS)
+
+
+def target_fingerprint(path):
+    """Identify a target across runs by its disk model and serial, else its path."""
+    identity = get_device_identity(path)
+    if identity['model'] and identity['serial']:
+        return hash_data(f"{identity['model']}:{identity['serial']}")
+    return hash_data(os.path.realpath(path))
+
+
+def get_mount(path):
+    """Find the mounted filesystem a path is on, or None."""
+    path = os.path.realpath(path)
+    best = None
+    for partition in psutil.disk_partitions(all=True):
+        mountpoint = partition.mountpoint
+        inside = path == mountpoint or path.startswith(mountpoint.rstrip(os.sep) + os.sep)
+        # The deepest mountpoint containing the path is the one it is on
+        if inside and (best is None or len(mountpoint) > len(best.mountpoint)):
+            best = partition
+    return best
+
+
+def get_os_name():
+    """Get a readable name of the operating system and its version."""
+    if platform.system() == 'Linux':
+        try:
+            return platform.freedesktop_os_release()['PRETTY_NAME']
+        except (OSError, KeyError):
+            pass
+    if platform.system() == 'Darwin':
+        return f"macOS {platform.mac_ver()[0]}"
+    return f"{platform.system()} {platform.release()}"
+
+
+def get_cpu_model():
+    """Get the CPU model name, falling back to what platform knows."""
     try:
-        i_type, i_gen, i_speed = get_disk_interface(device_info['device'])
-        sb_string += f"{'Interface: ':>12}" + i_type + " " + i_gen + " " + i_speed + "\n"
-    except Exception as e:
-        print(f"Error getting disk interface: {e}")
-        sb_string += f"{'Interface: ':>12}unknown\n"
+        with open('/proc/cpuinfo') as f:
+            for line in f:
+                if line.startswith('model name'):
+                    return line.split(':', 1)[1].strip()
+    except OSError:
+        pass
+    return platform.processor() or None
 
-    return sb_string
+
+def is_system_mount(mountpoint):
+    """Check whether a mountpoint is the volume the OS runs from."""
+    if platform.system() == 'Windows':
+        system_drive = os.environ.get('SystemDrive', 'C:')
+        return os.path.splitdrive(mountpoint)[0].upper() == system_drive.upper()
+    return mountpoint in ('/', '/boot', '/boot/efi', '/System/Volumes/Data')
+
+
+def warn_target_environment(system):
+    """Warn about targets whose results don't show what the disk can do."""
+    if system.get('network_filesystem'):
+        print(f"WARNING: The target is on a network filesystem ({system['filesystem']}), "
+              "the results measure the network and the server rather than a disk.",
+              file=sys.stderr)
+    if system.get('system_drive'):
+        print(f"WARNING: The target is on the system drive ({system['mountpoint']}), "
+              "background OS activity can skew the results, a longer --cooldown helps.",
+              file=sys.stderr)
+
+
+def collect_system_metadata(test_path, backend_version=None):
+    """Gather what is known about the machine and the target disk.
+
+    Everything is best effort, anything that can't be found is None.
+    """
+    def attempt(func, *args):
+        try:
+            return func(*args)
+        except Exception as e:
+            log.debug(f"Could not collect {func.__name__}: {e}")
+            return None
+
+    mount = attempt(get_mount, test_path)
+    identity = attempt(get_device_identity, test_path) or {}
+    device = identity.get('device')
+    return {
+        'os': attempt(get_os_name),
+        'kernel': platform.release() or None,
+        'cpu': attempt(get_cpu_model),
+        'ram': attempt(lambda: psutil.virtual_memory().total),
+        'filesystem': mount.fstype if mount else None,
+        'mount_options': mount.opts if mount else None,
+        'mountpoint': mount.mountpoint if mount else None,
+        'device': device,
+        'model': identity.get('model'),
+        'serial': identity.get('serial'),
+        'firmware': identity.get('firmware'),
+        'capacity': get_device_capacity(device) if device else None,
+        # Whether the device advertises discard (TRIM), None if unknown
+        'discard': get_discard_max_bytes(device) > 0
+        if device and get_discard_max_bytes(device) is not None else None,
+        'backend_version': backend_version,
+        'network_filesystem': bool(mount) and mount.fstype in NETWORK_FILESYSTEMS,
+        'system_drive': bool(mount) and is_system_mount(mount.mountpoint),
+    }
+
+
+def spprint_system_header(system, settings=None):
+    """Format the one line device summary shown above the results."""
+    settings = settings or {}
+    parts = [
+        system.get('model') or system.get('device') or
+        (f"Remote {system['remote']}" if system.get('remote') else 'Unknown device'),
+        format_size(system['capacity']) if system.get('capacity') else None,
+        system.get('filesystem'),
+        f"{settings['data_pattern']} data" if settings.get('data_pattern') else None,
+    ]
+    return ", ".join(part for part in parts if part)
 
 
 def disksinfo():
@@ -338,7 +5956,7 @@ def pathinfo(path):
     if not path.endswith(os.sep):
         path += os.sep
     if not os.path.exists(path):
-        print(f"Error: The specified path '{path}' does not exist.")
+        print(f"Error: The specified path '{path}' does not exist.", file=sys.stderr)
         return
     disks = disksinfo()
     for disk in disks:
@@ -513,20 +6131,1064 @@ def get_disk_interface(path):
     return (interface, gen, speed_str)
 
 
-def main():
-    # Check for fio dependency
-    if not check_fio_available():
-        print("Error: fio is not installed or not available in PATH.")
-        print("Please install fio before using this tool.")
-        return
+def run_target(backend, test_path, name, args, artifacts=None):
+    """Benchmark one target and save its raw and CDM8 reports under out/.
+
+    Returns (report, hash of the run, path of the CDM8 report).
+    """
+    test_hash = hash_data({
+        'platform': platform.system(),
+        'disk_name': name,
+        'test_path': test_path,
+        'date': time.strftime("%Y-%m-%d %H:%M:%S"),
+    })
+
+    if backend.remote:
+        # Nothing about the remote machine can be looked up from here
+        system = {'remote': backend.remote}
+    else:
+        system = collect_system_metadata(
+            test_path, get_fio_version() if backend.name == 'fio' else None)
+        if not args.no_warnings:
+            warn_target_environment(system)
+
+    smart_before = None
+    if system.get('device'):
+        smart_before = read_smart(system['device'])
+
+    started = time.monotonic()
+    if not args.quiet:
+        print(f"\nStarting {backend.name.upper()} Disk Speed Tests on {name}...\n")
+    report = backend.run(test_path, args, artifacts)
+    warn_cpu_bound(report.tests)
+    if args.mode == 'latency':
+        classify_latency(report.tests)
+    report.system = system
+    if smart_before:
+        smart_after = read_smart(system['device'])
+        if smart_after:
+            before, after = smart_snapshot(smart_before), smart_snapshot(smart_after)
+            report.smart = {'before': before, 'after': after,
+                            'delta': smart_delta(before, after)}
+    report.timing = {'elapsed_seconds': round(time.monotonic() - started, 1)}
+
+    try:
+        os.makedirs("out", exist_ok=True)
+    except Exception as e:
+        raise PdmError(f"Could not create the output directory: {e}", EXIT_IO_ERROR)
+
+    timestamp = time.strftime("%Y%m%d%H%M%S")
+
+    try:
+        with open(f"out/{backend.name}_result_{timestamp}_{test_hash}.json", 'w') as f:
+            json.dump(report.raw, f, indent=4)
+    except Exception as e:
+        raise PdmError(f"Could not save the test results: {e}", EXIT_IO_ERROR)
+
+    report_path = f"out/PDM_{timestamp}_{test_hash}.txt"
+    try:
+        with open(report_path, 'w') as f:
+            f.write(spprint_cdm8(report))
+    except Exception as e:
+        raise PdmError(f"Could not save the CDM8 formatted results: {e}", EXIT_IO_ERROR)
+    return report, test_hash, report_path
+
+
+def configure_fio_command(args):
+    """Set up fio_command from --fio-binary, --fio-arg and --fio-env."""
+    select_fio_binary(args.fio_binary or os.environ.get(FIO_BINARY_ENV),
+                      '--fio-binary' if args.fio_binary else FIO_BINARY_ENV)
+    fio_command['args'] = args.fio_arg
+    fio_command['env'] = dict(args.fio_env)
+
+
+def prepare_local_target(path, allow_block_device=False, allow_new_file=False):
+    """Check a local target path and return it absolute, directories ending in a separator.
+
+    With allow_new_file the path may name a file that doesn't exist yet, in an
+    existing directory.
+    """
+    test_path = os.path.abspath(path)
+    if not os.path.exists(test_path) and not allow_new_file:
+        raise PdmError(f"The specified path '{test_path}' does not exist", EXIT_BAD_ARGS)
+    error = validate_target(test_path, allow_block_device)
+    if error:
+        raise PdmError(error, EXIT_BAD_ARGS)
+    if os.path.isdir(test_path) and not test_path.endswith(os.sep):
+        test_path += os.sep
+    return test_path
+
 
+@dataclass
+class RunOptions:
+    """Options of run_benchmark(), the same as those of the command line.
+
+    The common ones are fields, any other option goes in argv the way it
+    would be written on the command line, like ['--retries', '3']. The config
+    file and the PYDISKMARK_* variables are not read.
+    """
+    preset: str = 'default'
+    backend: str = 'fio'
+    # Size of the test file, like 1g, None for what the preset uses
+    size: str = None
+    repeat: int = 1
+    # Hide the progress bar
+    quiet: bool = True
+    argv: list = field(default_factory=list)
+
+    def to_args(self, target):
+        """Turn the options into the arguments the backends read.
+
+        Raises PdmError with EXIT_BAD_ARGS instead of exiting like argparse.
+        """
+        parser = build_parser()
+
+        def fail(message):
+            raise PdmError(message, EXIT_BAD_ARGS)
+        parser.error = fail
+        argv = [target, '--preset', self.preset, '--backend', self.backend,
+                '--repeat', str(self.repeat)]
+        if self.size:
+            argv += ['--size', self.size]
+        if self.quiet:
+            argv.append('--quiet')
+        return parser.parse_args(argv + list(self.argv))
+
+
+def run_benchmark(target, options=None):
+    """Benchmark a local path and return its BenchmarkReport.
+
+    This is the way to use PyDiskMark from another program. Nothing is saved
+    and no report is printed, build_result_document() turns the report into
+    the document the command line saves and load_result_document() reads
+    one back. Raw block devices need '--allow-destructive' and '--yes' in
+    RunOptions.argv. Failures raise PdmError, its exit_code is the one the
+    command line would end with.
+
+    Reading the SEQ1M Q8T1 read speed of the quick preset:
+
+        import pdm
+        report = pdm.run_benchmark('/mnt/data', pdm.RunOptions(preset='quick'))
+        seq = next(test for test in report.tests
+                   if test['name'] == 'SEQ-R-1M-Q8-T1' and 'error' not in test)
+        print(f"{seq['speed_mbs']} MiB/s")
+    """
+    args = (options or RunOptions()).to_args(target)
+    quiet = console['quiet']
+    console['quiet'] = args.quiet
+    try:
+        if args.backend == 'fio':
+            configure_fio_command(args)
+        backend = BACKENDS[args.backend]()
+        error = backend.is_available()
+        if error:
+            raise PdmError(error, EXIT_MISSING_BACKEND)
+        test_path = prepare_local_target(target,
+                                         args.allow_block_device or args.allow_destructive,
+                                         backend.creates_target)
+        if args.shred:
+            check_shred_target(test_path)
+        if is_block_device(test_path):
+            confirm_block_device(test_path, backend.writes(test_path, args),
+                                 args.allow_destructive, args.force, args.yes)
+        report = backend.run(test_path, args)
+        report.system = collect_system_metadata(
+            test_path, get_fio_version() if backend.name == 'fio' else None)
+        return report
+    finally:
+        console['quiet'] = quiet
+
+
+class WatchTrend:
+    """Running statistics of the SEQ1M speeds of one target across --watch runs.
+
+    Only sums are kept, so hundreds of runs take no more memory than one.
+    """
+
+    def __init__(self):
+        self.stats = {direction: {'n': 0, 'first': None, 'last': None, 'min': None, 'max': None,
+                                  'sum_x': 0, 'sum_y': 0.0, 'sum_xx': 0, 'sum_xy': 0.0}
+                      for direction in ('read', 'write')}
+
+    def add(self, iteration, results):
+        for direction, speed in zip(('read', 'write'), headline_speeds(results)):
+            if speed == '-':
+                continue
+            speed = float(speed)
+            stats = self.stats[direction]
+            if stats['first'] is None:
+                stats['first'] = speed
+            stats['last'] = speed
+            stats['min'] = speed if stats['min'] is None else min(stats['min'], speed)
+            stats['max'] = speed if stats['max'] is None else max(stats['max'], speed)
+            stats['n'] += 1
+            stats['sum_x'] += iteration
+            stats['sum_y'] += speed
+            stats['sum_xx'] += iteration * iteration
+            stats['sum_xy'] += iteration * speed
+
+    def slope(self, direction):
+        """Least squares change in MB/s per run, None with fewer than two runs."""
+        stats = self.stats[direction]
+        n = stats['n']
+        denominator = n * stats['sum_xx'] - stats['sum_x'] ** 2
+        if n < 2 or not denominator:
+            return None
+        return (n * stats['sum_xy'] - stats['sum_x'] * stats['sum_y']) / denominator
+
+    def delta_line(self):
+        """One line with the latest speeds and how they compare to the first run."""
+        parts = []
+        for direction, stats in self.stats.items():
+            if stats['last'] is None:
+                continue
+            change = (stats['last'] - stats['first']) / stats['first'] * 100 \
+                if stats['first'] else 0.0
+            parts.append(f"{direction} {stats['last']:.2f} MB/s ({change:+.1f}%)")
+        return "SEQ1M " + ", ".join(parts) if parts else "no SEQ1M results"
+
+    def summary_lines(self):
+        lines = []
+        for direction, stats in self.stats.items():
+            if stats['last'] is None:
+                continue
+            slope = self.slope(direction)
+            trend = f", trend {slope:+.2f} MB/s per run" if slope is not None else ""
+            lines.append(f"SEQ1M {direction}: first {stats['first']:.2f}, last {stats['last']:.2f}, "
+                         f"min {stats['min']:.2f}, max {stats['max']:.2f} MB/s over "
+                         f"{stats['n']} run{'s' if stats['n'] > 1 else ''}{trend}")
+        return lines
+
+
+def watch_targets(targets, args, results_stream):
+    """Benchmark the targets every --watch seconds until Ctrl+C or --watch-count runs.
+
+    Every run goes to the history and, as one JSON line, to --output. A
+    line comparing it to the first run is printed after each one, and the
+    trend over all of them at the end.
+    """
+    trends = {name: WatchTrend() for _, _, name in targets}
+    exit_code = 0
+    iteration = 0
+    try:
+        while args.watch_count is None or iteration < args.watch_count:
+            iteration += 1
+            started = time.monotonic()
+            interrupted = False
+            for target_backend, test_path, name in targets:
+                try:
+                    report, test_hash, _ = run_target(target_backend, test_path, name, args)
+                except PdmError as e:
+                    print(f"\nError: {e}", file=sys.stderr)
+                    exit_code = e.exit_code
+                    continue
+                if report.partial:
+                    # The cut short run says nothing about the trend
+                    interrupted = True
+                    iteration -= 1
+                    break
+                document = build_result_document(report, args.tag)
+                document['watch'] = {'iteration': iteration, 'interval_seconds': args.watch}
+                if not args.no_history and not target_backend.remote:
+                    try:
+                        append_history(test_hash, target_fingerprint(test_path), document)
+                    except OSError as e:
+                        print(f"WARNING: Could not record the run in the history: {e}",
+                              file=sys.stderr)
+                if args.output:
+                    line = json.dumps(document) + "\n"
+                    try:
+                        if args.output == '-':
+                            results_stream.write(line)
+                            results_stream.flush()
+                        else:
+                            with open(args.output, 'a') as f:
+                                f.write(line)
+                    except OSError as e:
+                        print(f"Error saving results to '{args.output}': {e}", file=sys.stderr)
+                        return EXIT_IO_ERROR
+                trends[name].add(iteration, report.tests)
+                print(f"[run {iteration}] {name}: {trends[name].delta_line()}")
+            if interrupted or (args.watch_count is not None and iteration >= args.watch_count):
+                break
+            remaining = args.watch - (time.monotonic() - started)
+            if remaining > 0:
+                print(f"Next run in {format_duration(remaining)}, press Ctrl+C to stop.")
+                time.sleep(remaining)
+    except KeyboardInterrupt:
+        # Only reached between runs, a run itself stops through handle_interrupts
+        print(file=sys.stderr)
+
+    print(f"\nWatched {iteration} run{'s' if iteration != 1 else ''}:")
+    for name, trend in trends.items():
+        print(f"{name}:")
+        for line in trend.summary_lines() or ["no finished runs"]:
+            print(f"  {line}")
+    return exit_code
+
+
+# Options whose value is a file or directory, completed as such
+PATH_OPTIONS = {'target', 'path', 'config', 'output', 'log_file', 'artifacts', 'baseline',
+                'resume', 'chart', 'job_file_dir', 'fio_binary'}
+
+
+def parser_options(parser):
+    """Describe the options of a parser for the completion scripts."""
+    options = []
+    for action in parser._actions:
+        if not action.option_strings or action.help == argparse.SUPPRESS:
+            continue
+        takes_value = action.nargs != 0
+        choices = [str(choice) for choice in action.choices] if action.choices else None
+        options.append({
+            'flags': action.option_strings,
+            'takes_value': takes_value,
+            'choices': choices,
+            'paths': takes_value and not choices and action.dest in PATH_OPTIONS,
+            'repeatable': isinstance(action, argparse._AppendAction),
+            'help': action.help or '',
+        })
+    return options
+
+
+def bash_completion(parser):
+    """Build a bash completion script for the main command and subcommands."""
+    name = parser.prog
+    cases = []
+    for option in parser_options(parser):
+        pattern = '|'.join(option['flags'])
+        if option['choices']:
+            cases.append(f"        {pattern}) COMPREPLY=($(compgen -W "
+                         f"\"{' '.join(option['choices'])}\" -- \"$cur\")); return;;")
+        elif option['paths']:
+            cases.append(f"        {pattern}) COMPREPLY=($(compgen -f -- \"$cur\")); return;;")
+        elif option['takes_value']:
+            cases.append(f"        {pattern}) return;;")
+    flags = ' '.join(flag for option in parser_options(parser) for flag in option['flags'])
+    function = '_' + re.sub(r'\W', '_', name)
+    return f"""{function}() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+{chr(10).join(cases)}
+    esac
+    if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{' '.join(SUBCOMMANDS)}" -- "$cur") $(compgen -f -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -o filenames -F {function} {name}
+"""
+
+
+def zsh_completion(parser):
+    """Build a zsh completion script for the main command and subcommands."""
+    def text(value):
+        return re.sub(r"[\[\]:']", '', value)
+
+    specs = []
+    for option in parser_options(parser):
+        value = ''
+        if option['choices']:
+            value = f":value:({' '.join(option['choices'])})"
+        elif option['paths']:
+            value = ":path:_files"
+        elif option['takes_value']:
+            value = ":value: "
+        repeat = '*' if option['repeatable'] else ''
+        for flag in option['flags']:
+            specs.append(f"    '{repeat}{flag}[{text(option['help'])}]{value}'")
+    subcommands = ' '.join(SUBCOMMANDS)
+    specs.append(f"    \"1:target:_alternative 'subcommands:subcommand:({subcommands})' "
+                 f"'files:target:_files'\"")
+    return f"#compdef {parser.prog}\n\n_arguments -s \\\n" + " \\\n".join(specs) + "\n"
+
+
+def fish_completion(parser):
+    """Build a fish completion script for the main command and subcommands."""
+    def quote(value):
+        return "'" + value.replace('\\', '\\\\').replace("'", "\\'") + "'"
+
+    name = parser.prog
+    lines = [f"complete -c {name} -n __fish_use_subcommand -a {quote(subcommand)} "
+             f"-d {quote(description)}"
+             for subcommand, (_, description) in SUBCOMMANDS.items()]
+    for option in parser_options(parser):
+        line = f"complete -c {name}"
+        for flag in option['flags']:
+            line += f" -l {flag[2:]}" if flag.startswith('--') else f" -s {flag[1:]}"
+        if option['choices']:
+            line += f" -x -a {quote(' '.join(option['choices']))}"
+        elif option['paths']:
+            line += " -r -F"
+        elif option['takes_value']:
+            line += " -x"
+        lines.append(line + f" -d {quote(option['help'])}")
+    return "\n".join(lines) + "\n"
+
+
+def powershell_completion(parser):
+    """Build a PowerShell argument completer for the main command and subcommands."""
+    def quote(value):
+        return "'" + value.replace("'", "''") + "'"
+
+    options = parser_options(parser)
+    flags = ', '.join(quote(flag) for option in options for flag in option['flags'])
+    choices = '; '.join(
+        f"{quote(flag)} = @({', '.join(quote(choice) for choice in option['choices'])})"
+        for option in options if option['choices'] for flag in option['flags'])
+    subcommands = ', '.join(quote(subcommand) for subcommand in SUBCOMMANDS)
+    return f"""Register-ArgumentCompleter -Native -CommandName {quote(parser.prog)} -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $choices = @{{ {choices} }}
+    $words = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    $previous = if ($wordToComplete) {{ $words[-2] }} else {{ $words[-1] }}
+    if ($choices.ContainsKey($previous)) {{
+        $candidates = $choices[$previous]
+    }} elseif ($words.Count -le 2 -and -not $wordToComplete.StartsWith('-')) {{
+        $candidates = @({subcommands})
+    }} else {{
+        $candidates = @({flags})
+    }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+"""
+
+
+COMPLETION_SHELLS = {
+    'bash': bash_completion,
+    'zsh': zsh_completion,
+    'fish': fish_completion,
+    'powershell': powershell_completion,
+}
+
+
+def build_manpage(parser):
+    """Build a roff man page from the options of the main command."""
+    def roff(value):
+        value = value.replace('\\', '\\e').replace('-', '\\-')
+        # A line starting with . or ' would be read as a request
+        return '\\&' + value if value.startswith(('.', "'")) else value
+
+    name = parser.prog
+    lines = [f'.TH {roff(name.upper())} 1 "" "PyDiskMark" "User Commands"',
+             '.SH NAME', f"{roff(name)} \\- {roff(parser.description)}",
+             '.SH SYNOPSIS', f".B {roff(name)}", '[\\fIOPTIONS\\fR] [\\fITARGET\\fR]']
+    for subcommand in SUBCOMMANDS:
+        lines += ['.br', f".B {roff(name)} {subcommand}", '[\\fIARGS\\fR]']
+    lines += ['.SH OPTIONS']
+    for action in parser._actions:
+        if action.help == argparse.SUPPRESS:
+            continue
+        if action.option_strings:
+            term = ', '.join(f"\\fB{roff(flag)}\\fR" for flag in action.option_strings)
+            if action.nargs != 0:
+                metavar = action.metavar or (
+                    '{' + ','.join(str(choice) for choice in action.choices) + '}'
+                    if action.choices else action.dest.upper())
+                term += f" \\fI{roff(metavar)}\\fR"
+        else:
+            term = f"\\fI{roff(action.dest.upper())}\\fR"
+        lines += ['.TP', term, roff(action.help or '')]
+    lines += ['.SH COMMANDS']
+    for subcommand, (_, description) in SUBCOMMANDS.items():
+        lines += ['.TP', f"\\fB{subcommand}\\fR",
+                  roff(f"{description[0].upper()}{description[1:]}, see "
+                       f"'{name} {subcommand} -h'.")]
+    lines += ['.SH EXIT STATUS']
+    for code, meaning in ((0, 'Success'), (EXIT_BAD_ARGS, 'Bad arguments'),
+                          (EXIT_MISSING_BACKEND, 'The benchmark backend is missing'),
+                          (EXIT_IO_ERROR, 'An I/O error'),
+                          (EXIT_PARSE_ERROR, 'The results could not be parsed'),
+                          (EXIT_REGRESSION, 'compare found a regression'),
+                          (EXIT_BASELINE_FAILED, 'A baseline check failed'),
+                          (EXIT_HOOK_FAILED, 'A --pre-hook or --post-hook failed'),
+                          (EXIT_INTERRUPTED, 'The run was interrupted')):
+        lines += ['.TP', f"\\fB{code}\\fR", meaning]
+    return "\n".join(lines) + "\n"
+
+
+# Where option defaults are read from, the current directory comes first
+CONFIG_FILE_NAME = 'pydiskmark.toml'
+# Prefix of the environment variables setting option defaults, as in PYDISKMARK_COOLDOWN
+ENV_PREFIX = 'PYDISKMARK_'
+# Names the fio to run, read on its own since --fio-binary is in CLI_ONLY_OPTIONS
+FIO_BINARY_ENV = 'PYDISKMARK_FIO'
+# Options a config file or variable can't set, they lift the safety prompts or run
+# commands, and a pydiskmark.toml in whatever directory the tool runs from is read.
+# fio runs the commands of exec_prerun and the like in a job file or --set, and the
+# fio binary, its arguments and its environment (LD_PRELOAD) decide what runs too
+CLI_ONLY_OPTIONS = ('allow_destructive', 'yes', 'force', 'pre_hook', 'post_hook',
+                    'config', 'set', 'fio_binary', 'fio_arg', 'fio_env')
+
+
+def find_config_file():
+    """Find the config file with option defaults, or None."""
+    base = os.environ.get('XDG_CONFIG_HOME') or os.path.expanduser('~/.config')
+    for path in (CONFIG_FILE_NAME, os.path.join(base, 'pydiskmark', 'config.toml')):
+        if os.path.isfile(path):
+            return path
+    return None
+
+
+def option_actions(parser):
+    """Map the long option names of a parser, like no-history, to their actions."""
+    actions = {}
+    for action in parser._actions:
+        if isinstance(action, argparse._HelpAction):
+            continue
+        for flag in action.option_strings:
+            if flag.startswith('--'):
+                actions[flag[2:]] = action
+    return actions
+
+
+def convert_option_value(action, value, origin):
+    """Check and convert a config file or environment value the way argparse would."""
+    if action.dest in CLI_ONLY_OPTIONS:
+        raise PdmError(f"{origin}: {action.option_strings[-1]} can only be given on the "
+                       "command line", EXIT_BAD_ARGS)
+    if action.nargs == 0:
+        if isinstance(value, str):
+            value = {'1': True, 'true': True, 'yes': True, 'on': True, '0': False,
+                     'false': False, 'no': False, 'off': False}.get(value.strip().lower(), value)
+        if not isinstance(value, bool):
+            raise PdmError(f"{origin} must be true or false", EXIT_BAD_ARGS)
+        return action.const if value else action.default
+
+    def convert(item):
+        if isinstance(item, bool) or not isinstance(item, (str, int, float)):
+            raise PdmError(f"{origin} must be a string or a number", EXIT_BAD_ARGS)
+        try:
+            converted = action.type(str(item)) if action.type else str(item)
+        except (argparse.ArgumentTypeError, TypeError, ValueError) as e:
+            raise PdmError(f"{origin}: {e}", EXIT_BAD_ARGS)
+        if action.choices and converted not in action.choices:
+            raise PdmError(f"{origin} must be one of "
+                           f"{', '.join(str(choice) for choice in action.choices)}", EXIT_BAD_ARGS)
+        return converted
+
+    if isinstance(action, argparse._AppendAction):
+        return [convert(item) for item in (value if isinstance(value, list) else [value])]
+    if isinstance(value, list):
+        raise PdmError(f"{origin} can only be given once", EXIT_BAD_ARGS)
+    return convert(value)
+
+
+def resolve_options(parser, argv):
+    """Parse argv on top of the defaults from the config file and environment.
+
+    The command line wins over PYDISKMARK_* variables, which win over the
+    config file. Returns the arguments and the source of each value, one
+    of default, file, env or cli.
+    """
+    actions = option_actions(parser)
+    layers = []
+    path = find_config_file()
+    if path:
+        try:
+            with open(path, 'rb') as f:
+                data = tomllib.load(f)
+        except OSError as e:
+            raise PdmError(f"Could not read the config file '{path}': {e.strerror}",
+                           EXIT_IO_ERROR)
+        except tomllib.TOMLDecodeError as e:
+            raise PdmError(f"Could not parse the config file '{path}': {e}", EXIT_PARSE_ERROR)
+        values = {}
+        for key, value in data.items():
+            action = actions.get(key.replace('_', '-'))
+            if action is None:
+                raise PdmError(f"Unknown option '{key}' in the config file '{path}'",
+                               EXIT_BAD_ARGS)
+            values[action.dest] = convert_option_value(action, value, f"'{key}' in '{path}'")
+        layers.append(('file', values))
+    values = {}
+    for name, action in actions.items():
+        variable = ENV_PREFIX + name.upper().replace('-', '_')
+        if variable in os.environ:
+            values[action.dest] = convert_option_value(action, os.environ[variable], variable)
+    layers.append(('env', values))
+
+    # Without defaults the namespace only holds what the command line gave,
+    # which also keeps repeatable options from adding to the defaults
+    defaults = {action: action.default for action in parser._actions}
+    for action in defaults:
+        action.default = argparse.SUPPRESS
+    try:
+        given = vars(parser.parse_args(argv))
+    finally:
+        for action, default in defaults.items():
+            action.default = default
+
+    args, sources = {}, {}
+    for action, default in defaults.items():
+        if action.dest == argparse.SUPPRESS:
+            continue
+        args[action.dest], sources[action.dest] = default, 'default'
+        for source, values in layers:
+            if action.dest in values:
+                args[action.dest], sources[action.dest] = values[action.dest], source
+        if action.dest in given:
+            args[action.dest], sources[action.dest] = given[action.dest], 'cli'
+    return argparse.Namespace(**args), sources
+
+
+def config_main(argv):
+    """Entry point of the config subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} config",
+        description='Show the effective options after merging the config file '
+        f"({CONFIG_FILE_NAME} or ~/.config/pydiskmark/config.toml), {ENV_PREFIX}* "
+        'environment variables and any options given after "show".')
+    parser.add_argument('action', choices=['show'], help='What to do')
+    parser.add_argument('options', nargs=argparse.REMAINDER,
+                        help='Options of the main command to merge in')
+    args = parser.parse_args(argv)
+
+    main_parser = build_parser()
+    try:
+        options, sources = resolve_options(main_parser, args.options)
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+    print(f"Config file: {find_config_file() or 'none'}")
+    print(f"{'Option':<24} {'Source':<8} Value")
+    print("-" * 60)
+    names = {action.dest: name for name, action in reversed(option_actions(main_parser).items())}
+    for dest, value in vars(options).items():
+        print(f"{names.get(dest, dest):<24} {sources[dest]:<8} {json.dumps(value, default=str)}")
+    return 0
+
+
+def build_parser():
+    """Build the parser of the main command, which runs the benchmark."""
+    parser = argparse.ArgumentParser(
+        description='PyDiskMark - A simple disk speed testing tool using fio.',
+        epilog="Subcommands, run with -h for their options: " +
+        ", ".join(f"{name} ({description})" for name, (_, description) in SUBCOMMANDS.items()) +
+        ".")
+    parser.add_argument('target', type=str, nargs='?',
+                        help='Path to the directory to test (same as --path)')
+    parser.add_argument('-p', '--path', type=str, action='append',
+                        help='Path to the directory to test, can be repeated or a comma '
+                        'separated list to benchmark several targets one after another')
+    parser.add_argument('--allow-block-device', action='store_true',
+                        help='Allow the target path to be a block device')
+    parser.add_argument('--allow-destructive', action='store_true',
+                        help='Allow writing to a block device target, destroying its data')
+    parser.add_argument('-y', '--yes', action='store_true',
+                        help='Start without confirming the estimated duration or writing '
+                        'to a block device')
+    parser.add_argument('-b', '--backend', choices=BACKENDS,
+                        help='Tool used to run the benchmark (default: fio, '
+                        'falling back to native when fio is missing)')
+    config_group = parser.add_mutually_exclusive_group()
+    config_group.add_argument('-c', '--config', type=str,
+                              help='fio job file to run instead of a built-in '
+                              'preset, or - to read it from stdin')
+    config_group.add_argument('--preset', choices=PRESETS, default='default',
+                              help='Built-in fio job file to run (default: default)')
+    parser.add_argument('--list-presets', action='store_true',
+                        help='List the built-in presets and exit')
+    parser.add_argument('-j', '--jobs', type=str,
+                        help='Comma separated fio jobs to run, glob patterns '
+                        'like seq* are matched case-insensitively')
+    parser.add_argument('--list-jobs', action='store_true',
+                        help='List the jobs of the selected fio job file and exit')
+    parser.add_argument('--mode', choices=['read', 'write', 'readwrite', 'latency'],
+                        default='readwrite',
+                        help='Run only the read or only the write tests, or latency for a '
+                        f'~{LATENCY_SECONDS}s 4K QD1 random read latency check (default: readwrite)')
+    parser.add_argument('--ioengine', default='auto',
+                        help='fio I/O engine for every job, auto picks the best one fio '
+                        'supports (default: auto)')
+    parser.add_argument('--mixed', type=mixed_percent_arg, action='append', metavar='READ_PCT',
+                        help='Add a random 4K Q32T1 job mixing reads and writes with this '
+                        'share of reads (1-99), can be repeated for several mixes')
+    parser.add_argument('--fsync-test', action='store_true',
+                        help='Add a test of 4K writes each followed by an fsync, reporting '
+                        'how long the fsyncs take as databases see it')
+    parser.add_argument('--steady-state', action='store_true',
+                        help='Run the sequential write job until its bandwidth settles (up to '
+                        '30 minutes) and report the peak and steady state speed, to catch SSDs '
+                        'slowing down once their cache is full')
+    parser.add_argument('--trim-test', action='store_true',
+                        help='Add a test discarding (TRIMming) the test range of a block device, '
+                        'DESTRUCTIVE, needs --allow-destructive like other raw device writes')
+    parser.add_argument('--set', action='append', default=[], metavar='[SECTION.]KEY=VALUE',
+                        help="Override a fio option after loading the config, in [global] "
+                        "without a section or in every job with '*.', can be repeated")
+    parser.add_argument('--sweep', type=sweep_arg, action='append',
+                        help='Replace the jobs with one per value, e.g. bs=4k,64k,1m or '
+                        'qd=1,4,32, give both for every combination (fio backend)')
+    parser.add_argument('--sweep-pattern', choices=list(SWEEP_BASE), default='seq',
+                        help='Sweep sequential or random I/O (default: seq)')
+    parser.add_argument('--regions', type=positive_int_arg, metavar='N',
+                        help='Run the sequential jobs at N evenly spaced places from the start '
+                        'to the end of a block device target, to see how HDDs and SMR drives '
+                        'slow down across the disk')
+    parser.add_argument('--remote', action='append', metavar='HOST[:PORT]',
+                        help='Run the fio jobs on the fio --server at this host instead of '
+                        'here, the path is then on that host, can be repeated')
+    parser.add_argument('--dry-run', action='store_true',
+                        help='Print what would be run (the fio job file) and exit')
+    parser.add_argument('--drop-caches', action='store_true',
+                        help='Drop the Linux page cache before every read test, needs root')
+    parser.add_argument('--fio-binary', metavar='PATH',
+                        help=f'The fio to run instead of the one found on PATH, also read '
+                        f'from {FIO_BINARY_ENV}')
+    parser.add_argument('--fio-arg', type=fio_arg_arg, action='append', default=[],
+                        metavar='ARG',
+                        help='Pass an argument on to fio after our own, like '
+                        '--fio-arg=--cpus_allowed=0-3, can be repeated')
+    parser.add_argument('--fio-env', type=fio_env_arg, action='append', default=[],
+                        metavar='KEY=VALUE',
+                        help='Set an environment variable for fio, like TMPDIR or LD_PRELOAD, '
+                        'can be repeated')
+    parser.add_argument('--keep-job-files', action='store_true',
+                        help='Keep the generated fio job files for debugging, in the temp '
+                        'directory unless --job-file-dir is given')
+    parser.add_argument('--job-file-dir', metavar='DIR',
+                        help='Write the fio job files to DIR and keep them there, instead '
+                        'of passing them to fio on stdin')
+    parser.add_argument('--keep-data', action='store_true',
+                        help='Keep the fio data files or the native test file on the '
+                        'target after the run')
+    parser.add_argument('--shred', action='store_true',
+                        help='Overwrite the test data with zeros before deleting it and '
+                        'punch holes where the filesystem can, so no recognizable data '
+                        'is left in free space. Not for block devices')
+    parser.add_argument('--data-pattern', type=data_pattern_arg, default='random',
+                        metavar='zero|random|compressible:PCT',
+                        help='What the written data looks like, random data keeps compressing '
+                        'and deduplicating drives honest (default: random)')
+    parser.add_argument('--prepare-file', action='store_true',
+                        help='Lay out the fio data files before the first job and keep '
+                        'them, so later runs of the same size reuse them')
+    parser.add_argument('--cleanup', action='store_true',
+                        help='Delete the files of --prepare-file after the run anyway')
+    parser.add_argument('--log-level', default='warning', choices=LOG_LEVELS,
+                        help='Minimum level of log messages to show (default: warning)')
+    parser.add_argument('--log-filter', type=log_filter_arg, metavar='RULES',
+                        help='Levels per logger like warning,pdm=debug,git=error, the most '
+                        'specific logger name wins and a bare level replaces --log-level')
+    parser.add_argument('--log-format', default='text', choices=['text', 'json'],
+                        help='Write log messages as text or as JSON lines (default: text)')
+    parser.add_argument('--log-buffer', type=non_negative_int_arg, default=LOG_BUFFER_KIB,
+                        metavar='KIB',
+                        help='Keep the last KIB KiB of the log, down to debug messages, and '
+                        f'add them to the JSON --output, 0 to leave it out '
+                        f'(default: {LOG_BUFFER_KIB})')
+    parser.add_argument('--log-timestamps', choices=['utc', 'local', 'elapsed', 'none'],
+                        help='Time of each log message as UTC or local time, as seconds '
+                        'since the start, or none (default: utc for JSON, none for text)')
+    parser.add_argument('--color', choices=['auto', 'always', 'never'], default='auto',
+                        help='Use colors and progress bars: auto only on a terminal and '
+                        'without NO_COLOR set (default: auto)')
+    parser.add_argument('--log-file', type=str,
+                        help='Append log messages to this file')
+    parser.add_argument('--log-max-size', type=log_size_arg, metavar='SIZE',
+                        help='Rotate the log file once it grows past SIZE, like 10m, '
+                        'renaming it to .1, .2 and so on')
+    parser.add_argument('--log-keep', type=positive_int_arg, metavar='N',
+                        help=f'How many rotated log files to keep (default: {LOG_KEEP})')
+    parser.add_argument('--log-target', choices=['console', 'file', 'both'],
+                        help='Where log messages go (default: both with --log-file, '
+                        'else console)')
+    parser.add_argument('--no-warnings', action='store_true',
+                        help='Do not warn about network filesystem or system drive targets, '
+                        'the results still record them')
+    parser.add_argument('-q', '--quiet', action='store_true',
+                        help='No progress bars, summary or log messages below warning')
+    parser.add_argument('--tui', action='store_true',
+                        help='Show the results in a full screen grid as the tests finish')
+    parser.add_argument('--no-summary', action='store_true',
+                        help='Do not print the summary table after the run')
+    parser.add_argument('-o', '--output', type=str,
+                        help="Write the results to this file, '-' for stdout")
+    parser.add_argument('--format', choices=['json', 'csv', 'markdown', 'html', 'cdm-text'],
+                        default='json',
+                        help='Format of the --output file (default: json)')
+    parser.add_argument('--append', action='store_true',
+                        help='Append to the --output CSV file, writing the header only '
+                        'if the file is empty')
+    parser.add_argument('--force', action='store_true',
+                        help='Overwrite the --output file if it already exists, let the '
+                        'native backend overwrite an existing file, or test a block device '
+                        'with mounted filesystems')
+    parser.add_argument('--export', type=export_arg, action='append', default=[],
+                        help='Also write the results as Prometheus metrics, to a textfile '
+                        'collector file with prometheus:<path> or to a Pushgateway with '
+                        'pushgateway:<url>, can be repeated')
+    parser.add_argument('--chart', type=chart_arg, metavar='PATH',
+                        help='Also draw the results as a bar chart, SVG or PNG by the '
+                        'extension, numbered per target when testing several')
+    parser.add_argument('--chart-log', action='store_true',
+                        help='Use a logarithmic MB/s scale in the --chart')
+    parser.add_argument('--push-job', default='pydiskmark',
+                        help='Job label of the metrics pushed to a Pushgateway '
+                        '(default: pydiskmark)')
+    parser.add_argument('--desktop-notify', action='store_true',
+                        help='Show a desktop notification with the headline numbers '
+                        'when the run is over')
+    parser.add_argument('--baseline', type=str,
+                        help='TOML or JSON file with the minimum MB/s and IOPS per test, '
+                        f'the run exits with {EXIT_BASELINE_FAILED} if any is missed')
+    parser.add_argument('--artifacts', type=str, metavar='DIR',
+                        help='Keep the job files, raw fio output, log and report of the '
+                        'run in a new timestamped directory under DIR')
+    parser.add_argument('--tag', action='append', default=[],
+                        help='Label stored with the results, can be given several times')
+    parser.add_argument('--no-history', action='store_true',
+                        help=f'Do not record the run in {get_history_file()}')
+    parser.add_argument('-s', '--size', type=size_arg,
+                        help='Size of the test file, e.g. 256M, 4G or 512MiB '
+                        f'(minimum {format_size(MIN_TEST_SIZE)})')
+    parser.add_argument('--duration', type=positive_int_arg,
+                        help='Run every test for this many seconds instead of a fixed '
+                        'amount of data, per pass with the native backend')
+    parser.add_argument('--ignore-free-space', action='store_true',
+                        help='Run even if the target seems to lack the free space the test needs')
+    parser.add_argument('--block-size', type=block_size_arg, default=1024**2,
+                        help='Block size of the sequential tests (native backend, default: 1M)')
+    parser.add_argument('--threads', type=positive_int_arg, default=1, metavar='N',
+                        help='Threads doing I/O at once in the SEQ and RND tests, for drives '
+                        'one thread can\'t keep busy (native backend, default: 1)')
+    parser.add_argument('--continue-on-error', action='store_true',
+                        help='Skip fio jobs and native passes that fail instead of aborting '
+                        'the run')
+    parser.add_argument('--retries', type=non_negative_int_arg, default=1, metavar='N',
+                        help='Try a failing fio job again up to N times after the cooldown '
+                        'before giving up on it (default: 1)')
+    parser.add_argument('--resume', type=str, metavar='STATEFILE',
+                        help='Continue an interrupted fio run from the state file it left, '
+                        'skipping the jobs that already finished')
+    parser.add_argument('--pre-hook', type=str, metavar='CMD',
+                        help='Shell command to run before every job, with PYDISKMARK_JOB, '
+                        'PYDISKMARK_TARGET and PYDISKMARK_PHASE set')
+    parser.add_argument('--post-hook', type=str, metavar='CMD',
+                        help='Shell command to run after every job, like --pre-hook plus '
+                        'PYDISKMARK_RESULT_JSON naming a file with the results of the job')
+    parser.add_argument('--hook-policy', choices=['abort', 'warn'], default='warn',
+                        help='Whether a failing hook aborts the run or only warns (default: warn)')
+    parser.add_argument('--fail-fast', action='store_true',
+                        help='Stop at the first target that fails instead of moving on '
+                        'to the next one')
+    parser.add_argument('--timeout', type=positive_int_arg,
+                        help='Kill a fio job that runs longer than this many seconds')
+    parser.add_argument('--cooldown', type=non_negative_int_arg, default=5,
+                        help='Seconds to wait between jobs (default: 5)')
+    parser.add_argument('--wait-idle', type=positive_int_arg, nargs='?', const=5,
+                        metavar='SECONDS',
+                        help='Before every test wait until the disk saw no other I/O for '
+                        'this many seconds in a row (default: 5), at most 5 minutes')
+    parser.add_argument('--thermal-limit', type=float, metavar='CELSIUS',
+                        help='Wait between tests until the disk is at most this warm, '
+                        'where its temperature can be read')
+    parser.add_argument('--watch', type=interval_arg, metavar='INTERVAL',
+                        help='Run the benchmark again every INTERVAL (e.g. 30m) until Ctrl+C, '
+                        'printing how it compares to the first run')
+    parser.add_argument('--watch-count', type=positive_int_arg, metavar='N',
+                        help='Stop watching after N runs')
+    parser.add_argument('--repeat', type=positive_int_arg, default=1,
+                        help='Run the whole suite this many times and report mean and '
+                        'standard deviation (default: 1)')
+    parser.add_argument('--runs', type=positive_int_arg, default=5,
+                        help='Measured passes per test (native backend, default: 5)')
+    parser.add_argument('--warmup', type=non_negative_int_arg, default=1,
+                        help='Unmeasured warmup passes per test (native backend, default: 1)')
+    return parser
+
+
+def completions_main(argv):
+    """Entry point of the completions subcommand."""
+    parser = argparse.ArgumentParser(
+        prog=f"{os.path.basename(sys.argv[0])} completions",
+        description='Print a shell completion script, e.g. '
+        f"'{os.path.basename(sys.argv[0])} completions bash > "
+        f"/etc/bash_completion.d/{os.path.basename(sys.argv[0])}'.")
+    parser.add_argument('shell', choices=COMPLETION_SHELLS, help='Shell to complete for')
+    args = parser.parse_args(argv)
+    print(COMPLETION_SHELLS[args.shell](build_parser()), end="")
+    return 0
+
+
+def manpage_main(argv):
+    """Entry point of the manpage subcommand."""
     parser = argparse.ArgumentParser(
-        description='PyDiskMark - A simple disk speed testing tool using fio.')
-    parser.add_argument('-p', '--path', type=str,
-                        help='Path to the directory to test')
-    args = parser.parse_args()
+        prog=f"{os.path.basename(sys.argv[0])} manpage",
+        description='Print a man page in roff format, e.g. '
+        f"'{os.path.basename(sys.argv[0])} manpage | man -l -'.")
+    parser.parse_args(argv)
+    print(build_manpage(build_parser()), end="")
+    return 0
 
-    test_path = ''
+
+# The main command takes a bare target, so the subcommands are dispatched by
+# hand before it is parsed
+SUBCOMMANDS = {
+    'compare': (compare_main, 'compare two results files'),
+    'history': (history_main, 'list past runs'),
+    'score': (score_main, 'score a results file'),
+    'import': (import_main, 'convert CrystalDiskMark results for compare'),
+    'disks': (disks_main, 'list candidate targets'),
+    'completions': (completions_main, 'print a shell completion script'),
+    'manpage': (manpage_main, 'print a man page'),
+    'config': (config_main, 'show the options merged from the config file and environment'),
+}
+
+
+def main():
+    if len(sys.argv) > 1 and sys.argv[1] in SUBCOMMANDS:
+        return SUBCOMMANDS[sys.argv[1]][0](sys.argv[2:])
+
+    parser = build_parser()
+    try:
+        args, _ = resolve_options(parser, sys.argv[1:])
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+    console['color'] = args.color
+    console['quiet'] = args.quiet
+    # Quiet runs still show warnings and errors
+    log_level = 'warning' if args.quiet and args.log_level in ('debug', 'info') else args.log_level
+    try:
+        if args.log_max_size and not args.log_file:
+            parser.error("--log-max-size needs --log-file")
+        if args.log_keep and not args.log_max_size:
+            parser.error("--log-keep only applies with --log-max-size")
+        setup_logging(log_level, args.log_format, args.log_file, args.log_target,
+                      args.log_max_size, args.log_keep or LOG_KEEP, args.log_filter or (),
+                      args.log_timestamps)
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+    # Whoever gets the results JSON can read how the run went in it
+    log_buffer = None
+    if args.output and args.format == 'json' and args.log_buffer and not args.watch:
+        log_buffer = LogRingBuffer(args.log_buffer * 1024)
+        log_buffer.setFormatter(ColorLogFormatter(False, args.log_timestamps or 'utc'))
+        attach_log_handler(log_buffer)
+    if args.list_presets:
+        for name, (_, description) in PRESETS.items():
+            print(f"{name:<10} {description}")
+        return 0
+    if args.list_jobs:
+        try:
+            config = load_fio_config(args.config or PRESETS[args.preset][0])
+        except PdmError as e:
+            print(f"Error: {e}", file=sys.stderr)
+            return e.exit_code
+        for job in get_fio_jobs(config):
+            print(job)
+        return 0
+    if args.target and args.path:
+        parser.error("the target path was given both positionally and with --path")
+    args.path = [path for paths in args.path or [args.target] if paths
+                 for path in paths.split(',') if path]
+
+    if args.sweep and args.backend == 'native':
+        parser.error("--sweep needs the fio backend")
+    if args.steady_state and args.backend == 'native':
+        parser.error("--steady-state needs the fio backend")
+    if args.regions is not None and args.regions < 2:
+        parser.error("--regions needs at least 2 regions to compare")
+    if args.regions and args.backend == 'native':
+        parser.error("--regions needs the fio backend")
+    if args.regions and args.remote:
+        parser.error("--regions can't find the size of the devices of a --remote host")
+    if args.remote and args.backend == 'native':
+        parser.error("--remote needs the fio backend")
+    if args.resume and args.backend == 'native':
+        parser.error("--resume needs the fio backend")
+    if args.prepare_file and args.backend == 'native':
+        parser.error("--prepare-file needs the fio backend")
+    if args.mixed and args.backend == 'native':
+        parser.error("--mixed needs the fio backend")
+    if args.threads > 1 and args.backend != 'native':
+        parser.error("--threads is for the native backend, fio jobs take their thread "
+                     "count from numjobs, e.g. --set '*.numjobs=4'")
+    if (args.fio_arg or args.fio_env) and args.backend == 'native':
+        parser.error("--fio-arg and --fio-env need the fio backend")
+    if args.mode == 'latency' and (args.config or args.sweep or args.jobs or args.mixed or
+                                   args.fsync_test):
+        parser.error("--mode latency runs its own job, it can't be combined with "
+                     "--config, --sweep, --jobs, --mixed or --fsync-test")
+    if args.cleanup and not args.prepare_file:
+        parser.error("--cleanup only applies to --prepare-file")
+    if args.cleanup and args.keep_data:
+        parser.error("--cleanup and --keep-data contradict each other")
+    if args.shred and (args.keep_data or (args.prepare_file and not args.cleanup)):
+        parser.error("--shred deletes the test data, it can't be kept with --keep-data "
+                     "or --prepare-file without --cleanup")
+    if args.shred and args.remote:
+        parser.error("--shred can't reach the data files on remote hosts")
+    if args.remote and not args.path:
+        parser.error("--remote needs the path to test on the remote hosts")
+    if args.sweep and len(dict(args.sweep)) > 1:
+        sweeps = dict(args.sweep)
+        log.warning(f"Sweeping bs and qd together runs every combination, "
+                    f"{len(sweeps['bs']) * len(sweeps['qd'])} points per direction")
+
+    # Check for the backend dependency
+    if args.backend != 'native':
+        try:
+            configure_fio_command(args)
+        except PdmError as e:
+            print(f"Error: {e}", file=sys.stderr)
+            return e.exit_code
+    backend = BACKENDS[args.backend or 'fio']()
+    error = None if args.dry_run else backend.is_available()
+    if error and args.backend is None and \
+            not (args.config or args.sweep or args.remote or args.resume or args.prepare_file or
+                 args.mixed or args.regions or args.steady_state or args.fio_arg or
+                 args.fio_env):
+        # Only switch backends when the user didn't ask for fio or fio only features
+        print(f"WARNING: {error} Falling back to the native backend.", file=sys.stderr)
+        backend = NativeBackend()
+    elif error:
+        print(f"Error: {error}", file=sys.stderr)
+        print(backend.install_hint(), file=sys.stderr)
+        return EXIT_MISSING_BACKEND
+
+    if args.duration and args.size and backend.name == 'fio':
+        parser.error("--duration and --size can't be combined with the fio backend")
+    if args.append and args.format != 'csv':
+        parser.error("--append only works with --format csv")
+    if args.watch_count and not args.watch:
+        parser.error("--watch-count needs --watch")
+    if args.watch and (args.tui or args.resume or args.baseline or args.export or
+                       args.artifacts or args.chart):
+        parser.error("--watch can't be combined with --tui, --resume, --baseline, --export, "
+                     "--artifacts or --chart")
+    if args.watch and args.output and args.format != 'json':
+        parser.error("--watch writes JSON lines, --format must be json")
+    if args.tui and curses is None:
+        parser.error("--tui needs the curses module, which this Python doesn't have")
+    if args.tui and (args.output == '-' or not sys.stdout.isatty()):
+        parser.error("--tui needs a terminal on stdout")
+    baseline = None
+    if args.baseline:
+        try:
+            baseline = load_baseline(args.baseline)
+        except PdmError as e:
+            print(f"Error: {e}", file=sys.stderr)
+            return e.exit_code
+    # With --output - stdout carries nothing but the results
+    results_stream = sys.stdout
+    if args.output == '-':
+        sys.stdout = sys.stderr
+    if args.output and args.output != '-' and os.path.exists(args.output) \
+            and not (args.force or args.append):
+        print(
+            f"Error: Output file '{args.output}' already exists, use --force to overwrite it.")
+        return EXIT_BAD_ARGS
+
+    targets = []
     if not args.path:
 
         # Detect available disks
@@ -572,60 +7234,252 @@ def main():
         else:
             test_path = f"{selected_disk['path']}/"
         # print(test_path)
+
+        error = validate_target(selected_disk['path'])
+        if error:
+            print(f"Error: {error}.", file=sys.stderr)
+            return EXIT_BAD_ARGS
+        targets.append((backend, test_path, selected_disk['name']))
+    elif args.remote:
+        # The paths are on the remote hosts, there is nothing to check here
+        for remote in args.remote:
+            for test_path in args.path:
+                if not args.quiet:
+                    print(f"\nUsing remote path: {remote}:{test_path}")
+                targets.append((FioBackend(remote), test_path, f"{remote}:{test_path}"))
     else:
-        test_path = args.path
-        # make sure the path is absolute
-        test_path = os.path.abspath(test_path)
-        # make sure path ends with a slash
-        if not test_path.endswith(os.sep):
-            test_path += os.sep
-        # check if the path exists
-        if not os.path.exists(test_path):
-            print(f"Error: The specified path '{test_path}' does not exist.")
-            return
-        print(f"\nUsing custom path: {test_path}")
+        for test_path in args.path:
+            try:
+                test_path = prepare_local_target(
+                    test_path, args.allow_block_device or args.allow_destructive,
+                    backend.creates_target)
+                if args.shred:
+                    check_shred_target(test_path)
+            except PdmError as e:
+                print(f"Error: {e}.", file=sys.stderr)
+                return e.exit_code
+            if not args.quiet:
+                print(f"\nUsing custom path: {test_path}")
+            targets.append((backend, test_path, test_path))
+    if args.resume and len(targets) > 1:
+        parser.error("--resume continues a single target")
 
-    test_hash = hash_data({
-        'platform': platform.system(),
-        'disk_name': selected_disk['name'] if 'selected_disk' in locals() else 'Custom Path',
-        'test_path': test_path,
-        'date': time.strftime("%Y-%m-%d %H:%M:%S"),
-    })
+    if args.dry_run:
+        try:
+            for target_backend, test_path, _ in targets:
+                print(target_backend.dry_run(test_path, args), end="")
+        except PdmError as e:
+            print(f"Error: {e}", file=sys.stderr)
+            return e.exit_code
+        return 0
 
+    estimates = []
     try:
-        print(
-            f"\nStarting FIO Disk Speed Tests on {selected_disk['name'] if 'selected_disk' in locals() else test_path}...\n")
-        test_result = run_fio_test(test_path)
+        for target_backend, test_path, _ in targets:
+            if not target_backend.remote and is_block_device(test_path):
+                confirm_block_device(test_path, target_backend.writes(test_path, args),
+                                     args.allow_destructive, args.force, args.yes)
+            estimates.append(target_backend.estimate_runtime(test_path, args))
+    except PdmError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return e.exit_code
+    estimate = sum(seconds for seconds, _ in estimates)
+    unknown = sum(count for _, count in estimates)
+    estimate_text = f"Estimated duration: ~{format_duration(estimate)}"
+    if unknown:
+        estimate_text += f" plus {unknown} test{'s' if unknown > 1 else ''} of unknown length"
+    if not args.yes and sys.stdin.isatty():
+        input(f"{estimate_text} \u2014 press Enter to start or pass --yes to skip")
+    elif not args.quiet:
+        print(estimate_text)
 
-    finally:
+    if args.watch:
+        return watch_targets(targets, args, results_stream)
+
+    artifacts = None
+    if args.artifacts:
+        artifacts = os.path.join(args.artifacts, time.strftime("pydiskmark-%Y%m%d-%H%M%S"))
         try:
-            os.makedirs("out", exist_ok=True)
-        except Exception as e:
-            print(f"Error creating output directory: {e}")
-            return
+            os.makedirs(artifacts, exist_ok=True)
+            for t in range(len(targets) if len(targets) > 1 else 0):
+                os.makedirs(os.path.join(artifacts, f"target-{t + 1}"), exist_ok=True)
+            # The whole log, whatever the console shows, to make sense of the results
+            tee_log_file(os.path.join(artifacts, 'run.log'), 'debug', args.log_format,
+                         args.log_timestamps)
+        except (OSError, PdmError) as e:
+            print(f"Error creating the artifacts directory: {e}", file=sys.stderr)
+            return EXIT_IO_ERROR
 
-        timestamp = time.strftime("%Y%m%d%H%M%S")
+    runs = []
+    failures = []
+    exit_code = 0
+    run_started = time.monotonic()
+    tui = Tui() if args.tui else None
+    with tui or contextlib.nullcontext():
+        for t, ((target_backend, test_path, name), (seconds, count)) in \
+                enumerate(zip(targets, estimates)):
+            target_artifacts = artifacts
+            if artifacts and len(targets) > 1:
+                target_artifacts = os.path.join(artifacts, f"target-{t + 1}")
+            if tui:
+                tui.set_target(name)
+            try:
+                report, test_hash, report_path = run_target(target_backend, test_path, name, args,
+                                                            target_artifacts)
+            except PdmError as e:
+                print(f"\nError: {e}", file=sys.stderr)
+                exit_code = e.exit_code
+                failures.append((name, str(e)))
+                if args.fail_fast:
+                    break
+                continue
+            report.timing['estimated_seconds'] = round(seconds, 1)
+            report.timing['unknown_tests'] = count
+            document = build_result_document(report, args.tag)
+            if baseline:
+                document['checks'] = check_baseline(baseline, report.tests)
+            runs.append((report, document, name))
+            if tui:
+                tui.add_document(document)
 
-        try:
-            with open(f"out/fio_result_{timestamp}_{test_hash}.json", 'w') as f:
-                json.dump(test_result, f, indent=4)
-        except Exception as e:
-            print(f"Error saving test results: {e}")
-            return
+            # A cut short run would skew the history, and remote disks can't be fingerprinted
+            if not args.no_history and not report.partial and not target_backend.remote:
+                try:
+                    append_history(test_hash, target_fingerprint(test_path), document)
+                except OSError as e:
+                    # Losing the history entry shouldn't fail a finished run
+                    print(f"WARNING: Could not record the run in the history: {e}", file=sys.stderr)
 
-        parsed = parse_fio_results(test_result)
+            if not (args.no_summary or args.quiet):
+                print()
+                print(spprint_system_header(report.system, report.settings))
+                print(spprint_summary_table(report.tests))
+                if spprint_bandwidth_series(report.tests):
+                    print(spprint_bandwidth_series(report.tests))
+                if spprint_latency(report.tests):
+                    print(spprint_latency(report.tests))
+                if spprint_fsync(report.tests):
+                    print(spprint_fsync(report.tests))
+                if spprint_steady_state(report.tests):
+                    print(spprint_steady_state(report.tests))
+                if 'score' in document:
+                    print(f"Score: {document['score']:.0f}\n")
+                if report.smart and spprint_smart_delta(report.smart['delta']):
+                    print(spprint_smart_delta(report.smart['delta']) + "\n")
+                if report.sweep:
+                    print(spprint_sweep_table(report.sweep))
+                if report.regions:
+                    print(spprint_region_table(report.regions), end="")
+                print(f"Full report saved to {report_path}")
+            if baseline:
+                # Acceptance runs want the verdict even when quiet
+                print(f"\nBaseline checks for {test_path}:")
+                print(spprint_checks(document['checks']), end="")
+
+            if report.partial:
+                # Ctrl+C stops the remaining targets too
+                break
+        if tui:
+            tui.wait_until_closed()
+    if args.desktop_notify:
+        send_desktop_notification(*build_run_summary(
+            [(name, document['results'], None) for _, document, name in runs] +
+            [(name, None, error) for name, error in failures],
+            time.monotonic() - run_started, any(report.partial for report, _, _ in runs)))
 
-        cdm8_res = spprint_fio_to_cdm8(parsed, test_result)
+    if not runs:
+        if artifacts:
+            print(f"\nArtifacts saved to {artifacts}")
+        return exit_code
 
+    documents = [document for _, document, _ in runs]
+    if artifacts:
+        save_artifact(os.path.join(artifacts, 'report.json'), json.dumps(
+            build_multi_target_document(documents) if len(targets) > 1 else documents[0],
+            indent=4) + "\n")
+    if args.output:
         try:
-            with open(f"out/PDM_{timestamp}_{test_hash}.txt", 'w') as f:
-                f.write(cdm8_res)
+            if args.format == 'csv':
+                header = not args.append or args.output == '-' or \
+                    not os.path.exists(args.output) or os.path.getsize(args.output) == 0
+                content = "".join(build_result_csv(document, header and i == 0)
+                                  for i, document in enumerate(documents))
+            elif args.format == 'markdown':
+                content = "\n".join(build_result_markdown(document) for document in documents)
+            elif args.format == 'html':
+                content = build_result_html(documents)
+            elif args.format == 'cdm-text':
+                content = "\n".join(build_result_cdm_text(document) for document in documents)
+            else:
+                document = build_multi_target_document(documents) if len(targets) > 1 \
+                    else documents[0]
+                if log_buffer:
+                    document = {**document, 'log': log_buffer.lines()}
+                content = json.dumps(document, indent=4) + "\n"
+            if args.output == '-':
+                results_stream.write(content)
+                results_stream.flush()
+            elif args.append:
+                with open(args.output, 'a', newline='') as f:
+                    f.write(content)
+            else:
+                write_file_atomic(args.output, content)
         except Exception as e:
-            print(f"Error saving CDM8 formatted results: {e}")
-            return
+            print(f"Error saving results to '{args.output}': {e}", file=sys.stderr)
+            return EXIT_IO_ERROR
+
+    if args.chart:
+        for t, document in enumerate(documents):
+            path = args.chart
+            if len(documents) > 1:
+                path = f"{os.path.splitext(path)[0]}-{t + 1}{os.path.splitext(path)[1]}"
+            try:
+                path = write_chart(path, document, args.chart_log)
+            except (PdmError, OSError) as e:
+                print(f"Error saving the chart to '{path}': {e}", file=sys.stderr)
+                return EXIT_IO_ERROR
+            if not args.quiet:
+                print(f"Chart saved to {path}")
+
+    for kind, destination in args.export:
+        metrics = build_prometheus_metrics(documents)
+        if kind == 'prometheus':
+            try:
+                write_file_atomic(destination, metrics)
+            except OSError as e:
+                print(f"Error saving metrics to '{destination}': {e}", file=sys.stderr)
+                return EXIT_IO_ERROR
+        else:
+            try:
+                push_metrics(destination, args.push_job, metrics)
+            except (urllib.error.URLError, OSError, ValueError) as e:
+                # The benchmark itself went fine, only the push didn't
+                print(f"WARNING: Could not push the metrics to {destination}: {e}",
+                      file=sys.stderr)
+
+    if len(targets) > 1 and not (args.no_summary or args.quiet):
+        print()
+        print(spprint_target_comparison([(name, document['results'])
+                                         for _, document, name in runs]))
 
-        print(cdm8_res)
+    if artifacts:
+        print(f"\nArtifacts saved to {artifacts}")
 
+    if any(report.partial for report, _, _ in runs):
+        print("The run was interrupted, only the tests that finished were saved.",
+              file=sys.stderr)
+        return EXIT_INTERRUPTED
+    failed = sum(not check['passed'] for document in documents
+                 for check in document.get('checks', []))
+    if failed and not exit_code:
+        print(f"\n{failed} baseline check{'s' if failed > 1 else ''} failed.", file=sys.stderr)
+        return EXIT_BASELINE_FAILED
+    return exit_code
 
 if __name__ == '__main__':
-    main()
+    try:
+        sys.exit(main())
+    except KeyboardInterrupt:
+        # Ctrl+C outside of a run, nothing to clean up
+        print(file=sys.stderr)
+        sys.exit(EXIT_INTERRUPTED)
@@ -0,0 +1,603 @@
+"""Tests of pdm.py, run with python -m unittest test_pdm."""
+import argparse
+import contextlib
+import csv
+import errno
+import io
+import json
+import logging
+import os
+import re
+import subprocess
+import sys
+import tempfile
+import time
+import unittest
+from unittest import mock
+
+import pdm
+
+
+def make_result(name, direction, speed_mbs='100.00', iops=100.0):
+    """A test result the way parse_fio_results() reports it."""
+    return {'name': name, 'direction': direction, 'speed_mbs': speed_mbs, 'iops': iops,
+            'latency_us': '10.00'}
+
+
+def run_pdm(*argv, cwd=None):
+    """Run pdm.py as a command and return the completed process."""
+    return subprocess.run([sys.executable, os.path.abspath(pdm.__file__), *argv], cwd=cwd,
+                          capture_output=True, text=True)
+
+
+class ArgumentTest(unittest.TestCase):
+    def test_invalid_choices_are_rejected(self):
+        for option, value, choices in (('--backend', 'banana', list(pdm.BACKENDS)),
+                                       ('--log-level', 'verbose', pdm.LOG_LEVELS)):
+            with self.subTest(option=option):
+                result = run_pdm(tempfile.gettempdir(), option, value)
+                self.assertEqual(result.returncode, 2)
+                error = result.stderr.splitlines()[-1]
+                self.assertIn(f"invalid choice: '{value}'", error)
+                for choice in choices:
+                    self.assertIn(choice, error)
+
+    def test_usage_lists_the_choices(self):
+        result = run_pdm('--help')
+        self.assertEqual(result.returncode, 0)
+        self.assertIn(f"{{{','.join(pdm.BACKENDS)}}}", result.stdout)
+        self.assertIn(f"{{{','.join(pdm.LOG_LEVELS)}}}", result.stdout)
+
+
+class CsvOutputTest(unittest.TestCase):
+    def test_append_writes_the_header_once(self):
+        target, cwd = tempfile.mkdtemp(), tempfile.mkdtemp()
+        output = os.path.join(cwd, 'results.csv')
+        for _ in range(2):
+            result = run_pdm(target, '--backend', 'native', '--size', '16m', '--runs', '1',
+                             '--warmup', '0', '--cooldown', '0', '--no-history', '--quiet',
+                             '--format', 'csv', '--output', output, '--append', cwd=cwd)
+            self.assertEqual(result.returncode, 0, result.stderr)
+        with open(output, newline='') as f:
+            rows = list(csv.reader(f))
+        self.assertEqual(rows[0], pdm.CSV_COLUMNS)
+        self.assertEqual([row[4] for row in rows[1:]],
+                         ['SEQ-R-1M-Q1-T1', 'SEQ-W-1M-Q1-T1', 'RND-R-4K-Q1-T1',
+                          'RND-W-4K-Q1-T1'] * 2)
+
+
+class BaselineTest(unittest.TestCase):
+    def load(self, text, suffix='.toml'):
+        with tempfile.NamedTemporaryFile('w', suffix=suffix, delete=False) as f:
+            f.write(text)
+        self.addCleanup(os.remove, f.name)
+        return pdm.load_baseline(f.name)
+
+    def test_minimums_are_checked(self):
+        baseline = self.load('["SEQ1M Q8T1"]\nread_mbs = 500\nwrite_mbs = 2000\n'
+                             '["RND-R-4K-Q32-T1"]\nread_iops = 50\n'
+                             '["RND4K Q1T1"]\nread_mbs = 1\n')
+        results = [make_result('SEQ-R-1M-Q8-T1', 'read', '1000.00'),
+                   make_result('SEQ-W-1M-Q8-T1', 'write', '1000.00'),
+                   make_result('RND-R-4K-Q32-T1', 'read', iops=100.0)]
+        checks = pdm.check_baseline(baseline, results)
+        self.assertEqual([(check['test'], check['metric'], check['measured'], check['passed'])
+                          for check in checks],
+                         [('SEQ1M Q8T1', 'read_mbs', 1000.0, True),
+                          ('SEQ1M Q8T1', 'write_mbs', 1000.0, False),
+                          ('RND-R-4K-Q32-T1', 'read_iops', 100.0, True),
+                          # A test that didn't run fails its checks
+                          ('RND4K Q1T1', 'read_mbs', None, False)])
+
+    def test_json_baselines(self):
+        self.assertEqual(self.load('{"SEQ1M Q8T1": {"read_mbs": 3000}}', '.json'),
+                         {'SEQ1M Q8T1': {'read_mbs': 3000}})
+
+    def test_invalid_baselines(self):
+        for text in ('', '["SEQ1M Q8T1"]\nread_speed = 1\n', '["SEQ1M Q8T1"]\nread_mbs = "fast"\n',
+                     'SEQ = 1\n', '["SEQ1M Q8T1"\n'):
+            with self.subTest(text=text):
+                with self.assertRaises(pdm.PdmError) as caught:
+                    self.load(text)
+                self.assertEqual(caught.exception.exit_code, pdm.EXIT_PARSE_ERROR)
+
+
+class CpuBoundTest(unittest.TestCase):
+    def test_block_size_lists_are_not_small_blocks(self):
+        tests = [{'name': name, 'cpu_percent': {'max': 99.0}, 'block_size': bs}
+                 for name, bs in (('RND-R-4K-Q32-T1', '4K'), ('MIX-64K', '4K,64K'))]
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            pdm.warn_cpu_bound(tests)
+        self.assertIn(pdm.job_label('RND-R-4K-Q32-T1'), stderr.getvalue())
+        self.assertNotIn('MIX-64K', stderr.getvalue())
+
+
+class ResolveOptionsTest(unittest.TestCase):
+    def setUp(self):
+        self.cwd = os.getcwd()
+        self.dir = tempfile.mkdtemp()
+        # Keep the user's own config file and PYDISKMARK_* variables out of it
+        environ = {name: value for name, value in os.environ.items()
+                   if not name.startswith(pdm.ENV_PREFIX)}
+        environ['XDG_CONFIG_HOME'] = self.dir
+        patcher = mock.patch.dict(os.environ, environ, clear=True)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+        os.chdir(self.dir)
+        self.addCleanup(os.chdir, self.cwd)
+
+    def resolve(self, config='', env=None, argv=()):
+        with open(pdm.CONFIG_FILE_NAME, 'w') as f:
+            f.write(config)
+        os.environ.update(env or {})
+        return pdm.resolve_options(pdm.build_parser(), [self.dir, *argv])
+
+    def test_command_line_beats_environment_beats_file(self):
+        args, sources = self.resolve("cooldown = 1\nrepeat = 2\nsize = '2g'\n",
+                                     {'PYDISKMARK_COOLDOWN': '3', 'PYDISKMARK_REPEAT': '4'},
+                                     ['--cooldown', '5'])
+        self.assertEqual((args.cooldown, args.repeat, args.size), (5, 4, 2 * 1024**3))
+        self.assertEqual((sources['cooldown'], sources['repeat'], sources['size'],
+                          sources['runs']), ('cli', 'env', 'file', 'default'))
+
+    def test_file_errors_name_the_key(self):
+        for config, message in (("cooldwn = 1\n", "Unknown option 'cooldwn'"),
+                                ("repeat = 'many'\n", "'repeat' in"),
+                                ("repeat =\n", "Could not parse the config file")):
+            with self.subTest(config=config):
+                with self.assertRaises(pdm.PdmError) as caught:
+                    self.resolve(config)
+                self.assertIn(message, str(caught.exception))
+
+    def test_options_that_run_commands_stay_on_the_command_line(self):
+        options = {
+            'allow_destructive': 'true', 'yes': 'true', 'force': 'true',
+            'pre_hook': 'touch hooked', 'post_hook': 'touch hooked',
+            'config': 'evil.fio', 'set': '*.exec_prerun=touch hooked',
+            'fio_binary': '/bin/sh', 'fio_arg': '--exec_prerun=touch hooked',
+            'fio_env': 'LD_PRELOAD=/tmp/evil.so',
+        }
+        self.assertEqual(set(options), set(pdm.CLI_ONLY_OPTIONS))
+        for dest, value in options.items():
+            key = dest.replace('_', '-')
+            variable = pdm.ENV_PREFIX + dest.upper()
+            for config, env in ((f"{key} = {json.dumps(value)}\n", None), ('', {variable: value})):
+                with self.subTest(dest=dest, env=bool(env)):
+                    with self.assertRaises(pdm.PdmError) as caught:
+                        self.resolve(config, env)
+                    os.environ.pop(variable, None)
+                    self.assertEqual(caught.exception.exit_code, pdm.EXIT_BAD_ARGS)
+                    self.assertIn('can only be given on the command line', str(caught.exception))
+        args, sources = self.resolve(argv=['--fio-env', 'TMPDIR=/tmp', '--set', 'bs=8k'])
+        self.assertEqual((args.fio_env, args.set), ([('TMPDIR', '/tmp')], ['bs=8k']))
+
+
+class RunStateTest(unittest.TestCase):
+    def setUp(self):
+        self.config = pdm.load_fio_config(pdm.FIO_CONFIG)
+        self.path = os.path.join(tempfile.mkdtemp(), 'state.json')
+
+    def test_state_round_trip(self):
+        outputs = [{'jobs': [{'jobname': 'SEQ-R-1M-Q8-T1'}]}]
+        pdm.save_run_state(self.path, self.config, 2, 1, outputs)
+        state = pdm.load_run_state(self.path, self.config, 2)
+        self.assertEqual((state['completed'], state['outputs']), (1, outputs))
+        self.assertEqual(state['jobs'], pdm.get_fio_jobs(self.config))
+
+    def test_state_of_other_jobs_is_refused(self):
+        pdm.save_run_state(self.path, self.config, 1, 1, [{'jobs': []}])
+        for repeat, change in ((2, None), (1, ('global', 'size', '2g'))):
+            with self.subTest(repeat=repeat, change=change):
+                config = pdm.load_fio_config(pdm.FIO_CONFIG)
+                if change:
+                    config.set(*change)
+                with self.assertRaises(pdm.PdmError) as caught:
+                    pdm.load_run_state(self.path, config, repeat)
+                self.assertEqual(caught.exception.exit_code, pdm.EXIT_BAD_ARGS)
+                self.assertIn('different job configuration', str(caught.exception))
+
+    def test_other_files_are_refused(self):
+        with open(self.path, 'w') as f:
+            f.write('{"results": []}')
+        with self.assertRaises(pdm.PdmError) as caught:
+            pdm.load_run_state(self.path, self.config, 1)
+        self.assertEqual(caught.exception.exit_code, pdm.EXIT_PARSE_ERROR)
+
+
+class RegionTest(unittest.TestCase):
+    def job_config(self, text):
+        return pdm.parse_fio_job_file(text, 'test.fio')
+
+    def test_sequential_jobs_are_split_over_the_device(self):
+        config = self.job_config("[global]\nsize=1g\n[SEQ-R]\nrw=read\n"
+                                 "[RND-R]\nrw=randread\n[SEQ-W]\nrw=write\n")
+        pdm.make_region_config(config, 4, 100 * 1024**3)
+        self.assertEqual(pdm.get_fio_jobs(config),
+                         ['SEQ-R@1', 'SEQ-R@2', 'SEQ-R@3', 'SEQ-R@4', 'RND-R',
+                          'SEQ-W@1', 'SEQ-W@2', 'SEQ-W@3', 'SEQ-W@4'])
+        offsets = [int(config.get(f'SEQ-R@{region}', 'offset')) for region in range(1, 5)]
+        self.assertEqual(offsets[0], 0)
+        # The last region ends at the end of the device
+        self.assertEqual(offsets[-1] + 1024**3, 100 * 1024**3)
+        self.assertTrue(all(offset % pdm.REGION_ALIGNMENT == 0 for offset in offsets))
+        self.assertEqual(config.get('SEQ-W@2', 'size'), str(1024**3))
+
+    def test_small_devices_get_shorter_regions(self):
+        config = self.job_config("[global]\nsize=1g\n[SEQ-R]\nrw=read\n")
+        pdm.make_region_config(config, 8, 2 * 1024**3)
+        self.assertEqual(config.get('SEQ-R@1', 'size'), str(256 * 1024**2))
+        with self.assertRaises(pdm.PdmError):
+            pdm.make_region_config(self.job_config("[SEQ-R]\nrw=read\n"), 8, 4 * 1024**2)
+
+    def test_falloff_from_the_first_to_the_last_region(self):
+        config = self.job_config("[global]\nsize=1g\n[SEQ-R]\nrw=read\n")
+        pdm.make_region_config(config, 3, 10 * 1024**3)
+        tests = [make_result(f'SEQ-R@{region}', 'read', speed)
+                 for region, speed in ((2, '150.00'), (1, '200.00'), (3, '100.00'))]
+        series = pdm.build_region_series(tests, config)
+        self.assertEqual([point['region'] for point in series[0]['points']], [1, 2, 3])
+        self.assertEqual(series[0]['falloff_percent'], -50.0)
+
+
+class HtmlReportTest(unittest.TestCase):
+    def test_paths_and_tags_are_escaped(self):
+        script = '<script>alert(1)</script>'
+        report = pdm.BenchmarkReport(
+            backend='fio', version='fio-3.36', target=f'/mnt/{script}/',
+            tests=[make_result('SEQ-R-1M-Q8-T1', 'read'),
+                   make_result('SEQ-W-1M-Q8-T1', 'write')],
+            settings={'test_size': 1024**3})
+        document = pdm.build_result_document(report, tags=[script])
+        page = pdm.build_result_html([document])
+        self.assertNotIn(script, page)
+        self.assertIn('&lt;script&gt;alert(1)&lt;/script&gt;', page)
+
+
+class FioFileSizeTest(unittest.TestCase):
+    def sizes(self, text):
+        return pdm.get_fio_file_sizes(pdm.parse_fio_job_file(text, 'test.fio'))
+
+    def test_size_is_spread_over_the_files(self):
+        self.assertEqual(self.sizes("[global]\ndirectory=/data\nsize=1g\nnrfiles=2\n[job]\n"),
+                         {os.path.join('/data', 'job.0.0'): 512 * 1024**2,
+                          os.path.join('/data', 'job.0.1'): 512 * 1024**2})
+
+    def test_ranges_count_as_their_upper_end(self):
+        self.assertEqual(self.sizes("[job]\nfilename=/data/f\nfilesize=1M-10M\n"),
+                         {'/data/f': 10 * 1024**2})
+
+    def test_shares_of_the_target_are_left_out(self):
+        target = tempfile.mkdtemp()
+        for size in ('50%', '10%-50%'):
+            with self.subTest(size=size):
+                config = pdm.parse_fio_job_file(
+                    f"[global]\ndirectory={target}\nsize={size}\n[job]\n", 'test.fio')
+                self.assertEqual(pdm.get_fio_file_sizes(config),
+                                 {os.path.join(target, 'job.0.0'): None})
+                with self.assertLogs('pdm', 'INFO'):
+                    self.assertEqual(pdm.get_fio_required_space(config), 0)
+                self.assertEqual(pdm.prepare_fio_files(config), [])
+
+    def test_invalid_values_are_parse_errors(self):
+        for option in ('size=lots', 'nrfiles=abc', 'numjobs=0', 'filesize=1M-many'):
+            with self.subTest(option=option):
+                with self.assertRaises(pdm.PdmError) as caught:
+                    self.sizes(f"[job]\n{option}\n")
+                self.assertEqual(caught.exception.exit_code, pdm.EXIT_PARSE_ERROR)
+                self.assertIn(f"Invalid {option} in job job", str(caught.exception))
+
+
+def fio_output(*jobs, preamble=''):
+    """What fio --output-format=json prints for jobs that each read at 1000 MB/s."""
+    return preamble + json.dumps({'fio version': 'fio-3.36', 'global options': {}, 'jobs': [
+        {'jobname': job, 'groupid': 0, 'error': 0, 'job options': {'bs': '1m'},
+         'read': {'io_bytes': 1024**3, 'bw_bytes': 1000 * 1024**2, 'iops': 1000.0,
+                  'lat_ns': {'mean': 8000.0}}} for job in jobs]}) + "\n"
+
+
+class FakeFio(pdm.CommandRunner):
+    """Answers like fio would, with respond(job file) giving (exit code, stdout, stderr)."""
+
+    def __init__(self, respond, version='fio-3.36'):
+        self.respond = respond
+        self.version = version
+        self.job_files = []
+
+    def run(self, args, stdin=None, merge_stderr=False):
+        return pdm.CommandResult(0, self.version + "\n", '')
+
+    def start(self, args, stdin=None, env=None):
+        self.job_files.append(stdin)
+        code, stdout, stderr = self.respond(stdin)
+        script = (f"import sys; sys.stdout.write({stdout!r}); sys.stderr.write({stderr!r}); "
+                  f"sys.exit({code})")
+        return subprocess.Popen([sys.executable, '-c', script],
+                                stdout=subprocess.PIPE, stderr=subprocess.PIPE)
+
+
+def job_of(job_file):
+    """Name of the job section in a job file made by make_fio_job_config()."""
+    return [name for name in re.findall(r'^\[(.+)\]$', job_file, re.M) if name != 'global'][0]
+
+
+@unittest.skipIf(os.name == 'nt', "uses POSIX shell commands")
+class RunCommandTest(unittest.TestCase):
+    def test_timeout_kills_the_command(self):
+        started = time.monotonic()
+        result = pdm.run_command(pdm.CommandSpec(['sleep', '10'], timeout=0.5))
+        self.assertLess(time.monotonic() - started, 5)
+        self.assertTrue(result.timed_out)
+        self.assertNotEqual(result.returncode, 0)
+
+    def test_large_output_is_truncated(self):
+        with self.assertLogs('pdm', 'WARNING'):
+            result = pdm.run_command(pdm.CommandSpec('yes | head -c 20M', shell=True))
+        self.assertTrue(result.truncated)
+        self.assertEqual(len(result.stdout), pdm.COMMAND_OUTPUT_LIMIT)
+        self.assertEqual(result.returncode, 0)
+
+    def test_output_within_the_limit_is_kept(self):
+        result = pdm.run_command(pdm.CommandSpec('cat; echo oops >&2', shell=True,
+                                                 stdin='hello\n'))
+        self.assertEqual((result.stdout, result.stderr), ('hello\n', 'oops\n'))
+        self.assertFalse(result.truncated or result.timed_out)
+
+
+class LogFilterTest(unittest.TestCase):
+    def setUp(self):
+        self.root_handlers = logging.root.handlers[:]
+        self.root_level = logging.root.level
+
+    def tearDown(self):
+        logging.root.handlers = self.root_handlers
+        logging.root.setLevel(self.root_level)
+        for name in ('filtertest', 'filtertest.fio'):
+            logging.getLogger(name).setLevel(logging.NOTSET)
+
+    def test_most_specific_rule_wins(self):
+        # basicConfig() leaves the root logger alone once it has handlers
+        logging.root.handlers = []
+        rules = pdm.log_filter_arg('warning,filtertest=info,filtertest.fio=debug')
+        pdm.setup_logging('error', log_filter=rules)
+        enabled = {name: min(level for level in (logging.DEBUG, logging.INFO, logging.WARNING,
+                                                 logging.ERROR)
+                             if logging.getLogger(name).isEnabledFor(level))
+                   for name in ('other', 'filtertest', 'filtertest.disk',
+                                'filtertest.fio', 'filtertest.fio.jobs')}
+        self.assertEqual(enabled, {'other': logging.WARNING,
+                                   'filtertest': logging.INFO,
+                                   'filtertest.disk': logging.INFO,
+                                   'filtertest.fio': logging.DEBUG,
+                                   'filtertest.fio.jobs': logging.DEBUG})
+
+    def test_rules_are_parsed(self):
+        self.assertEqual(pdm.log_filter_arg('info, pdm = debug'),
+                         [(None, 'info'), ('pdm', 'debug')])
+
+    def test_invalid_filters_are_rejected(self):
+        for text in ('loud', 'pdm=loud', '=debug', 'pdm=debug,pdm=info', 'info,warning', ''):
+            with self.subTest(text=text):
+                with self.assertRaises(argparse.ArgumentTypeError):
+                    pdm.log_filter_arg(text)
+
+    def test_invalid_filter_fails_at_startup(self):
+        options = pdm.RunOptions(argv=['--log-filter', 'info,pdm=loud'])
+        with self.assertRaises(pdm.PdmError) as caught:
+            options.to_args(tempfile.gettempdir())
+        self.assertEqual(caught.exception.exit_code, pdm.EXIT_BAD_ARGS)
+        self.assertIn("invalid level 'loud'", str(caught.exception))
+
+
+class FioVersionTest(unittest.TestCase):
+    # What fio --version prints on various systems and builds
+    VERSIONS = {
+        'fio-3.36': (3, 36, 0),
+        'fio-3.28': (3, 28, 0),
+        'fio-3.1': (3, 1, 0),
+        'fio-3.0': (3, 0, 0),
+        'fio-2.2.10': (2, 2, 10),
+        'fio-2.1.3': (2, 1, 3),
+        'fio-2.0.9': (2, 0, 9),
+        # git snapshots count the commits since the tag, that isn't a patch level
+        'fio-3.28-42-g1234abcd': (3, 28, 0),
+        'fio-3.35-114-g07e3e2ef': (3, 35, 0),
+        'fio-3.33-dirty': (3, 33, 0),
+        'fio-3.29-7-g01686-dirty': (3, 29, 0),
+        # Windows builds end their line with CRLF
+        'fio-3.36\r\n': (3, 36, 0),
+        'fio-3.27\r\n': (3, 27, 0),
+    }
+
+    def test_versions_are_read(self):
+        for text, expected in self.VERSIONS.items():
+            with self.subTest(text=text):
+                self.assertEqual(pdm.fio_version_tuple(text), expected)
+
+    def test_unreadable_versions(self):
+        for text in (None, '', 'fio', 'fio-git'):
+            with self.subTest(text=text):
+                self.assertIsNone(pdm.fio_version_tuple(text))
+                self.assertIsNone(pdm.fio_too_old_reason(text))
+
+    def test_minimum_version(self):
+        self.assertIsNone(pdm.fio_too_old_reason('fio-3.0'))
+        self.assertIsNone(pdm.fio_too_old_reason('fio-3.28-42-g1234abcd'))
+        reason = pdm.fio_too_old_reason('fio-2.2.10')
+        self.assertIn('fio-2.2.10', reason)
+        self.assertIn('fio 3.0 or newer', reason)
+
+    def test_steady_state_minimum(self):
+        self.assertIsNone(pdm.steady_state_unsupported_reason('fio-3.36'))
+        self.assertIn('fio-2.1.3', pdm.steady_state_unsupported_reason('fio-2.1.3'))
+
+    def test_version_text(self):
+        self.assertEqual(pdm.fio_version_text((3, 0, 0)), '3.0')
+        self.assertEqual(pdm.fio_version_text((2, 2, 10)), '2.2.10')
+
+
+class FioRunnerTest(unittest.TestCase):
+    def setUp(self):
+        self.runner = pdm.command_runner
+        self.quiet = pdm.console['quiet']
+        pdm.console['quiet'] = True
+        self.target = tempfile.mkdtemp()
+        self.config = pdm.load_fio_config(pdm.FIO_CONFIG)
+
+    def tearDown(self):
+        pdm.command_runner = self.runner
+        pdm.console['quiet'] = self.quiet
+
+    def run_job(self, respond, job='SEQ-R-1M-Q8-T1'):
+        pdm.command_runner = FakeFio(respond)
+        return pdm.run_fio_job(self.target, self.config, job, None,
+                               {'process': None, 'cancelled': False})
+
+    def test_every_job_runs_in_order(self):
+        pdm.filter_fio_jobs(self.config, ['SEQ-*-1M-Q8-T1'])
+        runner = FakeFio(lambda job_file: (0, fio_output(job_of(job_file)), ''))
+        pdm.command_runner = runner
+        outputs = pdm.run_fio_test(self.target, self.config)
+        self.assertEqual([job_of(job_file) for job_file in runner.job_files],
+                         ['SEQ-R-1M-Q8-T1', 'SEQ-W-1M-Q8-T1'])
+        self.assertEqual([job['jobname'] for job in outputs[0]['jobs']],
+                         ['SEQ-R-1M-Q8-T1', 'SEQ-W-1M-Q8-T1'])
+
+    def test_text_before_the_json_is_skipped(self):
+        output = self.run_job(lambda job_file: (
+            0, fio_output('SEQ-R-1M-Q8-T1', preamble="fio: note: both iodepth and "
+                          "numjobs set\n"), ''))
+        results = pdm.parse_fio_results(output)
+        self.assertEqual(results[0]['name'], 'SEQ-R-1M-Q8-T1')
+        self.assertEqual(results[0]['speed_mbs'], '1000.00')
+
+    def test_failing_fio_reports_its_stderr(self):
+        with self.assertRaises(pdm.PdmError) as caught:
+            self.run_job(lambda job_file: (
+                1, '', "fio: pid=1234, err=5/file:io_u.c:1845, func=io_u error, "
+                "error=Input/output error\n"))
+        self.assertEqual(caught.exception.exit_code, pdm.EXIT_IO_ERROR)
+        self.assertIn('exited with code 1', str(caught.exception))
+        self.assertIn('error=Input/output error', str(caught.exception))
+
+    def test_jobs_run_in_file_order(self):
+        order = ['SEQ-R-1M-Q8-T1', 'RND-R-4K-Q32-T1', 'SEQ-W-1M-Q8-T1', 'RND-W-4K-Q32-T1']
+        self.assertEqual(pdm.get_fio_jobs(pdm.load_fio_config(pdm.PRESETS['quick'][0])), order)
+        runner = FakeFio(lambda job_file: (0, fio_output(job_of(job_file)), ''))
+        pdm.command_runner = runner
+        args = pdm.build_parser().parse_args(
+            [self.target, '--preset', 'quick', '--cooldown', '0', '--ignore-free-space'])
+        report = pdm.FioBackend().run(self.target, args, artifacts=tempfile.mkdtemp())
+        self.assertEqual(report.settings['job_order'], order)
+        self.assertEqual([job_of(job_file) for job_file in runner.job_files], order)
+        # The jobs run one at a time, a start delay would only add idle time
+        self.assertFalse(any('startdelay' in job_file for job_file in runner.job_files))
+
+    def test_sizes_fio_reports_as_text_are_kept(self):
+        def respond(job_file):
+            output = json.loads(fio_output(job_of(job_file)))
+            output['global options'] = {'size': '50%'}
+            return 0, json.dumps(output), ''
+
+        pdm.command_runner = FakeFio(respond)
+        args = pdm.RunOptions(preset='quick', argv=['--cooldown', '0', '--ignore-free-space']
+                              ).to_args(self.target)
+        report = pdm.FioBackend().run(self.target, args, artifacts=tempfile.mkdtemp())
+        self.assertEqual(report.settings['test_size'], '50%')
+        document = pdm.build_result_document(report)
+        self.assertIn('**Test size:** 50%', pdm.build_result_markdown(document))
+
+    def test_job_file_sized_by_the_target_runs(self):
+        job_file = os.path.join(self.target, 'share.fio')
+        with open(job_file, 'w') as f:
+            f.write("[global]\nsize=50%\nrw=read\n[SEQ-R-1M-Q8-T1]\nbs=1m\n")
+        pdm.command_runner = FakeFio(lambda job_file: (0, fio_output(job_of(job_file)), ''))
+        args = pdm.build_parser().parse_args(
+            [self.target, '--config', job_file, '--prepare-file', '--quiet'])
+        report = pdm.FioBackend().run(self.target, args, artifacts=tempfile.mkdtemp())
+        self.assertEqual([test['name'] for test in report.tests], ['SEQ-R-1M-Q8-T1'])
+        self.assertEqual(report.settings['prepared_files'], [])
+
+    def test_version_comes_from_the_runner(self):
+        pdm.command_runner = FakeFio(None, version='fio-3.28-42-g1234abcd')
+        self.assertEqual(pdm.get_fio_version(), 'fio-3.28-42-g1234abcd')
+        self.assertEqual(pdm.fio_version_tuple(pdm.get_fio_version()), (3, 28, 0))
+
+
+class FailingPasses:
+    """Stands in for run_native_threads, failing the given write passes with ENOSPC.
+
+    They count from 1 over all write tests.
+    """
+
+    def __init__(self, *failing):
+        self.failing = failing
+        self.writes = 0
+        self.run_native_threads = pdm.run_native_threads
+
+    def __call__(self, fd, bufs, size, sequential, write, *args):
+        if write:
+            self.writes += 1
+            if self.writes in self.failing:
+                raise OSError(errno.ENOSPC, os.strerror(errno.ENOSPC))
+        return self.run_native_threads(fd, bufs, size, sequential, write, *args)
+
+
+class NativeBackendTest(unittest.TestCase):
+    def setUp(self):
+        self.quiet = pdm.console['quiet']
+        pdm.console['quiet'] = True
+        self.target = tempfile.mkdtemp()
+
+    def tearDown(self):
+        pdm.console['quiet'] = self.quiet
+
+    def run_native(self, *argv, repeat=1):
+        args = pdm.RunOptions(backend='native', size='16m', repeat=repeat,
+                              argv=['--warmup', '0', '--cooldown', '0', *argv]
+                              ).to_args(self.target)
+        return pdm.NativeBackend().run(self.target, args)
+
+    def test_threads_split_every_test(self):
+        report = self.run_native('--runs', '1', '--threads', '2')
+        self.assertEqual([(test['name'], test['threads']) for test in report.tests],
+                         [('SEQ-R-1M-Q1-T2', 2), ('SEQ-W-1M-Q1-T2', 2),
+                          ('RND-R-4K-Q1-T2', 2), ('RND-W-4K-Q1-T2', 2)])
+        self.assertEqual(report.settings['threads'], 2)
+        self.assertEqual(os.listdir(self.target), [])
+
+    def test_every_measured_pass_is_kept(self):
+        report = self.run_native('--runs', '3', repeat=2)
+        for test in report.tests:
+            with self.subTest(test=test['name']):
+                passes = test['passes']
+                self.assertEqual(len(passes['speeds_mbs']), 6)
+                self.assertLessEqual(passes['min_mbs'], passes['max_mbs'])
+        self.assertEqual(report.tests[0]['passes']['bytes'], 6 * 16 * 1024**2)
+
+    def test_a_failing_pass_stops_the_run(self):
+        with mock.patch.object(pdm, 'run_native_threads', FailingPasses(2)):
+            with self.assertRaises(pdm.PdmError) as caught:
+                self.run_native('--runs', '3')
+        self.assertEqual(caught.exception.exit_code, pdm.EXIT_IO_ERROR)
+        self.assertIn('SEQ-W-1M-Q1-T1 run pass 2 of 3 failed', str(caught.exception))
+        self.assertIn(os.strerror(errno.ENOSPC), str(caught.exception))
+        self.assertEqual(os.listdir(self.target), [])
+
+    def test_failed_passes_are_skipped_with_continue_on_error(self):
+        with mock.patch.object(pdm, 'run_native_threads', FailingPasses(2, 4, 5, 6)):
+            with self.assertLogs('pdm', 'WARNING') as logs:
+                report = self.run_native('--runs', '3', '--continue-on-error', '--mode', 'write')
+        seq_w, rnd_w = report.tests
+        self.assertEqual((seq_w['failed_passes'], len(seq_w['passes']['speeds_mbs'])), (1, 2))
+        self.assertEqual(seq_w['passes']['bytes'], 2 * 16 * 1024**2)
+        # Every pass failed, like a fio job that failed
+        self.assertEqual(rnd_w['error'], pdm.EXIT_IO_ERROR)
+        output = "\n".join(logs.output)
+        self.assertIn('1 of 3 measured passes of SEQ-W-1M-Q1-T1 failed, its results come from '
+                      'the other 2', output)
+        self.assertIn('3 of 3 measured passes of RND-W-4K-Q1-T1 failed, it has no results', output)
+
+
+if __name__ == '__main__':
+    unittest.main()